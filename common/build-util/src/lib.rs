@@ -0,0 +1,23 @@
+//! Helpers shared by the build scripts of the workspace crates.
+
+use std::process::Command;
+
+/// Make the git commit hash of the source tree available as `TEAOS_GIT_HASH`.
+///
+/// `crate_dir` is the manifest directory of a crate two levels below the workspace root.
+pub fn emit_git_hash(crate_dir: &str) {
+    let git_dir = format!("{crate_dir}/../../.git");
+    println!("cargo::rerun-if-changed={git_dir}/HEAD");
+    println!("cargo::rerun-if-changed={git_dir}/refs");
+
+    let hash = Command::new("git")
+        .args(["rev-parse", "--short=12", "HEAD"])
+        .current_dir(crate_dir)
+        .output()
+        .ok()
+        .filter(|out| out.status.success())
+        .and_then(|out| String::from_utf8(out.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .unwrap_or_else(|| "unknown".into());
+    println!("cargo::rustc-env=TEAOS_GIT_HASH={hash}");
+}
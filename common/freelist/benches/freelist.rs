@@ -0,0 +1,76 @@
+//! Host benchmarks for carving blocks from and inserting blocks into a [`FreeList`].
+//!
+//! The list is first fragmented by freeing only every `stride`-th block of an arena, so carving
+//! has to walk past blocks that are too small. Each iteration carves a block and inserts it back.
+
+use std::hint::black_box;
+use std::ptr::NonNull;
+use std::time::Instant;
+
+use freelist::{ALIGN, FreeList};
+
+/// Number of small blocks the arena is divided into.
+const BLOCKS: usize = 4096;
+/// Size of a small block.
+const BLOCK_SIZE: usize = 4 * ALIGN;
+/// Size of the large block at the end of the arena, which is the only one satisfying carves.
+const LARGE_SIZE: usize = 64 * ALIGN;
+
+const ITERATIONS: usize = 10_000;
+
+type CarveFn = fn(&mut FreeList, usize) -> Option<NonNull<u8>>;
+
+fn main() {
+    println!(
+        "{:>10} {:>8} {:>8} {:>12}",
+        "strategy", "stride", "skipped", "ns/iter"
+    );
+    let strategies: [(&str, CarveFn); 2] = [
+        ("first-fit", FreeList::carve),
+        ("best-fit", FreeList::carve_best_fit),
+    ];
+    for (name, carve) in strategies {
+        for stride in [1, 2, 4, 16] {
+            let (skipped, ns) = bench_carve_insert(stride, carve);
+            println!("{name:>10} {stride:>8} {skipped:>8} {ns:>12.1}");
+        }
+    }
+}
+
+/// Run the carve/insert benchmark on a list with every `stride`-th small block free, using the
+/// given carve strategy.
+///
+/// Returns the number of free blocks each carve has to skip, and the time per iteration in
+/// nanoseconds.
+fn bench_carve_insert(stride: usize, carve: CarveFn) -> (usize, f64) {
+    let arena_size = BLOCKS * BLOCK_SIZE + LARGE_SIZE;
+    let mut arena = vec![0u128; arena_size / size_of::<u128>()];
+    let base = NonNull::new(arena.as_mut_ptr().cast::<u8>()).unwrap();
+
+    let mut list = FreeList::new();
+    let mut skipped = 0;
+    for i in (0..BLOCKS).step_by(stride) {
+        // Free blocks separated by used ones can't be coalesced. With a stride of one everything
+        // coalesces into a single block.
+        let ptr = unsafe { base.byte_add(i * BLOCK_SIZE) };
+        unsafe { list.insert(ptr, BLOCK_SIZE) };
+        skipped += 1;
+    }
+    let large = unsafe { base.byte_add(BLOCKS * BLOCK_SIZE) };
+    unsafe { list.insert(large, LARGE_SIZE) };
+
+    if stride == 1 {
+        skipped = 0;
+    }
+
+    let size = 2 * BLOCK_SIZE;
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        let ptr = carve(&mut list, black_box(size)).unwrap();
+        unsafe { list.insert(black_box(ptr), size) };
+    }
+    let elapsed = start.elapsed();
+
+    let ns = elapsed.as_nanos() as f64 / ITERATIONS as f64;
+    (skipped, ns)
+}
@@ -39,25 +39,85 @@ impl FreeList {
         while let Some(mut block_ptr) = *head {
             let block = unsafe { block_ptr.as_mut() };
 
-            if block.size == size {
-                *head = block.next;
-                return Some(block_ptr.cast());
+            if block.size >= size {
+                return Some(unsafe { Self::take(head, size) });
             }
 
-            if block.size > size {
-                let rest = block.size - size;
-                debug_assert!(rest >= mem::size_of::<FreeBlock>());
+            head = &mut block.next;
+        }
+
+        None
+    }
+
+    /// Carve a block out of the freelist, choosing the smallest block that fits.
+    ///
+    /// Unlike [`FreeList::carve`], this searches through the whole freelist (unless it finds an
+    /// exact match), which is slower but leaves large blocks intact for large requests.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not a multiple of [`ALIGN`].
+    pub fn carve_best_fit(&mut self, size: usize) -> Option<NonNull<u8>> {
+        assert!(size.is_multiple_of(ALIGN), "invalid size: {size}");
+
+        // The link pointing to the best block found so far, and that block's size.
+        let mut best: Option<(NonNull<Option<NonNull<FreeBlock>>>, usize)> = None;
+
+        let mut head = NonNull::from_mut(&mut self.head);
+        while let Some(mut block_ptr) = unsafe { *head.as_ref() } {
+            let block = unsafe { block_ptr.as_mut() };
+
+            if block.size >= size && best.is_none_or(|(_, best_size)| block.size < best_size) {
+                best = Some((head, block.size));
+                if block.size == size {
+                    break;
+                }
+            }
+
+            head = NonNull::from_mut(&mut block.next);
+        }
+
+        let (mut link, _) = best?;
+        Some(unsafe { Self::take(link.as_mut(), size) })
+    }
+
+    /// Carve a block with the given alignment out of the freelist.
+    ///
+    /// This searches through the freelist until it finds a block that contains an aligned region
+    /// of the requested size (first fit). Any leading padding and trailing remainder of that block
+    /// stay in the freelist.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `size` is not a multiple of [`ALIGN`].
+    /// Panics if `align` is not a power of two, or not a multiple of [`ALIGN`].
+    pub fn carve_aligned(&mut self, size: usize, align: usize) -> Option<NonNull<u8>> {
+        assert!(size.is_multiple_of(ALIGN), "invalid size: {size}");
+        assert!(
+            align.is_power_of_two() && align.is_multiple_of(ALIGN),
+            "invalid alignment: {align}",
+        );
+
+        let mut head = &mut self.head;
+
+        while let Some(mut block_ptr) = *head {
+            let block = unsafe { block_ptr.as_mut() };
+            let block_size = block.size;
+
+            let padding = block_ptr.cast::<u8>().align_offset(align);
+            if padding.checked_add(size).is_some_and(|s| s <= block_size) {
+                // Remove the whole block, then return the padding and remainder. Both are
+                // multiples of `ALIGN`, so they can be freelist blocks on their own.
+                let start = unsafe { Self::take(head, block_size) };
+                let aligned = unsafe { start.byte_add(padding) };
+                let end = unsafe { aligned.byte_add(size) };
 
                 unsafe {
-                    let new_block_ptr = block_ptr.byte_add(size);
-                    new_block_ptr.write(FreeBlock {
-                        size: rest,
-                        next: block.next,
-                    });
-                    *head = Some(new_block_ptr);
+                    self.insert(start, padding);
+                    self.insert(end, block_size - padding - size);
                 }
 
-                return Some(block_ptr.cast());
+                return Some(aligned);
             }
 
             head = &mut block.next;
@@ -66,6 +126,103 @@ impl FreeList {
         None
     }
 
+    /// Take `size` bytes from the start of the block `link` points to.
+    ///
+    /// # Safety
+    ///
+    /// `link` must point to a block in this freelist that is at least `size` bytes large.
+    unsafe fn take(link: &mut Option<NonNull<FreeBlock>>, size: usize) -> NonNull<u8> {
+        let mut block_ptr = link.expect("valid block");
+        let block = unsafe { block_ptr.as_mut() };
+        debug_assert!(block.size >= size);
+
+        if block.size == size {
+            *link = block.next;
+        } else {
+            let rest = block.size - size;
+            debug_assert!(rest >= mem::size_of::<FreeBlock>());
+
+            unsafe {
+                let new_block_ptr = block_ptr.byte_add(size);
+                new_block_ptr.write(FreeBlock {
+                    size: rest,
+                    next: block.next,
+                });
+                *link = Some(new_block_ptr);
+            }
+        }
+
+        block_ptr.cast()
+    }
+
+    /// Remove free memory from the end of the region managed by this freelist.
+    ///
+    /// If the last free block ends at `end`, the part of it starting at the first address aligned
+    /// to `align` is removed from the freelist, and that address is returned. The part of the
+    /// block below that address, including its header, stays in the freelist. Returns `None` if
+    /// nothing could be removed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `align` is not a power of two, or not a multiple of [`ALIGN`].
+    pub fn trim_end(&mut self, end: NonNull<u8>, align: usize) -> Option<NonNull<u8>> {
+        assert!(
+            align.is_power_of_two() && align.is_multiple_of(ALIGN),
+            "invalid alignment: {align}",
+        );
+
+        let mut head = &mut self.head;
+        loop {
+            let mut block_ptr = (*head)?;
+            let block = unsafe { block_ptr.as_mut() };
+
+            if block.next.is_some() {
+                head = &mut block.next;
+                continue;
+            }
+
+            let start = block_ptr.cast::<u8>();
+            if unsafe { start.byte_add(block.size) } != end {
+                return None;
+            }
+
+            let keep = start.align_offset(align);
+            if keep >= block.size {
+                return None;
+            }
+
+            if keep == 0 {
+                *head = None;
+            } else {
+                block.size = keep;
+            }
+
+            return Some(unsafe { start.byte_add(keep) });
+        }
+    }
+
+    /// Iterate over the free blocks, yielding their addresses and sizes in address order.
+    pub fn iter(&self) -> impl Iterator<Item = (NonNull<u8>, usize)> + '_ {
+        let mut next = self.head;
+        core::iter::from_fn(move || {
+            let block_ptr = next?;
+            let block = unsafe { block_ptr.as_ref() };
+            next = block.next;
+            Some((block_ptr.cast(), block.size))
+        })
+    }
+
+    /// Collect statistics about the free blocks.
+    pub fn stats(&self) -> FreeListStats {
+        let mut stats = FreeListStats::default();
+        for (_, size) in self.iter() {
+            stats.total_free += size;
+            stats.block_count += 1;
+            stats.largest_block = stats.largest_block.max(size);
+        }
+        stats
+    }
+
     /// Insert a free block into the freelist.
     ///
     /// # Safety
@@ -115,6 +272,17 @@ impl FreeList {
     }
 }
 
+/// Statistics about the blocks in a [`FreeList`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FreeListStats {
+    /// Total size of all free blocks, in bytes.
+    pub total_free: usize,
+    /// Number of free blocks.
+    pub block_count: usize,
+    /// Size of the largest free block, in bytes.
+    pub largest_block: usize,
+}
+
 /// Header for a block in a [`FreeList`].
 struct FreeBlock {
     size: usize,
@@ -144,3 +312,159 @@ pub fn round_up_align(x: usize) -> usize {
     let a = ALIGN - 1;
     (x + a) & !a
 }
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec;
+    use std::vec::Vec;
+
+    use super::*;
+
+    /// Create a freelist with blocks of the given sizes, separated by used gaps so they don't
+    /// coalesce.
+    ///
+    /// Returns the list, the start addresses of the blocks, and the backing memory.
+    fn build_list(sizes: &[usize]) -> (FreeList, Vec<NonNull<u8>>, Vec<u128>) {
+        let gap = ALIGN;
+        let total: usize = sizes.iter().map(|size| size + gap).sum();
+        let mut arena = vec![0u128; total / mem::size_of::<u128>()];
+        let base = NonNull::new(arena.as_mut_ptr().cast::<u8>()).unwrap();
+
+        let mut list = FreeList::new();
+        let mut blocks = Vec::new();
+        let mut offset = 0;
+        for &size in sizes {
+            let ptr = unsafe { base.byte_add(offset) };
+            unsafe { list.insert(ptr, size) };
+            blocks.push(ptr);
+            offset += size + gap;
+        }
+
+        (list, blocks, arena)
+    }
+
+    #[test]
+    fn test_iter_stats() {
+        let (list, blocks, _arena) = build_list(&[64, 32, 48]);
+
+        let iterated: Vec<_> = list.iter().collect();
+        assert_eq!(
+            iterated,
+            [(blocks[0], 64), (blocks[1], 32), (blocks[2], 48)]
+        );
+
+        let stats = list.stats();
+        assert_eq!(
+            stats,
+            FreeListStats {
+                total_free: 144,
+                block_count: 3,
+                largest_block: 64,
+            }
+        );
+
+        assert_eq!(FreeList::new().stats(), FreeListStats::default());
+    }
+
+    #[test]
+    fn test_carve_first_fit() {
+        let (mut list, blocks, _arena) = build_list(&[64, 32, 48]);
+
+        assert_eq!(list.carve(32), Some(blocks[0]));
+        assert_eq!(list.carve(32), Some(unsafe { blocks[0].byte_add(32) }));
+        assert_eq!(list.carve(48), Some(blocks[2]));
+        assert_eq!(list.carve(48), None);
+    }
+
+    #[test]
+    fn test_carve_best_fit() {
+        let (mut list, blocks, _arena) = build_list(&[96, 48, 64, 32]);
+
+        // Picks the tightest block, not the first that fits.
+        assert_eq!(list.carve_best_fit(48), Some(blocks[1]));
+        assert_eq!(list.carve_best_fit(32), Some(blocks[3]));
+
+        // Splits the smallest block that is larger than requested.
+        assert_eq!(list.carve_best_fit(16), Some(blocks[2]));
+        assert_eq!(
+            list.carve_best_fit(48),
+            Some(unsafe { blocks[2].byte_add(16) })
+        );
+
+        assert_eq!(list.carve_best_fit(128), None);
+        assert_eq!(list.carve_best_fit(96), Some(blocks[0]));
+        assert_eq!(list.carve_best_fit(16), None);
+    }
+
+    #[test]
+    fn test_carve_aligned() {
+        let (mut list, blocks, _arena) = build_list(&[512]);
+        let base = blocks[0];
+        let padding = base.align_offset(256);
+
+        let ptr = list.carve_aligned(64, 256).unwrap();
+        assert_eq!(ptr.align_offset(256), 0);
+        assert_eq!(ptr, unsafe { base.byte_add(padding) });
+
+        // The padding and the remainder stay available.
+        if padding > 0 {
+            assert_eq!(list.carve(padding), Some(base));
+        }
+        let rest = 512 - padding - 64;
+        assert_eq!(list.carve(rest), Some(unsafe { ptr.byte_add(64) }));
+        assert_eq!(list.carve(16), None);
+
+        // Freeing the aligned block restores the original, coalesced block.
+        unsafe {
+            list.insert(ptr, 64);
+            list.insert(ptr.byte_add(64), rest);
+            if padding > 0 {
+                list.insert(base, padding);
+            }
+        }
+        assert_eq!(list.carve(512), Some(base));
+    }
+
+    #[test]
+    fn test_trim_end() {
+        let (mut list, blocks, _arena) = build_list(&[32, 1024]);
+        let last = blocks[1];
+        let end = unsafe { last.byte_add(1024) };
+
+        // Only trims a block ending exactly at `end`.
+        assert_eq!(list.trim_end(unsafe { end.byte_sub(16) }, 256), None);
+
+        let cut = list.trim_end(end, 256).unwrap();
+        assert_eq!(cut.align_offset(256), 0);
+        assert_eq!(cut, unsafe { last.byte_add(last.align_offset(256)) });
+
+        // The part below the cut stays free, the rest is gone.
+        let kept = last.align_offset(256);
+        let blocks_left: Vec<_> = list.iter().collect();
+        if kept > 0 {
+            assert_eq!(blocks_left, [(blocks[0], 32), (last, kept)]);
+        } else {
+            assert_eq!(blocks_left, [(blocks[0], 32)]);
+        }
+
+        // Nothing left to trim at the old end.
+        assert_eq!(list.trim_end(end, 256), None);
+    }
+
+    #[test]
+    fn test_trim_end_whole_block() {
+        let (mut list, blocks, _arena) = build_list(&[32, 64]);
+        let end = unsafe { blocks[1].byte_add(64) };
+
+        assert_eq!(list.trim_end(end, ALIGN), Some(blocks[1]));
+        assert_eq!(list.iter().collect::<Vec<_>>(), [(blocks[0], 32)]);
+    }
+
+    #[test]
+    fn test_carve_aligned_too_small() {
+        let (mut list, _blocks, _arena) = build_list(&[64]);
+        assert_eq!(list.carve_aligned(128, 64), None);
+    }
+}
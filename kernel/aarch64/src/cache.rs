@@ -0,0 +1,51 @@
+//! Data cache maintenance, for sharing memory with non-coherent devices.
+
+use crate::instruction::{dc_civac, dc_cvac, dc_ivac, dsb_sy};
+use crate::memory::VA;
+use crate::register::CTR_EL0;
+
+/// Return the size of the smallest data cache line, in bytes.
+pub fn dcache_line_size() -> usize {
+    // `DminLine` is the log2 of the number of 4-byte words in a line.
+    4 << CTR_EL0::read().DminLine()
+}
+
+/// Clean the data cache lines covering `[start, start + len)`, so that devices observe the
+/// current memory contents.
+pub fn clean_dcache_range(start: VA, len: usize) {
+    for line in lines(start, len) {
+        dc_cvac(line);
+    }
+    dsb_sy();
+}
+
+/// Invalidate the data cache lines covering `[start, start + len)`, so that the CPU observes
+/// memory contents written by devices.
+///
+/// Cache lines only partially covered by the range are cleaned before invalidating, to preserve
+/// data outside the range that shares a line with it.
+pub fn invalidate_dcache_range(start: VA, len: usize) {
+    let line_size = dcache_line_size() as u64;
+    let end = start.into_u64() + len as u64;
+
+    for line in lines(start, len) {
+        let addr = line.into_u64();
+        let partial = addr < start.into_u64() || addr + line_size > end;
+        if partial {
+            dc_civac(line);
+        } else {
+            // SAFETY: The line lies fully within the range to invalidate.
+            unsafe { dc_ivac(line) };
+        }
+    }
+    dsb_sy();
+}
+
+/// Iterate over the start addresses of the data cache lines covering `[start, start + len)`.
+fn lines(start: VA, len: usize) -> impl Iterator<Item = VA> {
+    let line_size = dcache_line_size() as u64;
+    let first = start.into_u64() & !(line_size - 1);
+    let end = start.into_u64() + len as u64;
+
+    (first..end).step_by(line_size as usize).map(VA::new)
+}
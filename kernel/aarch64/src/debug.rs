@@ -0,0 +1,103 @@
+//! Support for post-mortem debugging.
+
+use core::arch::asm;
+use core::fmt;
+use core::ops::Range;
+
+use crate::memory::{VA, va_to_pa};
+
+/// Maximum number of frames [`backtrace`] follows.
+const MAX_FRAMES: usize = 64;
+
+/// A snapshot of the general-purpose registers.
+#[derive(Clone, Debug)]
+pub struct Registers {
+    pub x: [u64; 31],
+    pub sp: u64,
+}
+
+impl Registers {
+    /// Capture the current register values.
+    ///
+    /// The register holding the snapshot address is captured with that address as its value.
+    #[inline(always)]
+    pub fn capture() -> Self {
+        let mut regs = Self { x: [0; 31], sp: 0 };
+        unsafe {
+            asm!(
+                "stp x0, x1, [{p}, #0]",
+                "stp x2, x3, [{p}, #16]",
+                "stp x4, x5, [{p}, #32]",
+                "stp x6, x7, [{p}, #48]",
+                "stp x8, x9, [{p}, #64]",
+                "stp x10, x11, [{p}, #80]",
+                "stp x12, x13, [{p}, #96]",
+                "stp x14, x15, [{p}, #112]",
+                "stp x16, x17, [{p}, #128]",
+                "stp x18, x19, [{p}, #144]",
+                "stp x20, x21, [{p}, #160]",
+                "stp x22, x23, [{p}, #176]",
+                "stp x24, x25, [{p}, #192]",
+                "stp x26, x27, [{p}, #208]",
+                "stp x28, x29, [{p}, #224]",
+                "str x30, [{p}, #240]",
+                "mov {tmp}, sp",
+                "str {tmp}, [{p}, #248]",
+                p = in(reg) &raw mut regs,
+                tmp = out(reg) _,
+                options(preserves_flags, nostack),
+            );
+        }
+        regs
+    }
+}
+
+impl fmt::Display for Registers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, x) in self.x.iter().enumerate() {
+            let sep = if i % 4 == 3 { "\n" } else { "  " };
+            write!(f, "x{i:<2} = {x:#018x}{sep}")?;
+        }
+        write!(f, "sp  = {:#018x}", self.sp)
+    }
+}
+
+/// Walk the frame pointer chain of the caller, calling `f` with each saved return address.
+///
+/// Only frame records inside `stack` are followed. Since the stack grows downwards, each frame
+/// record must lie above the previous one, which rules out cycles. Frame records that aren't
+/// mapped also end the walk, so this is safe to call on a corrupted stack.
+///
+/// Requires the code to be compiled with frame pointers.
+#[inline(always)]
+pub fn backtrace(stack: Range<u64>, mut f: impl FnMut(u64)) {
+    let mut fp: u64;
+    unsafe {
+        asm!("mov {}, x29", out(reg) fp, options(nomem, preserves_flags, nostack));
+    }
+
+    let mut prev = None;
+    for _ in 0..MAX_FRAMES {
+        let in_stack = fp >= stack.start && fp.saturating_add(16) <= stack.end;
+        let ascending = prev.is_none_or(|prev| fp > prev);
+        if !in_stack || !ascending || !fp.is_multiple_of(8) {
+            break;
+        }
+
+        // The 16-byte frame record may straddle a page boundary.
+        let va = VA::new(fp);
+        if va_to_pa(va).is_none() || va_to_pa(va + 8_u64).is_none() {
+            break;
+        }
+
+        // A frame record holds the caller's frame pointer, followed by the return address.
+        let [next_fp, lr] = unsafe { va.as_ptr::<[u64; 2]>().read_volatile() };
+        if lr == 0 {
+            break;
+        }
+
+        f(lr);
+        prev = Some(fp);
+        fp = next_fp;
+    }
+}
@@ -2,6 +2,17 @@ use core::arch::asm;
 
 use crate::memory::VA;
 
+#[inline(always)]
+pub fn at_s1e0r(va: VA) {
+    unsafe {
+        asm!(
+            "at s1e0r, {x}",
+            x = in(reg) va.into_u64(),
+            options(preserves_flags, nostack),
+        );
+    }
+}
+
 #[inline(always)]
 pub fn at_s1e1r(va: VA) {
     unsafe {
@@ -13,6 +24,93 @@ pub fn at_s1e1r(va: VA) {
     }
 }
 
+#[inline(always)]
+pub fn at_s1e1w(va: VA) {
+    unsafe {
+        asm!(
+            "at s1e1w, {x}",
+            x = in(reg) va.into_u64(),
+            options(preserves_flags, nostack),
+        );
+    }
+}
+
+/// Clean and invalidate the data cache line containing `va`, to the point of coherency.
+#[inline(always)]
+pub fn dc_civac(va: VA) {
+    unsafe {
+        asm!(
+            "dc civac, {x}",
+            x = in(reg) va.into_u64(),
+            options(preserves_flags, nostack),
+        );
+    }
+}
+
+/// Clean the data cache line containing `va`, to the point of coherency.
+#[inline(always)]
+pub fn dc_cvac(va: VA) {
+    unsafe {
+        asm!(
+            "dc cvac, {x}",
+            x = in(reg) va.into_u64(),
+            options(preserves_flags, nostack),
+        );
+    }
+}
+
+/// Invalidate the data cache line containing `va`, to the point of coherency.
+///
+/// # Safety
+///
+/// Discards any dirty data in the cache line, including data outside the range the caller is
+/// interested in.
+#[inline(always)]
+pub unsafe fn dc_ivac(va: VA) {
+    unsafe {
+        asm!(
+            "dc ivac, {x}",
+            x = in(reg) va.into_u64(),
+            options(preserves_flags, nostack),
+        );
+    }
+}
+
+#[inline(always)]
+pub fn dmb_ish() {
+    unsafe {
+        asm!("dmb ish", options(preserves_flags, nostack));
+    }
+}
+
+#[inline(always)]
+pub fn dmb_ishst() {
+    unsafe {
+        asm!("dmb ishst", options(preserves_flags, nostack));
+    }
+}
+
+#[inline(always)]
+pub fn dmb_oshld() {
+    unsafe {
+        asm!("dmb oshld", options(preserves_flags, nostack));
+    }
+}
+
+#[inline(always)]
+pub fn dmb_oshst() {
+    unsafe {
+        asm!("dmb oshst", options(preserves_flags, nostack));
+    }
+}
+
+#[inline(always)]
+pub fn dsb_sy() {
+    unsafe {
+        asm!("dsb sy", options(preserves_flags, nostack));
+    }
+}
+
 #[inline(always)]
 pub fn dsb_ish() {
     unsafe {
@@ -34,6 +132,14 @@ pub fn isb() {
     }
 }
 
+/// Send an event to all cores, waking them from `wfe`.
+#[inline(always)]
+pub fn sev() {
+    unsafe {
+        asm!("sev", options(nomem, preserves_flags, nostack));
+    }
+}
+
 #[inline(always)]
 pub fn tlbi_vae1is(va: VA) {
     unsafe {
@@ -58,3 +164,11 @@ pub fn wfe() {
         asm!("wfe", options(nomem, preserves_flags, nostack));
     }
 }
+
+/// Hint that the core is spinning, e.g. in a lock backoff loop.
+#[inline(always)]
+pub fn yield_() {
+    unsafe {
+        asm!("yield", options(nomem, preserves_flags, nostack));
+    }
+}
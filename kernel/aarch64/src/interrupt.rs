@@ -0,0 +1,45 @@
+//! Interrupt masking and unmasking.
+
+use core::arch::asm;
+
+use crate::register::DAIF;
+
+/// Mask IRQs and FIQs on the current CPU.
+///
+/// Returns the previous interrupt mask state, to be passed to [`restore`].
+#[inline(always)]
+pub fn mask() -> DAIF {
+    let daif = DAIF::read();
+    // No `nomem`, so the compiler doesn't move memory accesses out of the masked section.
+    unsafe { asm!("msr daifset, #0b0011", options(preserves_flags, nostack)) };
+    daif
+}
+
+/// Restore the interrupt mask state.
+///
+/// # Safety
+///
+/// Restoring a state that has interrupts unmasked allows interrupt handlers to run. The caller
+/// must be prepared for that, i.e. it must not be inside a section that requires interrupts to be
+/// masked.
+#[inline(always)]
+pub unsafe fn restore(daif: DAIF) {
+    unsafe {
+        asm!(
+            "msr daif, {x}",
+            x = in(reg) u64::from(daif),
+            options(preserves_flags, nostack),
+        );
+    }
+}
+
+/// Unmask IRQs on the current CPU.
+///
+/// # Safety
+///
+/// Unmasking IRQs allows interrupt handlers to run. The caller must be prepared for that, i.e. it
+/// must not be inside a section that requires interrupts to be masked.
+#[inline(always)]
+pub unsafe fn unmask_irq() {
+    unsafe { asm!("msr daifclr, #0b0010", options(preserves_flags, nostack)) };
+}
@@ -1,16 +1,37 @@
+//! Support for the AArch64 architecture.
+//!
+//! Everything that executes AArch64 instructions is only available when targeting AArch64. The
+//! address types in [`memory`] are available everywhere, so crates that only share those can be
+//! tested on the host.
+
 #![no_std]
 
+#[cfg(target_arch = "aarch64")]
+pub mod cache;
+#[cfg(target_arch = "aarch64")]
+pub mod debug;
+#[cfg(target_arch = "aarch64")]
 pub mod instruction;
+#[cfg(target_arch = "aarch64")]
+pub mod interrupt;
 pub mod memory;
+#[cfg(target_arch = "aarch64")]
+pub mod psci;
+#[cfg(target_arch = "aarch64")]
 pub mod register;
 
+#[cfg(target_arch = "aarch64")]
 use core::hint;
+#[cfg(target_arch = "aarch64")]
 use core::time::Duration;
 
+#[cfg(target_arch = "aarch64")]
 use instruction::wfe;
+#[cfg(target_arch = "aarch64")]
 use register::{CNTFRQ_EL0, CNTVCT_EL0};
 
 /// Halt the CPU indefinitely.
+#[cfg(target_arch = "aarch64")]
 pub fn halt() -> ! {
     loop {
         wfe();
@@ -18,15 +39,70 @@ pub fn halt() -> ! {
 }
 
 /// Return the CPU uptime.
+#[cfg(target_arch = "aarch64")]
 pub fn uptime() -> Duration {
-    let count = CNTVCT_EL0::read().VirtualCount();
+    ticks_to_duration(uptime_ticks())
+}
+
+/// Return the raw value of the virtual counter.
+#[cfg(target_arch = "aarch64")]
+pub fn uptime_ticks() -> u64 {
+    CNTVCT_EL0::read().VirtualCount()
+}
+
+/// Convert a number of virtual counter ticks into a `Duration`, at full counter resolution.
+#[cfg(target_arch = "aarch64")]
+fn ticks_to_duration(count: u64) -> Duration {
     let freq = CNTFRQ_EL0::read().ClockFreq();
-    Duration::from_millis(count * 1_000 / freq)
+    let nanos = u128::from(count) * 1_000_000_000 / u128::from(freq);
+    Duration::from_nanos(nanos as u64)
+}
+
+/// A stopwatch measuring elapsed time based on the virtual counter.
+#[cfg(target_arch = "aarch64")]
+#[derive(Clone, Copy, Debug)]
+pub struct Stopwatch {
+    start: u64,
+}
+
+#[cfg(target_arch = "aarch64")]
+impl Stopwatch {
+    pub fn start() -> Self {
+        Self {
+            start: uptime_ticks(),
+        }
+    }
+
+    /// Return the time elapsed since the stopwatch was started.
+    pub fn elapsed(&self) -> Duration {
+        ticks_to_duration(uptime_ticks() - self.start)
+    }
 }
 
+/// Busy-wait for at least the given period.
+///
+/// The deadline is computed in counter ticks, so there is no rounding drift between the start and
+/// end of the wait, and counter wrap-around is handled.
+#[cfg(target_arch = "aarch64")]
 pub fn delay(period: Duration) {
-    let end = uptime() + period;
-    while uptime() < end {
+    let freq = CNTFRQ_EL0::read().ClockFreq();
+    let ticks = (period.as_nanos() * u128::from(freq)).div_ceil(1_000_000_000);
+    let ticks = u64::try_from(ticks).unwrap_or(u64::MAX);
+
+    let start = uptime_ticks();
+    while uptime_ticks().wrapping_sub(start) < ticks {
         hint::spin_loop();
     }
 }
+
+/// Busy-wait for at least the given number of microseconds.
+#[cfg(target_arch = "aarch64")]
+pub fn delay_us(us: u64) {
+    delay(Duration::from_micros(us));
+}
+
+/// Busy-wait for at least the given number of milliseconds.
+#[cfg(target_arch = "aarch64")]
+pub fn delay_ms(ms: u64) {
+    delay(Duration::from_millis(ms));
+}
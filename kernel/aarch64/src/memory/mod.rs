@@ -1,8 +1,11 @@
+#[cfg(target_arch = "aarch64")]
 pub mod paging;
 
 mod address;
 
-use crate::instruction::{at_s1e1r, isb};
+#[cfg(target_arch = "aarch64")]
+use crate::instruction::{at_s1e0r, at_s1e1r, at_s1e1w, isb};
+#[cfg(target_arch = "aarch64")]
 use crate::register::PAR_EL1;
 
 pub use self::address::{PA, VA};
@@ -10,9 +13,36 @@ pub use self::address::{PA, VA};
 pub const PAGE_SHIFT: u64 = 12;
 pub const PAGE_SIZE: usize = 1 << PAGE_SHIFT;
 pub const PAGE_MAP_LEVELS: u64 = 3;
+/// Size of a block mapped by a single level 2 descriptor.
+pub const BLOCK_SIZE: usize = PAGE_SIZE << 9;
 
+#[cfg(target_arch = "aarch64")]
 pub fn va_to_pa(va: VA) -> Option<PA> {
     at_s1e1r(va);
+    translation_result(va)
+}
+
+/// Translate `va` with the permissions of an EL1 write.
+///
+/// Returns `None` if `va` isn't mapped, or not writable from EL1.
+#[cfg(target_arch = "aarch64")]
+pub fn va_to_pa_write(va: VA) -> Option<PA> {
+    at_s1e1w(va);
+    translation_result(va)
+}
+
+/// Translate `va` with the permissions of an EL0 read.
+///
+/// Returns `None` if `va` isn't mapped, or not readable from EL0.
+#[cfg(target_arch = "aarch64")]
+pub fn user_va_to_pa(va: VA) -> Option<PA> {
+    at_s1e0r(va);
+    translation_result(va)
+}
+
+/// Read the result of a preceding address translation instruction.
+#[cfg(target_arch = "aarch64")]
+fn translation_result(va: VA) -> Option<PA> {
     isb();
 
     let par = PAR_EL1::read();
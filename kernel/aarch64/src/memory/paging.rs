@@ -3,10 +3,18 @@ use crate::register::{MAIR_EL1, TCR_EL1, TTBR0_EL1, TTBR1_EL1};
 
 use super::{PA, PAGE_SIZE, VA};
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct Flags(u64);
 
 impl Flags {
+    /// Extract the flags from a page or block descriptor, dropping the output address and the
+    /// descriptor type bits.
+    pub fn from_descriptor(desc: u64) -> Self {
+        const LOWER: u64 = 0x3ff << 2;
+        const UPPER: u64 = 0x1fff << 51;
+        Self(desc & (LOWER | UPPER))
+    }
+
     pub fn attr_idx(self, x: u8) -> Self {
         self.set(x, 2, 0b111)
     }
@@ -31,6 +39,11 @@ impl Flags {
         self.set(x, 54, 0b1)
     }
 
+    /// Set the descriptor bits reserved for software use (bits 55 to 58).
+    pub fn software(self, x: u8) -> Self {
+        self.set(x, 55, 0b1111)
+    }
+
     fn set<X: Into<u64>>(mut self, x: X, shift: u64, mask: u64) -> Self {
         self.0 &= !(mask << shift);
         self.0 |= x.into() << shift;
@@ -74,18 +87,23 @@ impl From<Shareability> for u64 {
 pub struct MairIndexes {
     pub device: u8,
     pub normal: u8,
+    /// Index of normal non-cacheable memory, if the firmware configured one.
+    pub normal_nc: Option<u8>,
 }
 
 impl MairIndexes {
     pub fn read() -> Self {
         let mut device = None;
         let mut normal = None;
+        let mut normal_nc = None;
 
         let mut check = |idx, attr| {
             if attr == 0x00 {
                 device = Some(idx);
             } else if attr == 0xff {
                 normal = Some(idx);
+            } else if attr == 0x44 {
+                normal_nc = Some(idx);
             }
         };
 
@@ -102,6 +120,7 @@ impl MairIndexes {
         Self {
             device: device.expect("missing device attr"),
             normal: normal.expect("missing normal attr"),
+            normal_nc,
         }
     }
 }
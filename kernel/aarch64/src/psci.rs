@@ -0,0 +1,20 @@
+//! Power State Coordination Interface (PSCI) calls.
+
+use core::arch::asm;
+
+use crate::halt;
+
+const SYSTEM_RESET: u64 = 0x8400_0009;
+
+/// Reset the system.
+///
+/// PSCI calls are issued through the HVC conduit, which is what QEMU's `virt` machine provides
+/// when it doesn't emulate EL2.
+pub fn system_reset() -> ! {
+    unsafe {
+        asm!("hvc #0", in("x0") SYSTEM_RESET, options(nostack));
+    }
+
+    // `SYSTEM_RESET` doesn't return on success.
+    halt();
+}
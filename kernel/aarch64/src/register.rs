@@ -99,6 +99,37 @@ system_register!(CNTVCT_EL0,
     VirtualCount[0:63],
 );
 
+system_register!(CNTV_CTL_EL0,
+    ENABLE[0:0],
+    IMASK[1:1],
+    ISTATUS[2:2],
+);
+
+system_register!(CNTV_CVAL_EL0,
+    CompareValue[0:63],
+);
+
+system_register!(CNTV_TVAL_EL0,
+    TimerValue[0:31],
+);
+
+system_register!(CTR_EL0,
+    IminLine[0:3],
+    L1Ip[14:15],
+    DminLine[16:19],
+    ERG[20:23],
+    CWG[24:27],
+    IDC[28:28],
+    DIC[29:29],
+);
+
+system_register!(DAIF,
+    F[6:6],
+    I[7:7],
+    A[8:8],
+    D[9:9],
+);
+
 system_register!(ESR_EL1,
     ISS[0:24],
     IL[25:25],
@@ -161,3 +192,160 @@ system_register!(TTBR1_EL1,
 system_register!(VBAR_EL1,
     VBA[11:63],
 );
+
+impl ESR_EL1 {
+    /// Decode the exception class and, for aborts, the relevant ISS fields.
+    pub fn decode(&self) -> EsrDecoded {
+        let iss = self.ISS();
+        let abort = || Abort {
+            fault: FaultStatus::decode(iss & 0x3f),
+            far_valid: iss & (1 << 10) == 0,
+        };
+
+        match self.EC() {
+            0x00 => EsrDecoded::Unknown,
+            0x15 => EsrDecoded::Svc {
+                imm: (iss & 0xffff) as u16,
+            },
+            0x20 => EsrDecoded::InstructionAbort {
+                lower_el: true,
+                abort: abort(),
+            },
+            0x21 => EsrDecoded::InstructionAbort {
+                lower_el: false,
+                abort: abort(),
+            },
+            0x22 => EsrDecoded::PcAlignment,
+            0x24 => EsrDecoded::DataAbort {
+                lower_el: true,
+                abort: abort(),
+                write: iss & (1 << 6) != 0,
+            },
+            0x25 => EsrDecoded::DataAbort {
+                lower_el: false,
+                abort: abort(),
+                write: iss & (1 << 6) != 0,
+            },
+            0x26 => EsrDecoded::SpAlignment,
+            0x3c => EsrDecoded::Breakpoint {
+                imm: (iss & 0xffff) as u16,
+            },
+            ec => EsrDecoded::Other { ec, iss },
+        }
+    }
+}
+
+/// A human-readable interpretation of an `ESR_EL1` value.
+#[derive(Clone, Copy, Debug)]
+pub enum EsrDecoded {
+    Unknown,
+    Svc {
+        imm: u16,
+    },
+    InstructionAbort {
+        lower_el: bool,
+        abort: Abort,
+    },
+    PcAlignment,
+    DataAbort {
+        lower_el: bool,
+        abort: Abort,
+        write: bool,
+    },
+    SpAlignment,
+    Breakpoint {
+        imm: u16,
+    },
+    Other {
+        ec: u64,
+        iss: u64,
+    },
+}
+
+impl fmt::Display for EsrDecoded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let el = |lower_el| if lower_el { "EL0" } else { "EL1" };
+
+        match self {
+            Self::Unknown => write!(f, "unknown reason"),
+            Self::Svc { imm } => write!(f, "SVC #{imm}"),
+            Self::InstructionAbort { lower_el, abort } => {
+                write!(
+                    f,
+                    "instruction abort from {}: {}",
+                    el(*lower_el),
+                    abort.fault
+                )
+            }
+            Self::PcAlignment => write!(f, "PC alignment fault"),
+            Self::DataAbort {
+                lower_el,
+                abort,
+                write,
+            } => {
+                let access = if *write { "write" } else { "read" };
+                write!(
+                    f,
+                    "data abort from {}: {}, {access}",
+                    el(*lower_el),
+                    abort.fault
+                )
+            }
+            Self::SpAlignment => write!(f, "SP alignment fault"),
+            Self::Breakpoint { imm } => write!(f, "BRK #{imm}"),
+            Self::Other { ec, iss } => write!(f, "exception class {ec:#x} (ISS={iss:#x})"),
+        }
+    }
+}
+
+/// The ISS fields common to instruction and data aborts.
+#[derive(Clone, Copy, Debug)]
+pub struct Abort {
+    pub fault: FaultStatus,
+    /// Whether FAR_EL1 holds the faulting address.
+    pub far_valid: bool,
+}
+
+/// A decoded instruction or data fault status code (IFSC/DFSC).
+#[derive(Clone, Copy, Debug)]
+pub enum FaultStatus {
+    AddressSize { level: u8 },
+    Translation { level: u8 },
+    AccessFlag { level: u8 },
+    Permission { level: u8 },
+    Alignment,
+    SyncExternal,
+    TlbConflict,
+    Other(u8),
+}
+
+impl FaultStatus {
+    fn decode(code: u64) -> Self {
+        let level = (code & 0b11) as u8;
+        match code {
+            0b00_0000..=0b00_0011 => Self::AddressSize { level },
+            0b00_0100..=0b00_0111 => Self::Translation { level },
+            0b00_1000..=0b00_1011 => Self::AccessFlag { level },
+            0b00_1100..=0b00_1111 => Self::Permission { level },
+            0b01_0000 => Self::SyncExternal,
+            0b10_0001 => Self::Alignment,
+            0b11_0000 => Self::TlbConflict,
+            code => Self::Other(code as u8),
+        }
+    }
+}
+
+impl fmt::Display for FaultStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::AddressSize { level } => write!(f, "address size fault at level {level}"),
+            Self::Translation { level } => write!(f, "translation fault at level {level}"),
+            Self::AccessFlag { level } => write!(f, "access flag fault at level {level}"),
+            Self::Permission { level } => write!(f, "permission fault at level {level}"),
+            Self::Alignment => write!(f, "alignment fault"),
+            Self::SyncExternal => write!(f, "synchronous external abort"),
+            Self::TlbConflict => write!(f, "TLB conflict abort"),
+            Self::Other(code) => write!(f, "fault status {code:#x}"),
+        }
+    }
+}
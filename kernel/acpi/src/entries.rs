@@ -0,0 +1,187 @@
+//! Iteration over the variable-length entry lists that follow some ACPI table headers.
+
+use core::{iter, mem, slice};
+
+use crate::{MADT, MADT_GICC, MADT_GICD, MADT_TYPE_GICC, MADT_TYPE_GICD, MCFG, MCFG_Allocation};
+
+impl MCFG {
+    /// Iterate over the PCIe configuration space allocations.
+    ///
+    /// # Safety
+    ///
+    /// `self` must be followed by the rest of the table, as given by `header.length`.
+    pub unsafe fn allocations(&self) -> impl Iterator<Item = &MCFG_Allocation> {
+        const ENTRY_SIZE: usize = mem::size_of::<MCFG_Allocation>();
+
+        let offset = mem::offset_of!(MCFG, allocations);
+        let entries = unsafe { trailing_bytes(self, self.header.length, offset) };
+
+        // All ACPI structures are packed, so any pointer is suitably aligned.
+        (0..entries.len() / ENTRY_SIZE).map(move |i| {
+            let entry = &entries[i * ENTRY_SIZE..][..ENTRY_SIZE];
+            unsafe { &*entry.as_ptr().cast::<MCFG_Allocation>() }
+        })
+    }
+}
+
+/// An entry in the MADT interrupt controller structure list.
+pub enum MadtEntry<'a> {
+    Gicc(&'a MADT_GICC),
+    Gicd(&'a MADT_GICD),
+    /// An entry of a type we don't parse.
+    Other {
+        type_: u8,
+    },
+}
+
+impl MADT {
+    /// Iterate over the interrupt controller structures.
+    ///
+    /// Iteration stops at the first entry with an invalid length.
+    ///
+    /// # Safety
+    ///
+    /// `self` must be followed by the rest of the table, as given by `header.length`.
+    pub unsafe fn interrupt_controllers(&self) -> impl Iterator<Item = MadtEntry<'_>> {
+        let offset = mem::offset_of!(MADT, interrupt_controllers);
+        let mut entries = unsafe { trailing_bytes(self, self.header.length, offset) };
+
+        iter::from_fn(move || {
+            let &[type_, length, ..] = entries else {
+                return None;
+            };
+            let length = usize::from(length);
+            if length < 2 || length > entries.len() {
+                return None;
+            }
+
+            let (entry, rest) = entries.split_at(length);
+            entries = rest;
+
+            // All ACPI structures are packed, so any pointer is suitably aligned.
+            let entry = match type_ {
+                MADT_TYPE_GICC if length >= mem::size_of::<MADT_GICC>() => {
+                    MadtEntry::Gicc(unsafe { &*entry.as_ptr().cast() })
+                }
+                MADT_TYPE_GICD if length >= mem::size_of::<MADT_GICD>() => {
+                    MadtEntry::Gicd(unsafe { &*entry.as_ptr().cast() })
+                }
+                _ => MadtEntry::Other { type_ },
+            };
+            Some(entry)
+        })
+    }
+}
+
+/// Return the bytes of a table following its fixed part, which has size `offset`.
+///
+/// # Safety
+///
+/// `table` must be followed by the rest of the table, for a total of `length` bytes.
+unsafe fn trailing_bytes<T>(table: &T, length: u32, offset: usize) -> &[u8] {
+    let len = (length as usize).saturating_sub(offset);
+    let ptr: *const u8 = (table as *const T).cast();
+    unsafe { slice::from_raw_parts(ptr.add(offset), len) }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use super::*;
+    use crate::DESCRIPTION_HEADER;
+
+    fn header(signature: [u8; 4], length: usize) -> DESCRIPTION_HEADER {
+        DESCRIPTION_HEADER {
+            signature,
+            length: length as u32,
+            revision: 1,
+            checksum: 0,
+            oem_id: [0; 6],
+            oem_table_id: [0; 8],
+            oem_revision: 0,
+            creator_id: [0; 4],
+            creator_revision: 0,
+        }
+    }
+
+    #[test]
+    fn test_mcfg_allocations() {
+        #[repr(C, packed)]
+        struct Table {
+            mcfg: MCFG,
+            allocations: [MCFG_Allocation; 2],
+        }
+
+        let allocation = |base_address, start_bus_number, end_bus_number| MCFG_Allocation {
+            base_address,
+            segment: 0,
+            start_bus_number,
+            end_bus_number,
+            reserved: [0; 4],
+        };
+        let table = Table {
+            mcfg: MCFG {
+                header: header(*b"MCFG", mem::size_of::<Table>()),
+                reserved: [0; 8],
+                allocations: [],
+            },
+            allocations: [allocation(0x1000, 0, 7), allocation(0x2000, 8, 15)],
+        };
+
+        let allocations: Vec<_> = unsafe { table.mcfg.allocations() }
+            .map(|a| (a.base_address, a.start_bus_number, a.end_bus_number))
+            .collect();
+        assert_eq!(allocations, [(0x1000, 0, 7), (0x2000, 8, 15)]);
+    }
+
+    #[test]
+    fn test_madt_interrupt_controllers() {
+        #[repr(C, packed)]
+        struct Table {
+            madt: MADT,
+            gicd: MADT_GICD,
+            unknown: [u8; 4],
+            truncated: [u8; 2],
+        }
+
+        let mut table = Table {
+            madt: MADT {
+                header: header(*b"APIC", mem::size_of::<Table>()),
+                local_interrupt_controller_address: 0,
+                flags: 0,
+                interrupt_controllers: [],
+            },
+            gicd: MADT_GICD {
+                type_: MADT_TYPE_GICD,
+                length: mem::size_of::<MADT_GICD>() as u8,
+                reserved1: 0,
+                gic_id: 0,
+                physical_base_address: 0x0800_0000,
+                system_vector_base: 0,
+                gic_version: 2,
+                reserved2: [0; 3],
+            },
+            unknown: [0x42, 4, 0, 0],
+            // An entry whose length extends beyond the table.
+            truncated: [MADT_TYPE_GICC, 80],
+        };
+
+        let mut entries = unsafe { table.madt.interrupt_controllers() };
+        let Some(MadtEntry::Gicd(gicd)) = entries.next() else {
+            panic!("expected GICD entry");
+        };
+        assert_eq!({ gicd.physical_base_address }, 0x0800_0000);
+        assert!(matches!(
+            entries.next(),
+            Some(MadtEntry::Other { type_: 0x42 })
+        ));
+        assert!(entries.next().is_none());
+
+        // Zero-length entries stop iteration, instead of looping forever.
+        table.unknown[1] = 0;
+        assert_eq!(unsafe { table.madt.interrupt_controllers() }.count(), 1);
+    }
+}
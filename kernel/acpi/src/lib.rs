@@ -1,4 +1,4 @@
-//! FFI definitions for ACPI types.
+//! FFI definitions for ACPI types, and helpers for locating ACPI tables.
 //!
 //! Extracted from the [ACPI] specification.
 //!
@@ -8,6 +8,22 @@
 #![allow(non_camel_case_types)]
 #![allow(clippy::upper_case_acronyms)]
 
+mod entries;
+mod xsdt;
+
+pub use self::entries::MadtEntry;
+pub use self::xsdt::{AcpiError, Xsdt};
+
+/// Check that the `len` bytes at `ptr` sum to zero, as required for ACPI table checksums.
+///
+/// # Safety
+///
+/// `ptr` must be valid for reads of `len` bytes.
+pub unsafe fn checksum_ok(ptr: *const u8, len: usize) -> bool {
+    let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+    bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)) == 0
+}
+
 // 5.2 ACPI System Description Tables
 // ----------------------------------
 
@@ -20,6 +36,14 @@ pub struct GAS {
     pub address: u64,
 }
 
+pub const GAS_SYSTEM_MEMORY: u8 = 0x00;
+
+pub const GAS_ACCESS_UNDEFINED: u8 = 0;
+pub const GAS_ACCESS_BYTE: u8 = 1;
+pub const GAS_ACCESS_WORD: u8 = 2;
+pub const GAS_ACCESS_DWORD: u8 = 3;
+pub const GAS_ACCESS_QWORD: u8 = 4;
+
 #[repr(C, packed)]
 pub struct RSDP {
     pub signature: [u8; 8],
@@ -52,6 +76,52 @@ pub struct XSDT {
     pub entry: [u8; 0],
 }
 
+#[repr(C, packed)]
+pub struct MADT {
+    pub header: DESCRIPTION_HEADER,
+    pub local_interrupt_controller_address: u32,
+    pub flags: u32,
+    pub interrupt_controllers: [u8; 0],
+}
+
+#[repr(C, packed)]
+pub struct MADT_GICC {
+    pub type_: u8,
+    pub length: u8,
+    reserved1: u16,
+    pub cpu_interface_number: u32,
+    pub acpi_processor_uid: u32,
+    pub flags: u32,
+    pub parking_protocol_version: u32,
+    pub performance_interrupt_gsiv: u32,
+    pub parked_address: u64,
+    pub physical_base_address: u64,
+    pub gicv: u64,
+    pub gich: u64,
+    pub vgic_maintenance_interrupt: u32,
+    pub gicr_base_address: u64,
+    pub mpidr: u64,
+    pub processor_power_efficiency_class: u8,
+    reserved2: u8,
+    pub spe_overflow_interrupt: u16,
+    pub trbe_interrupt: u16,
+}
+
+#[repr(C, packed)]
+pub struct MADT_GICD {
+    pub type_: u8,
+    pub length: u8,
+    reserved1: u16,
+    pub gic_id: u32,
+    pub physical_base_address: u64,
+    pub system_vector_base: u32,
+    pub gic_version: u8,
+    reserved2: [u8; 3],
+}
+
+pub const MADT_TYPE_GICC: u8 = 0x0b;
+pub const MADT_TYPE_GICD: u8 = 0x0c;
+
 // learn.microsoft.com
 // -------------------
 
@@ -0,0 +1,259 @@
+//! Lookup of tables referenced by the XSDT.
+
+use core::{mem, ptr};
+
+use crate::{DESCRIPTION_HEADER, RSDP, XSDT, checksum_ok};
+
+/// Errors found while validating the RSDP or XSDT.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AcpiError {
+    /// A table has an unexpected signature.
+    BadSignature,
+    /// A table has a revision we don't support.
+    UnsupportedRevision { revision: u8 },
+    /// A table's bytes don't sum to zero.
+    BadChecksum,
+}
+
+/// The Extended System Description Table, giving access to the other ACPI tables.
+pub struct Xsdt {
+    xsdt: *const XSDT,
+    /// Offset to add to physical addresses to obtain pointers.
+    phys_offset: u64,
+}
+
+impl Xsdt {
+    /// Locate the XSDT through the given RSDP, with ACPI tables accessible at their physical
+    /// addresses.
+    ///
+    /// # Safety
+    ///
+    /// `rsdp` must be a valid pointer to an [`RSDP`], and all ACPI tables must be valid and
+    /// identity-mapped.
+    ///
+    /// Fails if the RSDP or XSDT are malformed or have unsupported revisions.
+    pub unsafe fn new(rsdp: *const RSDP) -> Result<Self, AcpiError> {
+        unsafe { Self::with_phys_offset(rsdp, 0) }
+    }
+
+    /// Locate the XSDT through the given RSDP, with ACPI tables accessible at their physical
+    /// addresses plus `phys_offset`.
+    ///
+    /// # Safety
+    ///
+    /// `rsdp` must be a valid pointer to an [`RSDP`], and all ACPI tables must be valid and
+    /// mapped at their physical address plus `phys_offset`.
+    ///
+    /// Fails if the RSDP or XSDT are malformed or have unsupported revisions.
+    pub unsafe fn with_phys_offset(rsdp: *const RSDP, phys_offset: u64) -> Result<Self, AcpiError> {
+        let rsdp_bytes: *const u8 = rsdp.cast();
+        let rsdp = unsafe { &*rsdp };
+
+        if rsdp.signature != *b"RSD PTR " {
+            return Err(AcpiError::BadSignature);
+        }
+        if rsdp.revision != 2 {
+            let revision = rsdp.revision;
+            return Err(AcpiError::UnsupportedRevision { revision });
+        }
+        // The first checksum covers only the ACPI 1.0 part of the structure, the extended
+        // checksum covers all of it.
+        let extended_len = rsdp.length as usize;
+        if !unsafe { checksum_ok(rsdp_bytes, RSDP_V1_LEN) }
+            || !unsafe { checksum_ok(rsdp_bytes, extended_len) }
+        {
+            return Err(AcpiError::BadChecksum);
+        }
+
+        let xsdt_ptr = (rsdp.xsdt_address + phys_offset) as *const XSDT;
+        let xsdt = unsafe { &*xsdt_ptr };
+        if xsdt.header.signature != *b"XSDT" {
+            return Err(AcpiError::BadSignature);
+        }
+        if xsdt.header.revision != 1 {
+            let revision = xsdt.header.revision;
+            return Err(AcpiError::UnsupportedRevision { revision });
+        }
+        if !unsafe { table_checksum_ok(xsdt_ptr.cast()) } {
+            return Err(AcpiError::BadChecksum);
+        }
+
+        Ok(Self {
+            xsdt: xsdt_ptr,
+            phys_offset,
+        })
+    }
+
+    /// Find the first table with the given signature.
+    pub fn find_table(&self, signature: &[u8; 4]) -> Option<*const DESCRIPTION_HEADER> {
+        self.tables().find(|&table| {
+            let header = unsafe { &*table };
+            header.signature == *signature
+        })
+    }
+
+    /// Iterate over the tables referenced by the XSDT.
+    ///
+    /// Tables with invalid checksums are skipped.
+    pub fn tables(&self) -> impl Iterator<Item = *const DESCRIPTION_HEADER> + '_ {
+        const ENTRY_SIZE: usize = mem::size_of::<u64>();
+
+        let xsdt = unsafe { &*self.xsdt };
+        let entries_size = xsdt.header.length as usize - mem::offset_of!(XSDT, entry);
+        let entries = xsdt.entry.as_ptr();
+
+        (0..entries_size / ENTRY_SIZE)
+            .map(move |i| {
+                // Entries are only 4-byte aligned.
+                let entry = unsafe { entries.add(i * ENTRY_SIZE).cast::<u64>() };
+                let addr = unsafe { ptr::read_unaligned(entry) };
+                (addr + self.phys_offset) as *const DESCRIPTION_HEADER
+            })
+            .filter(|&table| unsafe { table_checksum_ok(table) })
+    }
+}
+
+/// Size of the ACPI 1.0 part of the RSDP, covered by its first checksum.
+const RSDP_V1_LEN: usize = mem::offset_of!(RSDP, length);
+
+/// Validate the checksum of the table with the given header, which covers the whole table.
+///
+/// # Safety
+///
+/// `table` must point to a table that is readable for its full length.
+unsafe fn table_checksum_ok(table: *const DESCRIPTION_HEADER) -> bool {
+    let len = unsafe { (*table).length } as usize;
+    unsafe { checksum_ok(table.cast(), len) }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::boxed::Box;
+
+    use super::*;
+
+    /// An XSDT with two entries, and the tables it references.
+    #[repr(C)]
+    struct Tables {
+        rsdp: RSDP,
+        xsdt: XSDT,
+        entries: [u8; 16],
+        mcfg: DESCRIPTION_HEADER,
+        spcr: DESCRIPTION_HEADER,
+    }
+
+    fn header(signature: [u8; 4], length: usize, revision: u8) -> DESCRIPTION_HEADER {
+        DESCRIPTION_HEADER {
+            signature,
+            length: length as u32,
+            revision,
+            checksum: 0,
+            oem_id: [0; 6],
+            oem_table_id: [0; 8],
+            oem_revision: 0,
+            creator_id: [0; 4],
+            creator_revision: 0,
+        }
+    }
+
+    /// Compute the checksum byte that makes the `len` bytes at `ptr` sum to zero.
+    ///
+    /// The checksum byte itself must be zero when this is called.
+    fn checksum(ptr: *const u8, len: usize) -> u8 {
+        let bytes = unsafe { core::slice::from_raw_parts(ptr, len) };
+        0u8.wrapping_sub(bytes.iter().fold(0u8, |sum, b| sum.wrapping_add(*b)))
+    }
+
+    fn fix_header_checksum(header: &mut DESCRIPTION_HEADER, len: usize) {
+        header.checksum = 0;
+        header.checksum = checksum((&raw const *header).cast(), len);
+    }
+
+    // Use a non-zero offset, to check that it is applied to all physical addresses.
+    const OFFSET: u64 = 0x1000;
+
+    /// Create a valid set of tables, with correct addresses and checksums.
+    ///
+    /// The tables are boxed, so that their addresses stay stable.
+    fn valid_tables() -> Box<Tables> {
+        let header_size = mem::size_of::<DESCRIPTION_HEADER>();
+        let mut tables = Box::new(Tables {
+            rsdp: RSDP {
+                signature: *b"RSD PTR ",
+                checksum: 0,
+                oem_id: [0; 6],
+                revision: 2,
+                rsdt_address: 0,
+                length: mem::size_of::<RSDP>() as u32,
+                xsdt_address: 0,
+                extended_checksum: 0,
+                reserved: [0; 3],
+            },
+            xsdt: XSDT {
+                header: header(*b"XSDT", header_size + 16, 1),
+                entry: [],
+            },
+            entries: [0; 16],
+            mcfg: header(*b"MCFG", header_size, 1),
+            spcr: header(*b"SPCR", header_size, 2),
+        });
+
+        let phys = |ptr: *const DESCRIPTION_HEADER| ptr as u64 - OFFSET;
+
+        tables.rsdp.xsdt_address = phys((&raw const tables.xsdt).cast());
+        let mcfg_pa = phys(&raw const tables.mcfg);
+        let spcr_pa = phys(&raw const tables.spcr);
+        tables.entries[..8].copy_from_slice(&mcfg_pa.to_le_bytes());
+        tables.entries[8..].copy_from_slice(&spcr_pa.to_le_bytes());
+
+        let rsdp_ptr: *const u8 = (&raw const tables.rsdp).cast();
+        tables.rsdp.checksum = checksum(rsdp_ptr, RSDP_V1_LEN);
+        tables.rsdp.extended_checksum = checksum(rsdp_ptr, mem::size_of::<RSDP>());
+        fix_header_checksum(&mut tables.xsdt.header, header_size + 16);
+        fix_header_checksum(&mut tables.mcfg, header_size);
+        fix_header_checksum(&mut tables.spcr, header_size);
+
+        tables
+    }
+
+    #[test]
+    fn test_find_table() {
+        let tables = valid_tables();
+
+        let xsdt = unsafe { Xsdt::with_phys_offset(&raw const tables.rsdp, OFFSET) }.unwrap();
+        assert_eq!(xsdt.tables().count(), 2);
+        assert_eq!(xsdt.find_table(b"MCFG"), Some(&raw const tables.mcfg));
+        assert_eq!(xsdt.find_table(b"SPCR"), Some(&raw const tables.spcr));
+        assert_eq!(xsdt.find_table(b"APIC"), None);
+    }
+
+    #[test]
+    fn test_bad_checksums() {
+        // A corrupted table is skipped.
+        let mut tables = valid_tables();
+        tables.mcfg.oem_revision = 1;
+        let xsdt = unsafe { Xsdt::with_phys_offset(&raw const tables.rsdp, OFFSET) }.unwrap();
+        assert_eq!(xsdt.tables().count(), 1);
+        assert_eq!(xsdt.find_table(b"MCFG"), None);
+        assert_eq!(xsdt.find_table(b"SPCR"), Some(&raw const tables.spcr));
+
+        // A corrupted XSDT is rejected.
+        let mut tables = valid_tables();
+        tables.entries[0] ^= 1;
+        let result = unsafe { Xsdt::with_phys_offset(&raw const tables.rsdp, OFFSET) };
+        assert_eq!(result.err(), Some(AcpiError::BadChecksum));
+
+        // Both RSDP checksums are checked.
+        let mut tables = valid_tables();
+        tables.rsdp.oem_id[0] = 1;
+        let result = unsafe { Xsdt::with_phys_offset(&raw const tables.rsdp, OFFSET) };
+        assert_eq!(result.err(), Some(AcpiError::BadChecksum));
+
+        let mut tables = valid_tables();
+        tables.rsdp.reserved[0] = 1;
+        let result = unsafe { Xsdt::with_phys_offset(&raw const tables.rsdp, OFFSET) };
+        assert_eq!(result.err(), Some(AcpiError::BadChecksum));
+    }
+}
@@ -1,17 +1,28 @@
 //! FFI-compatible versions of `BootInfo` types.
 
-use core::slice;
+use core::mem::MaybeUninit;
+use core::{ptr, slice, str};
 
 use aarch64::memory::PA;
 
-use crate::{MemoryBlock, Uart};
+use crate::{Framebuffer, MemoryBlock, Module, Uart};
 
 #[repr(C)]
 #[derive(Debug)]
 pub struct BootInfo {
     memory: Memory,
-    uart: Uart,
+    // `Option<Uart>` has no stable layout, so we pass a flag instead.
+    has_uart: bool,
+    uart: MaybeUninit<Uart>,
     acpi_rsdp: PA,
+    // `Option<Framebuffer>` has no stable layout, so we pass a flag instead.
+    has_framebuffer: bool,
+    framebuffer: MaybeUninit<Framebuffer>,
+    /// Null if there is no command line.
+    cmdline_ptr: *const u8,
+    cmdline_len: usize,
+    modules_ptr: *const Module<'static>,
+    modules_len: usize,
 }
 
 #[repr(C)]
@@ -25,8 +36,21 @@ impl super::BootInfo<'_> {
     pub fn into_ffi(self) -> BootInfo {
         BootInfo {
             memory: self.memory.into_ffi(),
-            uart: self.uart,
+            has_uart: self.uart.is_some(),
+            uart: match self.uart {
+                Some(uart) => MaybeUninit::new(uart),
+                None => MaybeUninit::uninit(),
+            },
             acpi_rsdp: self.acpi_rsdp,
+            has_framebuffer: self.framebuffer.is_some(),
+            framebuffer: match self.framebuffer {
+                Some(fb) => MaybeUninit::new(fb),
+                None => MaybeUninit::uninit(),
+            },
+            cmdline_ptr: self.cmdline.map_or(ptr::null(), str::as_ptr),
+            cmdline_len: self.cmdline.map_or(0, str::len),
+            modules_ptr: self.modules.as_ptr().cast(),
+            modules_len: self.modules.len(),
         }
     }
 
@@ -35,11 +59,23 @@ impl super::BootInfo<'_> {
     /// All pointers in `ffi` must be valid.
     pub unsafe fn from_ffi(ffi: BootInfo) -> Self {
         let memory = unsafe { super::Memory::from_ffi(ffi.memory) };
+        let uart = ffi.has_uart.then(|| unsafe { ffi.uart.assume_init() });
+        let framebuffer = ffi
+            .has_framebuffer
+            .then(|| unsafe { ffi.framebuffer.assume_init() });
+        let cmdline = (!ffi.cmdline_ptr.is_null()).then(|| unsafe {
+            let bytes = slice::from_raw_parts(ffi.cmdline_ptr, ffi.cmdline_len);
+            str::from_utf8_unchecked(bytes)
+        });
+        let modules = unsafe { slice::from_raw_parts(ffi.modules_ptr, ffi.modules_len) };
 
         Self {
             memory,
-            uart: ffi.uart,
+            uart,
             acpi_rsdp: ffi.acpi_rsdp,
+            framebuffer,
+            cmdline,
+            modules,
         }
     }
 }
@@ -58,6 +94,6 @@ impl super::Memory<'_> {
     pub unsafe fn from_ffi(ffi: Memory) -> Self {
         let blocks = unsafe { slice::from_raw_parts(ffi.blocks_ptr, ffi.blocks_len) };
 
-        Self { blocks }
+        Self::from_slice(blocks)
     }
 }
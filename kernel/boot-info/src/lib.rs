@@ -9,7 +9,8 @@ extern crate alloc;
 pub mod ffi;
 
 use alloc::vec::Vec;
-use core::fmt;
+use core::marker::PhantomData;
+use core::{fmt, slice, str};
 
 use aarch64::memory::{PA, PAGE_SIZE};
 
@@ -21,9 +22,19 @@ pub struct BootInfo<'boot> {
     ///
     /// This information can be retrieved from the ACPI structures, but the boot loader provides it
     /// separately so the kernel can set up serial output as quickly as possible.
-    pub uart: Uart,
+    ///
+    /// `None` if the system has no supported UART.
+    pub uart: Option<Uart>,
     /// Address of the ACPI RSDP structure.
     pub acpi_rsdp: PA,
+    /// Info about the graphics framebuffer, if the system has a display.
+    pub framebuffer: Option<Framebuffer>,
+    /// The kernel command line, taken from the UEFI load options.
+    ///
+    /// `None` if no load options were passed, or if they were empty or not valid text.
+    pub cmdline: Option<&'boot str>,
+    /// Additional files loaded by the boot loader, as listed in the boot manifest.
+    pub modules: &'boot [Module<'boot>],
 }
 
 #[derive(Debug)]
@@ -32,30 +43,43 @@ pub struct Memory<'boot> {
 }
 
 impl<'boot> Memory<'boot> {
-    pub fn new(mut blocks: Vec<MemoryBlock>) -> Self {
-        // Cleanup: Merge consecutive blocks of the same type.
-        blocks.sort_unstable_by_key(|b| b.start);
-
-        fn can_merge(a: &MemoryBlock, b: &MemoryBlock) -> bool {
-            let consequtive = a.start + a.pages * PAGE_SIZE == b.start;
-            let same_type = a.type_ == b.type_;
-            consequtive && same_type
-        }
+    /// Create a memory map from the given blocks, as-is.
+    pub fn from_slice(blocks: &'boot [MemoryBlock]) -> Self {
+        Self { blocks }
+    }
 
-        let mut i = 0;
-        while let (Some(cur), Some(next)) = (blocks.get(i), blocks.get(i + 1)) {
-            if can_merge(cur, next) {
-                blocks[i].pages += next.pages;
-                blocks.remove(i + 1);
-            } else {
-                i += 1;
-            }
-        }
+    /// Create a memory map from the given blocks, merging them with [`merge_blocks`].
+    ///
+    /// The merged block list is leaked, to make it live for the rest of the boot process.
+    pub fn from_vec(blocks: Vec<MemoryBlock>) -> Self {
+        let blocks = merge_blocks(blocks);
+        Self::from_slice(blocks.leak())
+    }
+}
 
-        Self {
-            blocks: blocks.leak(),
+/// Sort the given blocks by address and merge consecutive blocks of the same type and
+/// attributes.
+pub fn merge_blocks(mut blocks: Vec<MemoryBlock>) -> Vec<MemoryBlock> {
+    blocks.sort_unstable_by_key(|b| b.start);
+
+    fn can_merge(a: &MemoryBlock, b: &MemoryBlock) -> bool {
+        let consequtive = a.start + a.pages * PAGE_SIZE == b.start;
+        let same_type = a.type_ == b.type_;
+        let same_attributes = a.attributes == b.attributes;
+        consequtive && same_type && same_attributes
+    }
+
+    let mut i = 0;
+    while let (Some(cur), Some(next)) = (blocks.get(i), blocks.get(i + 1)) {
+        if can_merge(cur, next) {
+            blocks[i].pages += next.pages;
+            blocks.remove(i + 1);
+        } else {
+            i += 1;
         }
     }
+
+    blocks
 }
 
 #[repr(C)]
@@ -64,6 +88,24 @@ pub struct MemoryBlock {
     pub type_: MemoryType,
     pub start: PA,
     pub pages: usize,
+    /// Memory attributes, as reported by UEFI (`EFI_MEMORY_*` bits).
+    pub attributes: u64,
+}
+
+impl MemoryBlock {
+    /// The memory supports uncacheable access.
+    pub const ATTR_UC: u64 = 1 << 0;
+    /// The memory supports write-combining access.
+    pub const ATTR_WC: u64 = 1 << 1;
+    /// The memory supports write-through cacheable access.
+    pub const ATTR_WT: u64 = 1 << 2;
+    /// The memory supports write-back cacheable access.
+    pub const ATTR_WB: u64 = 1 << 3;
+
+    /// Whether the memory can be mapped write-back cacheable.
+    pub fn cacheable(&self) -> bool {
+        self.attributes & Self::ATTR_WB != 0
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -99,17 +141,186 @@ impl fmt::Display for MemoryType {
     }
 }
 
+/// A file loaded into memory by the boot loader.
+///
+/// Module memory is of type [`MemoryType::Kernel`], so it stays intact after the kernel reclaims
+/// boot memory, and is reachable through the physmap.
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct Module<'boot> {
+    // A `&str` has no stable layout, so we store its parts instead.
+    name_ptr: *const u8,
+    name_len: usize,
+    /// Physical address of the first page holding the file contents.
+    pub base: PA,
+    /// Number of pages holding the file contents.
+    pub pages: usize,
+    /// Size of the file, in bytes.
+    pub size: usize,
+    _name: PhantomData<&'boot str>,
+}
+
+impl<'boot> Module<'boot> {
+    pub fn new(name: &'boot str, base: PA, pages: usize, size: usize) -> Self {
+        Self {
+            name_ptr: name.as_ptr(),
+            name_len: name.len(),
+            base,
+            pages,
+            size,
+            _name: PhantomData,
+        }
+    }
+
+    /// The name the module was given in the boot manifest.
+    pub fn name(&self) -> &'boot str {
+        // SAFETY: `name_ptr` and `name_len` were taken from a `&'boot str` in `new`.
+        unsafe { str::from_utf8_unchecked(slice::from_raw_parts(self.name_ptr, self.name_len)) }
+    }
+}
+
+impl fmt::Debug for Module<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Module")
+            .field("name", &self.name())
+            .field("base", &self.base)
+            .field("pages", &self.pages)
+            .field("size", &self.size)
+            .finish()
+    }
+}
+
 #[repr(C)]
 #[derive(Clone, Copy, Debug)]
 pub enum Uart {
-    Pl011 { base: PA },
-    Uart16550 { base: PA },
+    /// A PL011 UART. `irq` is the GIC interrupt ID, or 0 if the UART has no interrupt.
+    ///
+    /// `baud_rate` is the rate the firmware configured the UART with, or 0 if unknown.
+    Pl011 { base: PA, irq: u32, baud_rate: u32 },
+    /// A 16550 UART. `irq` is the GIC interrupt ID, or 0 if the UART has no interrupt.
+    ///
+    /// `baud_rate` is the rate the firmware configured the UART with, or 0 if unknown.
+    /// `reg_width` is the register access width in bytes (1, 2 or 4), which is also the stride
+    /// between registers.
+    Uart16550 {
+        base: PA,
+        irq: u32,
+        baud_rate: u32,
+        reg_width: u8,
+    },
 }
 
 impl Uart {
     pub fn base(&self) -> PA {
         match self {
-            Self::Pl011 { base } | Self::Uart16550 { base } => *base,
+            Self::Pl011 { base, .. } | Self::Uart16550 { base, .. } => *base,
+        }
+    }
+
+    /// Return the GIC interrupt ID of the UART, if it has one.
+    pub fn irq(&self) -> Option<u32> {
+        match self {
+            Self::Pl011 { irq, .. } | Self::Uart16550 { irq, .. } => (*irq != 0).then_some(*irq),
+        }
+    }
+}
+
+/// A linear framebuffer with 32-bit pixels.
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+pub struct Framebuffer {
+    pub base: PA,
+    /// Width of the visible area, in pixels.
+    pub width: u32,
+    /// Height of the visible area, in pixels.
+    pub height: u32,
+    /// Number of pixels per scan line, which may be larger than `width`.
+    pub stride: u32,
+    pub format: PixelFormat,
+}
+
+impl Framebuffer {
+    pub const BYTES_PER_PIXEL: usize = 4;
+
+    /// Return the size of the framebuffer, in bytes.
+    pub fn size(&self) -> usize {
+        self.stride as usize * self.height as usize * Self::BYTES_PER_PIXEL
+    }
+}
+
+/// Byte order of the color channels in a framebuffer pixel.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// Red in byte 0, green in byte 1, blue in byte 2.
+    Rgb,
+    /// Blue in byte 0, green in byte 1, red in byte 2.
+    Bgr,
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::vec;
+
+    use super::*;
+
+    fn block(type_: MemoryType, start: u64, pages: usize) -> MemoryBlock {
+        MemoryBlock {
+            type_,
+            start: PA::new(start),
+            pages,
+            attributes: MemoryBlock::ATTR_WB,
         }
     }
+
+    fn summary(blocks: &[MemoryBlock]) -> Vec<(MemoryType, u64, usize)> {
+        blocks
+            .iter()
+            .map(|b| (b.type_, b.start.into_u64(), b.pages))
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_adjacent_same_type() {
+        let blocks = merge_blocks(vec![
+            block(MemoryType::Unused, 0x3000, 2),
+            block(MemoryType::Unused, 0x1000, 2),
+        ]);
+        assert_eq!(summary(&blocks), [(MemoryType::Unused, 0x1000, 4)]);
+    }
+
+    #[test]
+    fn test_merge_adjacent_different_type() {
+        let blocks = merge_blocks(vec![
+            block(MemoryType::Unused, 0x1000, 2),
+            block(MemoryType::Boot, 0x3000, 2),
+        ]);
+        assert_eq!(
+            summary(&blocks),
+            [
+                (MemoryType::Unused, 0x1000, 2),
+                (MemoryType::Boot, 0x3000, 2)
+            ]
+        );
+
+        let mut uncached = block(MemoryType::Unused, 0x3000, 2);
+        uncached.attributes = MemoryBlock::ATTR_UC;
+        let blocks = merge_blocks(vec![block(MemoryType::Unused, 0x1000, 2), uncached]);
+        assert_eq!(blocks.len(), 2);
+    }
+
+    #[test]
+    fn test_merge_non_adjacent() {
+        let blocks = merge_blocks(vec![
+            block(MemoryType::Unused, 0x1000, 2),
+            block(MemoryType::Unused, 0x4000, 2),
+        ]);
+        assert_eq!(
+            summary(&blocks),
+            [
+                (MemoryType::Unused, 0x1000, 2),
+                (MemoryType::Unused, 0x4000, 2)
+            ]
+        );
+    }
 }
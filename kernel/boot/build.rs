@@ -0,0 +1,6 @@
+use std::env;
+
+fn main() {
+    let crate_dir = env::var("CARGO_MANIFEST_DIR").unwrap();
+    build_util::emit_git_hash(&crate_dir);
+}
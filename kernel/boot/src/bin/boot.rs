@@ -5,6 +5,7 @@
 
 use core::ffi::c_void;
 use core::panic::PanicInfo;
+use core::sync::atomic::{AtomicBool, Ordering};
 
 use boot::log;
 
@@ -16,10 +17,26 @@ unsafe extern "efiapi" fn efi_main(image_handle: *mut c_void, system_table: *mut
 
 #[panic_handler]
 fn panic(panic: &PanicInfo<'_>) -> ! {
+    // Writing the crash log might panic itself, in which case we give up.
+    static PANICKING: AtomicBool = AtomicBool::new(false);
+    if PANICKING.swap(true, Ordering::Relaxed) {
+        aarch64::halt();
+    }
+
     log!("PANIC: {}", panic.message());
     if let Some(loc) = panic.location() {
         log!("  in file '{}' at line {}", loc.file(), loc.line());
     }
 
+    match panic.location() {
+        Some(loc) => boot::write_crash_log(format_args!(
+            "{} (in file '{}' at line {})",
+            panic.message(),
+            loc.file(),
+            loc.line(),
+        )),
+        None => boot::write_crash_log(format_args!("{}", panic.message())),
+    }
+
     aarch64::halt();
 }
@@ -4,6 +4,10 @@
 //! UEFI application that loads the kernel and userimg from the boot disk, collects information
 //! about the system required for the kernel to boot, then exits boot services and jumps into the
 //! kernel.
+//!
+//! Additional files, like further user images or a ramdisk, can be listed in a `\boot.manifest`
+//! file on the boot disk, one `name path` pair per line. They are loaded into memory and passed to
+//! the kernel as modules.
 
 #![cfg_attr(not(test), no_std)]
 
@@ -15,15 +19,18 @@ mod allocator;
 mod paging;
 mod uefi;
 
+use aarch64::Stopwatch;
 use aarch64::memory::paging::{AccessPermissions, Flags};
 use aarch64::memory::{PA, PAGE_SIZE, VA};
+use alloc::format;
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use boot_info::{BootInfo, MemoryType};
+use boot_info::{BootInfo, MemoryType, Module};
 use core::ffi::c_void;
-use core::mem;
+use core::{fmt, mem};
 use elf::ElfFile;
-use kstd::io::Read;
+use kstd::io::{BufReader, Read, Seek, Write};
 
 use crate::paging::KernelPager;
 
@@ -36,33 +43,100 @@ pub unsafe fn init_uefi(image_handle: *mut c_void, system_table: *mut c_void) {
     unsafe { uefi::init(image_handle, system_table.cast()) }
 }
 
+/// Path of the crash log written by [`write_crash_log`].
+const CRASH_LOG_PATH: &str = "\\teaos.log";
+
+/// Write a crash log containing the given message and the current memory map to `\teaos.log` on
+/// the boot volume, replacing any previous log.
+///
+/// This is meant to be called from the panic handler, to allow inspecting boot failures on
+/// machines without an accessible console. It does nothing if boot services are not available
+/// anymore.
+pub fn write_crash_log(message: fmt::Arguments) {
+    if !uefi::boot_services_available() {
+        return;
+    }
+
+    let mut log = format!("TeaOS boot loader crashed: {message}\n\nmemory map:\n");
+    let (buffer_size, _) = uefi::get_memory_map_size();
+    let memory_map = uefi::get_memory_map(vec![0; buffer_size + 1024]);
+    for desc in memory_map.iter() {
+        let start = desc.physical_start;
+        let pages = desc.number_of_pages;
+        let type_ = desc.type_;
+        log += &format!("  {start:#012x}  {pages:8}  {type_}\n");
+    }
+
+    let boot_fs = uefi::get_boot_fs();
+    let root = boot_fs.open_volume();
+    // `File::create` doesn't truncate, so remove any previous log first.
+    root.create(CRASH_LOG_PATH).delete();
+    let mut file = root.create(CRASH_LOG_PATH);
+    // We are already panicking, so there is nothing useful to do about write errors.
+    let _ = file.write_all(log.as_bytes()).and_then(|()| file.flush());
+}
+
 /// Run the boot loader.
 ///
 /// This loads the kernel binary and userimg, retrieves all required boot information, and finally
 /// passes control to the kernel.
 pub fn load() -> ! {
     log!("entered UEFI boot loader");
+    log!(
+        "TeaOS boot version={} git={} profile={} platform={:?}",
+        env!("CARGO_PKG_VERSION"),
+        env!("TEAOS_GIT_HASH"),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        uefi::firmware_vendor(),
+    );
 
     log!("loading kernel binary");
+    let phase = Stopwatch::start();
     let mut kernel = load_kernel();
     log!("  kernel.entry={:#?}", kernel.entry);
     log!("  kernel.userimg_start={:?}", kernel.userimg_start);
     log!("  kernel.physmap_start={:?}", kernel.physmap_start);
+    log!("  took {:?}", phase.elapsed());
 
     log!("loading userimg");
+    let phase = Stopwatch::start();
     load_userimg(&mut kernel.pager, kernel.userimg_start);
+    log!("  took {:?}", phase.elapsed());
+
+    log!("loading modules");
+    let phase = Stopwatch::start();
+    let modules = load_modules();
+    log!("  took {:?}", phase.elapsed());
 
     log!("retrieving ACPI RSDP pointer");
+    let phase = Stopwatch::start();
     let rsdp = find_acpi_rsdp();
     log!("  rsdp_ptr={rsdp:#?}");
 
     log!("retrieving UART config");
     let uart_info = unsafe { find_uart(rsdp) };
     log!("  uart={uart_info:?}");
+    log!("  took {:?}", phase.elapsed());
+
+    log!("retrieving framebuffer config");
+    let phase = Stopwatch::start();
+    let framebuffer = find_framebuffer();
+    log!("  framebuffer={framebuffer:?}");
+    log!("  took {:?}", phase.elapsed());
+
+    log!("retrieving kernel command line");
+    let cmdline = read_cmdline();
+    log!("  cmdline={cmdline:?}");
 
     log!("creating phys mapping");
-    let uart_base = uart_info.base();
+    let phase = Stopwatch::start();
+    let uart_base = uart_info.map(|uart| uart.base());
     create_physmap(&mut kernel.pager, kernel.physmap_start, uart_base);
+    log!("  took {:?}", phase.elapsed());
 
     log!("exiting boot services");
     let memory_info = exit_boot_services();
@@ -76,6 +150,9 @@ pub fn load() -> ! {
         memory: memory_info,
         uart: uart_info,
         acpi_rsdp: PA::new(rsdp as u64),
+        framebuffer,
+        cmdline,
+        modules,
     }
     .into_ffi();
 
@@ -99,16 +176,31 @@ const KERNEL_MEMORY: uefi::sys::MEMORY_TYPE = 0x80000000;
 /// returned page table.
 fn load_kernel() -> Kernel {
     let boot_fs = uefi::get_boot_fs();
-    let root = boot_fs.open_volume();
+    let mut root = boot_fs.open_volume();
+    for entry in root.read_dir() {
+        let kind = if entry.is_directory() { "dir" } else { "file" };
+        log!(
+            "  boot volume: {} ({kind}, {} bytes)",
+            entry.name,
+            entry.size
+        );
+    }
+
     let kernel_file = root.open("\\kernel");
+    let kernel_size = kernel_file.get_size();
 
-    let mut elf = ElfFile::open(kernel_file);
+    // Parsing issues many small reads, each of which is a firmware call, so buffer them.
+    let mut elf = ElfFile::open(BufReader::new(kernel_file), kernel_size);
 
     let entry = elf.entry();
     let entry = unsafe { mem::transmute::<u64, fn(boot_info::ffi::BootInfo) -> !>(entry) };
 
     let mut pager = KernelPager::new();
-    let phdrs: Vec<_> = elf.program_headers().collect();
+    let mut segments = Vec::new();
+    let phdrs: Vec<_> = elf
+        .program_headers()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|error| panic!("invalid kernel binary: {error:?}"));
     for phdr in phdrs {
         if !phdr.is_load() {
             continue;
@@ -133,6 +225,12 @@ fn load_kernel() -> Kernel {
         let count = buffer.len() / PAGE_SIZE;
         pager.map_ram_region(va, pa, count, flags);
         log!("  mapped {va:#} -> {pa:#} ({count} pages)");
+
+        segments.push((va, buffer));
+    }
+
+    if elf.is_dynamic() {
+        apply_relocations(&mut elf, &mut segments);
     }
 
     let mut userimg_start = None;
@@ -148,6 +246,11 @@ fn load_kernel() -> Kernel {
         }
     }
 
+    if let Some(text) = elf.section_by_name(c".text") {
+        let start = text.address();
+        log!("  kernel.text={start:#x}..{:#x}", start + text.size());
+    }
+
     let userimg_start =
         userimg_start.unwrap_or_else(|| panic!("missing `userimg_start` kernel symbol"));
     let physmap_start =
@@ -161,6 +264,42 @@ fn load_kernel() -> Kernel {
     }
 }
 
+/// Apply the dynamic relocations of a position-independent kernel to its loaded segments.
+///
+/// Segments are loaded at their link addresses, so the load bias is zero for now. Only
+/// `R_AARCH64_RELATIVE` relocations are supported.
+fn apply_relocations<R: Read + Seek>(elf: &mut ElfFile<R>, segments: &mut [(VA, &mut [u8])]) {
+    const LOAD_BIAS: u64 = 0;
+
+    let Some(relocations) = elf.dynamic_relocations() else {
+        return;
+    };
+
+    let mut count = 0;
+    for rela in relocations {
+        assert_eq!(
+            rela.r_type(),
+            elf::R_AARCH64_RELATIVE,
+            "unsupported kernel relocation type",
+        );
+
+        let target = VA::new(rela.offset());
+        let (offset, buffer) = segments
+            .iter_mut()
+            .find_map(|(va, buffer)| {
+                let offset = target.into_u64().checked_sub(va.into_u64())? as usize;
+                (offset + 8 <= buffer.len()).then_some((offset, buffer))
+            })
+            .unwrap_or_else(|| panic!("kernel relocation outside segments: {target:?}"));
+
+        let value = LOAD_BIAS.wrapping_add_signed(rela.addend());
+        buffer[offset..offset + 8].copy_from_slice(&value.to_le_bytes());
+        count += 1;
+    }
+
+    log!("  applied {count} relocations");
+}
+
 /// Load the userimg binary.
 ///
 /// The userimg binary is expected to be located in the boot file system at `\userimg`, and is
@@ -183,14 +322,87 @@ fn load_userimg(pager: &mut KernelPager, userimg_start: VA) {
     log!("  mapped {userimg_start:#} -> {pa:#} ({pages} pages)");
 }
 
-fn create_physmap(pager: &mut KernelPager, physmap_start: VA, uart_base: PA) {
-    let mut map = |pa: PA, pages, type_| {
+/// Path of the optional boot manifest read by [`load_modules`].
+const MANIFEST_PATH: &str = "\\boot.manifest";
+
+/// Load the modules listed in the boot manifest.
+///
+/// The manifest is a text file at `\boot.manifest` on the boot file system. Each line names a
+/// module and gives the path of the file to load, separated by whitespace, e.g.
+/// `initrd \initrd.img`. Empty lines and lines starting with `#` are ignored.
+///
+/// Module files are read into kernel memory, which the kernel can access through the physmap.
+/// Returns an empty list if there is no manifest.
+///
+/// # Panics
+///
+/// Panics if the manifest is malformed, or a listed file doesn't exist.
+fn load_modules() -> &'static [Module<'static>] {
+    let boot_fs = uefi::get_boot_fs();
+    let root = boot_fs.open_volume();
+    let Some(mut manifest_file) = root.try_open(MANIFEST_PATH) else {
+        log!("  no boot manifest");
+        return &[];
+    };
+
+    let size = manifest_file.get_size() as usize;
+    let manifest = manifest_file.read_exact_vec(size).unwrap();
+    let manifest =
+        String::from_utf8(manifest).unwrap_or_else(|_| panic!("boot manifest is not valid UTF-8"));
+
+    let mut modules = Vec::new();
+    for (name, path) in parse_manifest(&manifest) {
+        let mut file = root
+            .try_open(path)
+            .unwrap_or_else(|| panic!("module file not found: {path}"));
+
+        // UEFI can't allocate zero pages, so empty files still get one.
+        let size = file.get_size() as usize;
+        let buffer = uefi::allocate_page_memory(size.max(1), KERNEL_MEMORY);
+        file.read_exact(&mut buffer[..size]).unwrap();
+
+        let base = PA::new(buffer.as_ptr() as u64);
+        let pages = buffer.len() / PAGE_SIZE;
+        log!("  loaded {name} from {path} to {base:#} ({pages} pages)");
+
+        let name = String::from(name).leak();
+        modules.push(Module::new(name, base, pages, size));
+    }
+
+    modules.leak()
+}
+
+/// Parse the contents of a boot manifest into `(name, path)` pairs.
+///
+/// # Panics
+///
+/// Panics if a line doesn't consist of exactly a name and a path.
+fn parse_manifest(manifest: &str) -> impl Iterator<Item = (&str, &str)> {
+    manifest
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(|line| {
+            let mut fields = line.split_whitespace();
+            match (fields.next(), fields.next(), fields.next()) {
+                (Some(name), Some(path), None) => (name, path),
+                _ => panic!("invalid boot manifest line: {line:?}"),
+            }
+        })
+}
+
+/// Map all memory blocks from the UEFI memory map, and the UART registers if any, into the physmap.
+///
+/// MMIO blocks, and blocks that don't support write-back caching, are mapped with device memory
+/// attributes. Everything else is mapped as normal cacheable memory.
+fn create_physmap(pager: &mut KernelPager, physmap_start: VA, uart_base: Option<PA>) {
+    let mut map = |pa: PA, pages, device| {
         let va = physmap_start + pa.into_u64();
         let flags = Flags::default()
             .access_permissions(AccessPermissions::PrivRW)
             .privileged_execute_never(true);
 
-        if type_ == MemoryType::Mmio {
+        if device {
             pager.map_mmio_region(va, pa, pages, flags);
         } else {
             pager.map_ram_region(va, pa, pages, flags);
@@ -202,14 +414,24 @@ fn create_physmap(pager: &mut KernelPager, physmap_start: VA, uart_base: PA) {
     let buffer = vec![0; buffer_size + 1024];
     let memory_map = uefi::get_memory_map(buffer);
 
+    let mut uart_mapped = false;
     for desc in memory_map.iter() {
         if let Some(block) = memory_bootinfo_from_uefi(desc) {
-            map(block.start, block.pages, block.type_);
+            let device = block.type_ == MemoryType::Mmio || !block.cacheable();
+            map(block.start, block.pages, device);
+
+            let end = block.start + block.pages * PAGE_SIZE;
+            uart_mapped |= uart_base.is_some_and(|base| (block.start..end).contains(&base));
         };
     }
 
-    // The UEFI memory map doesn't include all device MMIO regions, so map the UART one explicitly.
-    map(uart_base, 1, MemoryType::Mmio);
+    // The UEFI memory map doesn't include all device MMIO regions, so map the UART one explicitly,
+    // unless that would overlap a block mapped above.
+    if let Some(base) = uart_base
+        && !uart_mapped
+    {
+        map(base, 1, true);
+    }
 }
 
 /// Find the ACPI RSDP in the UEFI config table.
@@ -231,51 +453,141 @@ fn find_acpi_rsdp() -> *mut acpi::RSDP {
 ///
 /// Finds the SPCR in the ACPI tables and extracts the UART type and base address.
 ///
+/// Returns `None`, after logging a warning, if there is no SPCR or it describes a UART we don't
+/// support. The kernel then boots without a serial console.
+///
 /// # Safety
 ///
 /// `rsdp` must be a valid pointer to an [`acpi::RSDP`].
-unsafe fn find_uart(rsdp_ptr: *mut acpi::RSDP) -> boot_info::Uart {
-    let rsdp = unsafe { &*rsdp_ptr };
-
-    assert_eq!(rsdp.signature, *b"RSD PTR ");
-    assert_eq!(rsdp.revision, 2);
-
-    let xsdt_ptr = rsdp.xsdt_address as *const acpi::XSDT;
-    let xsdt = unsafe { &*xsdt_ptr };
-    assert_eq!(xsdt.header.signature, *b"XSDT");
-    assert_eq!(xsdt.header.revision, 1);
-
-    let xsdt_size = xsdt.header.length as usize;
-    let mut entry_size = xsdt_size - mem::offset_of!(acpi::XSDT, entry);
-    let mut entry_ptr = xsdt.entry.as_ptr();
-
-    let mut spcr: Option<&acpi::SPCR> = None;
-    const ADDR_SIZE: usize = mem::size_of::<usize>();
-    while entry_size >= ADDR_SIZE {
-        let addr_bytes_ptr = entry_ptr as *mut [u8; ADDR_SIZE];
-        let addr_bytes = unsafe { *addr_bytes_ptr };
-        let addr = usize::from_le_bytes(addr_bytes);
-        let desc_ptr = addr as *mut acpi::DESCRIPTION_HEADER;
-        let desc = unsafe { &*desc_ptr };
-        if desc.signature == *b"SPCR" {
-            spcr = Some(unsafe { &*desc_ptr.cast() });
-            break;
+unsafe fn find_uart(rsdp_ptr: *mut acpi::RSDP) -> Option<boot_info::Uart> {
+    // SAFETY: ACPI tables are identity-mapped while boot services are active.
+    let xsdt = unsafe { acpi::Xsdt::new(rsdp_ptr) }.expect("valid ACPI tables");
+    let Some(spcr_ptr) = xsdt.find_table(b"SPCR") else {
+        log!("  warning: no SPCR table, booting without a UART");
+        return None;
+    };
+    let spcr = unsafe { &*spcr_ptr.cast::<acpi::SPCR>() };
+    assert_eq!(spcr.header.revision, 2);
+
+    let gas = &spcr.base_address;
+    if gas.address_space_id != acpi::GAS_SYSTEM_MEMORY {
+        let id = gas.address_space_id;
+        log!("  warning: unsupported UART address space {id:#x}, booting without a UART");
+        return None;
+    }
+    let base = PA::new(gas.address);
+
+    // Bit 3 of the interrupt type indicates an ARM GIC interrupt.
+    let irq = if spcr.interrupt_type & (1 << 3) != 0 {
+        spcr.global_system_interrupt
+    } else {
+        0
+    };
+
+    // A zero value means the baud rate isn't known.
+    let baud_rate = match spcr.configured_baud_rate {
+        3 => 9600,
+        4 => 19200,
+        6 => 57600,
+        7 => 115200,
+        _ => 0,
+    };
+
+    let uart = match spcr.interface_type {
+        acpi::UART_TYPE_16550 | acpi::UART_TYPE_16550_EXT => {
+            // Legacy tables leave the access size undefined, which means byte access.
+            let reg_width = match gas.access_size {
+                acpi::GAS_ACCESS_UNDEFINED | acpi::GAS_ACCESS_BYTE => 1,
+                acpi::GAS_ACCESS_WORD => 2,
+                acpi::GAS_ACCESS_DWORD => 4,
+                size => {
+                    log!("  warning: unsupported 16550 access size {size}, booting without a UART");
+                    return None;
+                }
+            };
+            boot_info::Uart::Uart16550 {
+                base,
+                irq,
+                baud_rate,
+                reg_width,
+            }
+        }
+        acpi::UART_TYPE_PL011 => boot_info::Uart::Pl011 {
+            base,
+            irq,
+            baud_rate,
+        },
+        value => {
+            log!("  warning: unsupported UART type {value:#x}, booting without a UART");
+            return None;
         }
+    };
+
+    Some(uart)
+}
 
-        entry_ptr = unsafe { entry_ptr.add(ADDR_SIZE) };
-        entry_size -= ADDR_SIZE;
+/// Retrieve information about the graphics framebuffer.
+///
+/// Returns `None` if the system has no Graphics Output Protocol, or if it doesn't offer a mode
+/// with a linear 32-bit RGB or BGR framebuffer. The current mode is kept if it is suitable,
+/// otherwise the suitable mode with the highest resolution is selected.
+fn find_framebuffer() -> Option<boot_info::Framebuffer> {
+    use uefi::sys::*;
+
+    let gop = uefi::get_graphics_output()?;
+
+    #[allow(non_upper_case_globals)]
+    let pixel_format = |info: &GRAPHICS_OUTPUT_MODE_INFORMATION| match info.pixel_format {
+        PixelRedGreenBlueReserved8BitPerColor => Some(boot_info::PixelFormat::Rgb),
+        PixelBlueGreenRedReserved8BitPerColor => Some(boot_info::PixelFormat::Bgr),
+        PixelBitMask | PixelBltOnly => None,
+        _ => None,
+    };
+
+    if pixel_format(&gop.current_info()).is_none() {
+        let resolution = |info: &GRAPHICS_OUTPUT_MODE_INFORMATION| {
+            info.horizontal_resolution * info.vertical_resolution
+        };
+        let best = (0..gop.max_mode())
+            .map(|mode| (mode, gop.query_mode(mode)))
+            .filter(|(_, info)| pixel_format(info).is_some())
+            .max_by_key(|(_, info)| resolution(info));
+        let (mode, _) = best?;
+        gop.set_mode(mode);
     }
 
-    let spcr = spcr.expect("SPCR table present");
-    assert_eq!(spcr.header.revision, 2);
+    let info = gop.current_info();
+    let (base, _) = gop.framebuffer();
 
-    let base = PA::new(spcr.base_address.address);
+    Some(boot_info::Framebuffer {
+        base: PA::new(base),
+        width: info.horizontal_resolution,
+        height: info.vertical_resolution,
+        stride: info.pixels_per_scan_line,
+        format: pixel_format(&info)?,
+    })
+}
 
-    match spcr.interface_type {
-        acpi::UART_TYPE_16550 | acpi::UART_TYPE_16550_EXT => boot_info::Uart::Uart16550 { base },
-        acpi::UART_TYPE_PL011 => boot_info::Uart::Pl011 { base },
-        value => unimplemented!("UART type: {value:#x}"),
+/// Read the kernel command line from the load options of the boot loader image.
+///
+/// The load options are decoded up to the first NUL character and trimmed of surrounding
+/// whitespace. Returns `None` if there are no load options, or if they are empty or not valid
+/// UCS-2 text (boot managers may pass binary data). The returned string lives in boot memory.
+fn read_cmdline() -> Option<&'static str> {
+    let loaded_image = uefi::get_loaded_image();
+    let options = loaded_image.load_options()?;
+
+    let chars = options.split(|&c| c == 0).next().unwrap_or_default();
+    let cmdline: String = char::decode_utf16(chars.iter().copied())
+        .collect::<Result<_, _>>()
+        .ok()?;
+
+    let cmdline = cmdline.trim();
+    if cmdline.is_empty() {
+        return None;
     }
+
+    Some(String::from(cmdline).leak())
 }
 
 /// Exit the UEFI boot services.
@@ -302,7 +614,7 @@ fn exit_boot_services() -> boot_info::Memory<'static> {
     // We can't deallocate anymore, so we must avoid dropping the `MemoryMap`.
     mem::forget(memory_map);
 
-    boot_info::Memory::new(block_info)
+    boot_info::Memory::from_vec(block_info)
 }
 
 fn memory_bootinfo_from_uefi(
@@ -326,6 +638,7 @@ fn memory_bootinfo_from_uefi(
         type_,
         start: desc.physical_start.into(),
         pages: desc.number_of_pages as usize,
+        attributes: desc.attribute,
     };
     Some(block)
 }
@@ -1,7 +1,7 @@
 use core::mem;
 
 use aarch64::memory::paging::{Flags, MairIndexes, Shareability, load_ttbr1};
-use aarch64::memory::{PA, PAGE_MAP_LEVELS, PAGE_SIZE, VA};
+use aarch64::memory::{BLOCK_SIZE, PA, PAGE_MAP_LEVELS, PAGE_SIZE, VA};
 use aarch64::register::TCR_EL1;
 
 use crate::uefi;
@@ -20,17 +20,32 @@ impl KernelPager {
         }
     }
 
+    /// Map a region, preferring 2 MiB blocks wherever the region is suitably aligned.
     fn map_region(&mut self, start_va: VA, start_pa: PA, pages: usize, flags: Flags) {
+        const BLOCK_PAGES: usize = BLOCK_SIZE / PAGE_SIZE;
+
         let flags = flags.unprivileged_execute_never(true).access_flag(true);
 
         let mut va = start_va;
         let mut pa = start_pa;
-        for _ in 0..pages {
-            let desc = Descriptor::new_page(pa, flags);
-            self.insert(va, desc);
-
-            va += PAGE_SIZE;
-            pa += PAGE_SIZE;
+        let mut remaining = pages;
+        while remaining > 0 {
+            let aligned = va.is_aligned_to(BLOCK_SIZE) && pa.is_aligned_to(BLOCK_SIZE);
+            if aligned && remaining >= BLOCK_PAGES {
+                let desc = Descriptor::new_block(pa, flags);
+                self.insert(va, desc, PAGE_MAP_LEVELS - 1);
+
+                va += BLOCK_SIZE;
+                pa += BLOCK_SIZE;
+                remaining -= BLOCK_PAGES;
+            } else {
+                let desc = Descriptor::new_page(pa, flags);
+                self.insert(va, desc, PAGE_MAP_LEVELS);
+
+                va += PAGE_SIZE;
+                pa += PAGE_SIZE;
+                remaining -= 1;
+            }
         }
     }
 
@@ -48,11 +63,29 @@ impl KernelPager {
         self.map_region(start_va, start_pa, pages, flags);
     }
 
-    fn insert(&mut self, va: VA, desc: Descriptor) {
+    /// Insert a descriptor into the table at level `leaf_level`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any part of the range mapped by `desc` is already mapped. Mappings of the kernel
+    /// address space must never overlap, so an overlap indicates a bug, e.g. in the linker script.
+    fn insert(&mut self, va: VA, desc: Descriptor, leaf_level: u64) {
+        let size = if leaf_level == PAGE_MAP_LEVELS {
+            PAGE_SIZE
+        } else {
+            BLOCK_SIZE
+        };
+        let end = va + size;
+
         // Traverse through intermediary levels, creating page tables as needed.
         let mut table = unsafe { &mut *self.root };
-        for level in 0..PAGE_MAP_LEVELS {
+        for level in 0..leaf_level {
             let idx = va.page_table_idx(level);
+            assert!(
+                !table[idx].is_block(),
+                "mapping {va:?}-{end:?} overlaps an existing block mapping"
+            );
+
             if !table[idx].valid() {
                 let table_ptr = alloc_page_table();
                 let table_pa = PA::new(table_ptr as u64);
@@ -62,7 +95,12 @@ impl KernelPager {
             table = unsafe { &mut *table[idx].next_table() };
         }
 
-        let idx = va.page_table_idx(PAGE_MAP_LEVELS);
+        // At the block level, a valid entry might also be a table holding page mappings.
+        let idx = va.page_table_idx(leaf_level);
+        assert!(
+            table[idx].is_empty(),
+            "mapping {va:?}-{end:?} overlaps an existing mapping"
+        );
         table[idx] = desc;
     }
 
@@ -91,10 +129,22 @@ impl Descriptor {
         Self(u64::from(base) | u64::from(flags) | 0b11)
     }
 
+    fn new_block(base: PA, flags: Flags) -> Self {
+        Self(u64::from(base) | u64::from(flags) | 0b01)
+    }
+
     fn valid(&self) -> bool {
         self.0 & 0b11 == 0b11
     }
 
+    fn is_block(&self) -> bool {
+        self.0 & 0b11 == 0b01
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0 & 0b1 == 0
+    }
+
     fn next_table(&self) -> *mut Table {
         let addr = self.0 & 0xfffffffff000;
         addr as *mut _
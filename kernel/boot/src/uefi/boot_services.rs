@@ -2,7 +2,7 @@ use core::ffi::c_void;
 use core::ptr;
 
 use super::bs_ref::BsRef;
-use super::protocol::{FileSystem, LoadedImage};
+use super::protocol::{FileSystem, GraphicsOutput, LoadedImage};
 use super::{MemoryMap, sys, validate_mut_ptr, validate_table_header};
 
 use alloc::vec::Vec;
@@ -64,6 +64,20 @@ impl BootServices {
         interface
     }
 
+    /// Return the first interface installed for the given protocol, if any.
+    pub fn locate_protocol(&self, protocol: &sys::GUID) -> Option<*mut c_void> {
+        let locate_protocol = unsafe { (**self.ptr).locate_protocol };
+
+        let mut interface = ptr::null_mut();
+        let status = locate_protocol(protocol, ptr::null_mut(), &mut interface);
+        if status == sys::NOT_FOUND {
+            return None;
+        }
+
+        assert_eq!(status, sys::SUCCESS);
+        Some(interface)
+    }
+
     pub fn get_loaded_image(&self, handle: sys::HANDLE) -> LoadedImage {
         let ptr = self.handle_protocol(handle, &sys::LOADED_IMAGE_PROTOCOL_GUID);
         unsafe { LoadedImage::new(ptr.cast()) }
@@ -74,6 +88,11 @@ impl BootServices {
         unsafe { FileSystem::new(ptr.cast()) }
     }
 
+    pub fn get_graphics_output(&self) -> Option<GraphicsOutput> {
+        let ptr = self.locate_protocol(&sys::GRAPHICS_OUTPUT_PROTOCOL_GUID)?;
+        Some(unsafe { GraphicsOutput::new(ptr.cast()) })
+    }
+
     pub fn allocate_pages(&self, pages: usize, memory_type: sys::MEMORY_TYPE) -> *mut u8 {
         let allocate_pages = unsafe { (**self.ptr).allocate_pages };
 
@@ -84,6 +103,17 @@ impl BootServices {
         address as *mut u8
     }
 
+    /// # Safety
+    ///
+    /// `ptr` must point to `pages` pages previously allocated with [`Self::allocate_pages`], and
+    /// the memory must not be used anymore.
+    pub unsafe fn free_pages(&self, ptr: *mut u8, pages: usize) {
+        let free_pages = unsafe { (**self.ptr).free_pages };
+
+        let status = free_pages(ptr as u64, pages);
+        assert_eq!(status, sys::SUCCESS);
+    }
+
     pub fn allocate_pool(&self, size: usize) -> *mut u8 {
         let allocate_pool = unsafe { (**self.ptr).allocate_pool };
 
@@ -101,6 +131,18 @@ impl BootServices {
         assert_eq!(status, sys::SUCCESS);
     }
 
+    /// Busy-wait for at least the given number of microseconds.
+    ///
+    /// Useful for devices that need time to settle during initialization, before boot services
+    /// are exited.
+    #[expect(dead_code, reason = "no users yet")]
+    pub fn stall(&self, microseconds: usize) {
+        let stall = unsafe { (**self.ptr).stall };
+
+        let status = stall(microseconds);
+        assert_eq!(status, sys::SUCCESS);
+    }
+
     /// # Safety
     ///
     /// Calling this method invalidates any references to the boot services and protocols. Callers
@@ -10,6 +10,7 @@ mod string;
 use alloc::vec;
 use alloc::vec::Vec;
 use core::ffi::c_void;
+use core::ops::{Deref, DerefMut};
 use core::{mem, slice};
 
 use crc::Crc32;
@@ -18,7 +19,7 @@ use kstd::sync::Mutex;
 use crate::{validate_mut_ptr, validate_ptr};
 
 use self::boot_services::BootServices;
-use self::protocol::{ConsoleOut, FileSystem};
+use self::protocol::{ConsoleOut, FileSystem, GraphicsOutput, LoadedImage};
 
 static UEFI: Mutex<Option<Uefi>> = Mutex::new(None);
 
@@ -94,6 +95,17 @@ pub fn exit_boot_services(map_key: usize) {
     }
 }
 
+/// Whether boot services can currently be used.
+///
+/// Returns `false` if the UEFI state is locked, so this can be used safely from a panic handler.
+pub fn boot_services_available() -> bool {
+    let initialized = UEFI.try_lock().is_some_and(|uefi| uefi.is_some());
+    let refs_available = BOOT_SERVICE_REFS
+        .try_lock()
+        .is_some_and(|refs| refs.is_some());
+    initialized && refs_available
+}
+
 pub fn image_handle() -> sys::HANDLE {
     Uefi::borrow(|uefi| uefi.image_handle)
 }
@@ -110,6 +122,30 @@ pub fn config_table() -> ConfigTable {
     Uefi::borrow(|uefi| uefi.config_table())
 }
 
+/// Return the name of the firmware vendor.
+pub fn firmware_vendor() -> alloc::string::String {
+    Uefi::borrow(|uefi| {
+        let ptr = unsafe { (*uefi.system_table).firmware_vendor.cast::<u16>() };
+        if ptr.is_null() {
+            return "unknown".into();
+        }
+
+        let mut chars = Vec::new();
+        // SAFETY: The firmware vendor is a null-terminated UCS-2 string.
+        unsafe {
+            let mut p = ptr;
+            while *p != 0 {
+                chars.push(*p);
+                p = p.add(1);
+            }
+        }
+
+        char::decode_utf16(chars)
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect()
+    })
+}
+
 pub fn allocate_page(memory_type: sys::MEMORY_TYPE) -> &'static mut [u8; PAGE_SIZE] {
     let ptr = boot_services().allocate_pages(1, memory_type);
     let ptr = ptr as *mut [u8; PAGE_SIZE];
@@ -121,18 +157,62 @@ pub fn allocate_page(memory_type: sys::MEMORY_TYPE) -> &'static mut [u8; PAGE_SI
     buffer
 }
 
+/// Allocate zeroed pages holding at least `size` bytes, that are never freed.
 pub fn allocate_page_memory(size: usize, memory_type: sys::MEMORY_TYPE) -> &'static mut [u8] {
-    // Round up to page size.
-    let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
-    let pages = size / PAGE_SIZE;
+    PageAllocation::new(size, memory_type).leak()
+}
 
-    let ptr = boot_services().allocate_pages(pages, memory_type);
-    let buffer = unsafe { slice::from_raw_parts_mut(ptr, size) };
+/// Zeroed pages allocated from the firmware, which are freed when the allocation is dropped.
+///
+/// Freeing requires boot services, so allocations that are still alive when boot services are
+/// exited must be leaked.
+pub struct PageAllocation {
+    ptr: *mut u8,
+    pages: usize,
+}
 
-    // Zero the page memory.
-    buffer.iter_mut().for_each(|b| *b = 0);
+impl PageAllocation {
+    /// Allocate pages holding at least `size` bytes.
+    pub fn new(size: usize, memory_type: sys::MEMORY_TYPE) -> Self {
+        // Round up to page size.
+        let size = (size + PAGE_SIZE - 1) & !(PAGE_SIZE - 1);
+        let pages = size / PAGE_SIZE;
 
-    buffer
+        let ptr = boot_services().allocate_pages(pages, memory_type);
+        let mut alloc = Self { ptr, pages };
+
+        // Zero the page memory.
+        alloc.fill(0);
+
+        alloc
+    }
+
+    /// Give up ownership of the pages, so they are never freed.
+    pub fn leak(self) -> &'static mut [u8] {
+        let alloc = mem::ManuallyDrop::new(self);
+        unsafe { slice::from_raw_parts_mut(alloc.ptr, alloc.pages * PAGE_SIZE) }
+    }
+}
+
+impl Deref for PageAllocation {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { slice::from_raw_parts(self.ptr, self.pages * PAGE_SIZE) }
+    }
+}
+
+impl DerefMut for PageAllocation {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { slice::from_raw_parts_mut(self.ptr, self.pages * PAGE_SIZE) }
+    }
+}
+
+impl Drop for PageAllocation {
+    fn drop(&mut self) {
+        // SAFETY: The pages were allocated in `new`, and nobody can reference them anymore.
+        unsafe { boot_services().free_pages(self.ptr, self.pages) };
+    }
 }
 
 pub fn get_memory_map_size() -> (usize, usize) {
@@ -146,6 +226,10 @@ pub fn get_memory_map(buffer: Vec<u8>) -> MemoryMap {
         .unwrap_or_else(|(size, _)| panic!("buffer too small: {buffer_size} < {size}"))
 }
 
+pub fn get_loaded_image() -> LoadedImage {
+    boot_services().get_loaded_image(image_handle())
+}
+
 pub fn get_boot_fs() -> FileSystem {
     let bs = boot_services();
 
@@ -154,6 +238,10 @@ pub fn get_boot_fs() -> FileSystem {
     bs.get_file_system(boot_device)
 }
 
+pub fn get_graphics_output() -> Option<GraphicsOutput> {
+    boot_services().get_graphics_output()
+}
+
 pub struct ConfigTable {
     ptr: *mut sys::CONFIGURATION_TABLE,
     len: usize,
@@ -227,18 +315,17 @@ unsafe fn validate_table_header(ptr: *const sys::TABLE_HEADER, signature: u64) {
     assert_eq!(hdr.signature, signature);
     assert_eq!(hdr.revision & (2 << 16), 2 << 16);
 
-    let start: *const u8 = ptr.cast();
-    let crc32_start: *const u8 = (&raw const hdr.crc32).cast();
-    let crc32_end: *const u8 = (&raw const hdr.reserved).cast();
+    // The CRC is computed with the CRC field itself zeroed. The table is read-only, so we stream
+    // it through the CRC calculator and substitute zeros for the field.
+    let size = hdr.header_size as usize;
+    let bytes: &[u8] = unsafe { slice::from_raw_parts(ptr.cast(), size) };
+
+    let crc32_offset = mem::offset_of!(sys::TABLE_HEADER, crc32);
+    let crc32_field = crc32_offset..crc32_offset + mem::size_of::<u32>();
 
     let mut crc = Crc32::new();
-    for i in 0..hdr.header_size {
-        let data = unsafe { start.add(i as usize) };
-        if data >= crc32_start && data < crc32_end {
-            crc.update(0x00);
-        } else {
-            crc.update(unsafe { *data });
-        }
+    for (i, byte) in bytes.iter().enumerate() {
+        crc.update(if crc32_field.contains(&i) { 0 } else { *byte });
     }
-    assert_eq!(crc.finish(), hdr.crc32);
+    assert_eq!(crc.finish(), hdr.crc32, "table CRC mismatch");
 }
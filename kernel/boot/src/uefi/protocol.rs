@@ -1,11 +1,12 @@
 use alloc::vec;
-use core::{fmt, ptr};
+use core::mem::ManuallyDrop;
+use core::{fmt, iter, mem, ptr, slice};
 
-use kstd::io::{self, Read, Seek};
+use kstd::io::{self, Read, Seek, Write};
 
 use super::bs_ref::BsRef;
 use super::string::String;
-use super::{sys, validate_mut_ptr};
+use super::{sys, validate_mut_ptr, validate_ptr};
 
 pub struct LoadedImage {
     ptr: BsRef<*mut sys::LOADED_IMAGE_PROTOCOL>,
@@ -29,6 +30,23 @@ impl LoadedImage {
     pub fn device_handle(&self) -> sys::HANDLE {
         unsafe { (**self.ptr).device_handle }
     }
+
+    /// Return the load options passed to the image, interpreted as a UCS-2 string.
+    ///
+    /// Returns `None` if no load options were passed, or if their size isn't a multiple of the
+    /// character size.
+    pub fn load_options(&self) -> Option<&[u16]> {
+        let proto = unsafe { &**self.ptr };
+        let size = proto.load_options_size as usize;
+        let ptr: *const u16 = proto.load_options.cast();
+        if size == 0 || ptr.is_null() || !size.is_multiple_of(mem::size_of::<u16>()) {
+            return None;
+        }
+
+        validate_ptr(ptr);
+        let len = size / mem::size_of::<u16>();
+        Some(unsafe { slice::from_raw_parts(ptr, len) })
+    }
 }
 
 pub struct ConsoleOut {
@@ -63,6 +81,69 @@ impl fmt::Write for ConsoleOut {
     }
 }
 
+pub struct GraphicsOutput {
+    ptr: BsRef<*mut sys::GRAPHICS_OUTPUT_PROTOCOL>,
+}
+
+impl GraphicsOutput {
+    /// # Safety
+    ///
+    /// `ptr` must be a valid pointer to a [`sys::GRAPHICS_OUTPUT_PROTOCOL`].
+    pub unsafe fn new(ptr: *mut sys::GRAPHICS_OUTPUT_PROTOCOL) -> Self {
+        validate_mut_ptr(ptr);
+
+        let proto = unsafe { &*ptr };
+        validate_mut_ptr(proto.mode);
+
+        Self {
+            ptr: BsRef::new(ptr),
+        }
+    }
+
+    fn mode(&self) -> &sys::GRAPHICS_OUTPUT_PROTOCOL_MODE {
+        unsafe { &*(**self.ptr).mode }
+    }
+
+    pub fn max_mode(&self) -> u32 {
+        self.mode().max_mode
+    }
+
+    /// Return information about the current mode.
+    pub fn current_info(&self) -> sys::GRAPHICS_OUTPUT_MODE_INFORMATION {
+        let info = self.mode().info;
+        validate_mut_ptr(info);
+        unsafe { *info }
+    }
+
+    /// Return the physical address and size of the framebuffer for the current mode.
+    pub fn framebuffer(&self) -> (sys::PHYSICAL_ADDRESS, usize) {
+        let mode = self.mode();
+        (mode.frame_buffer_base, mode.frame_buffer_size)
+    }
+
+    pub fn query_mode(&self, mode_number: u32) -> sys::GRAPHICS_OUTPUT_MODE_INFORMATION {
+        let query_mode = unsafe { (**self.ptr).query_mode };
+
+        let mut size = 0;
+        let mut info = ptr::null_mut();
+        let status = query_mode(*self.ptr, mode_number, &mut size, &mut info);
+        assert_eq!(status, sys::SUCCESS);
+        validate_mut_ptr(info);
+
+        // The info buffer is allocated from pool memory and must be freed by the caller.
+        let result = unsafe { *info };
+        super::boot_services().free_pool(info.cast());
+        result
+    }
+
+    pub fn set_mode(&self, mode_number: u32) {
+        let set_mode = unsafe { (**self.ptr).set_mode };
+
+        let status = set_mode(*self.ptr, mode_number);
+        assert_eq!(status, sys::SUCCESS);
+    }
+}
+
 pub struct FileSystem {
     ptr: BsRef<*mut sys::SIMPLE_FILE_SYSTEM_PROTOCOL>,
 }
@@ -113,39 +194,161 @@ impl File {
     }
 
     pub fn open(&self, file_name: &str) -> File {
+        self.try_open(file_name)
+            .unwrap_or_else(|| panic!("file not found: {file_name}"))
+    }
+
+    /// Open the named file for reading, returning `None` if it doesn't exist.
+    pub fn try_open(&self, file_name: &str) -> Option<File> {
+        self.open_with_mode(file_name, sys::FILE_MODE_READ)
+    }
+
+    /// Open the named file for writing, creating it if it doesn't exist.
+    ///
+    /// Note that an existing file is not truncated.
+    pub fn create(&self, file_name: &str) -> File {
+        let mode = sys::FILE_MODE_READ | sys::FILE_MODE_WRITE | sys::FILE_MODE_CREATE;
+        self.open_with_mode(file_name, mode)
+            .expect("created file exists")
+    }
+
+    fn open_with_mode(&self, file_name: &str, mode: u64) -> Option<File> {
         let open = unsafe { (**self.ptr).open };
 
         let file_name = String::from(file_name);
         let mut new_handle = ptr::null_mut();
-        let status = open(
-            *self.ptr,
-            &mut new_handle,
-            file_name.as_ptr(),
-            sys::FILE_MODE_READ,
-            0,
-        );
+        let status = open(*self.ptr, &mut new_handle, file_name.as_ptr(), mode, 0);
+        if status == sys::NOT_FOUND {
+            return None;
+        }
         assert_eq!(status, sys::SUCCESS);
 
-        unsafe { Self::new(new_handle) }
+        Some(unsafe { Self::new(new_handle) })
     }
 
-    pub fn get_size(&self) -> u64 {
+    /// Return information about this file.
+    pub fn get_info(&self) -> FileInfo {
         let get_info = unsafe { (**self.ptr).get_info };
 
-        // The size of the `FILE_INFO` struct depends on the length of the file name. We
-        // optimistically assume that file name is reasonably short.
-        let mut buf_size = 1024;
-        let mut buf = vec![0; buf_size];
-        let status = get_info(
-            *self.ptr,
-            &sys::FILE_INFO_ID,
-            &mut buf_size,
-            buf.as_mut_ptr().cast(),
-        );
-        assert_eq!(status, sys::SUCCESS);
+        // The size of the `FILE_INFO` struct depends on the length of the file name, so we retry
+        // with a larger buffer if needed. The buffer is allocated as `u64`s, to satisfy the
+        // alignment requirement of `FILE_INFO`.
+        let mut buf = vec![0_u64; 128];
+        loop {
+            let mut buf_size = buf.len() * mem::size_of::<u64>();
+            let status = get_info(
+                *self.ptr,
+                &sys::FILE_INFO_ID,
+                &mut buf_size,
+                buf.as_mut_ptr().cast(),
+            );
+            if status == sys::BUFFER_TOO_SMALL {
+                buf.resize(buf_size.div_ceil(mem::size_of::<u64>()), 0);
+                continue;
+            }
+            assert_eq!(status, sys::SUCCESS);
+
+            return unsafe { FileInfo::parse(buf.as_ptr().cast(), buf_size) };
+        }
+    }
 
-        let file_info: *const sys::FILE_INFO = buf.as_ptr().cast();
-        unsafe { (*file_info).file_size }
+    /// Return the size of this file, in bytes.
+    pub fn get_size(&self) -> u64 {
+        self.get_info().size
+    }
+
+    /// Delete this file, returning whether the deletion succeeded.
+    ///
+    /// The file is closed even if it couldn't be deleted.
+    pub fn delete(self) -> bool {
+        // `delete` closes the handle, so we must not run our `Drop` impl.
+        let this = ManuallyDrop::new(self);
+        let delete = unsafe { (**this.ptr).delete };
+
+        let status = delete(*this.ptr);
+
+        // SAFETY: `this` is never used again, so the `BsRef` is only dropped once.
+        drop(unsafe { ptr::read(&this.ptr) });
+
+        status == sys::SUCCESS
+    }
+}
+
+impl File {
+    /// Iterate over the entries of this directory.
+    ///
+    /// Iteration starts at the first entry, regardless of previous reads.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this file isn't a directory.
+    pub fn read_dir(&mut self) -> impl Iterator<Item = FileInfo> + '_ {
+        self.seek(0).unwrap();
+        iter::from_fn(move || self.read_dir_entry())
+    }
+
+    fn read_dir_entry(&mut self) -> Option<FileInfo> {
+        let read = unsafe { (**self.ptr).read };
+
+        // Reading a directory returns one `FILE_INFO` per call. Its size depends on the length of
+        // the file name, so we retry with a larger buffer if needed. The buffer is allocated as
+        // `u64`s, to satisfy the alignment requirement of `FILE_INFO`.
+        let mut buf = vec![0_u64; 128];
+        loop {
+            let mut buf_size = buf.len() * mem::size_of::<u64>();
+            let status = read(*self.ptr, &mut buf_size, buf.as_mut_ptr().cast());
+            if status == sys::BUFFER_TOO_SMALL {
+                buf.resize(buf_size.div_ceil(mem::size_of::<u64>()), 0);
+                continue;
+            }
+            assert_eq!(status, sys::SUCCESS);
+
+            // A zero-size read signals the end of the directory.
+            if buf_size == 0 {
+                return None;
+            }
+
+            return Some(unsafe { FileInfo::parse(buf.as_ptr().cast(), buf_size) });
+        }
+    }
+}
+
+/// Information about a file, as returned by [`File::get_info`] and [`File::read_dir`].
+#[derive(Debug)]
+pub struct FileInfo {
+    pub name: alloc::string::String,
+    /// Size of the file, in bytes.
+    pub size: u64,
+    /// File attribute bits (`sys::FILE_*`).
+    pub attribute: u64,
+}
+
+impl FileInfo {
+    /// # Safety
+    ///
+    /// `ptr` must be a valid pointer to a [`sys::FILE_INFO`] of `size` bytes, including the file
+    /// name.
+    unsafe fn parse(ptr: *const sys::FILE_INFO, size: usize) -> Self {
+        validate_ptr(ptr);
+
+        let info = unsafe { &*ptr };
+        let name_offset = mem::offset_of!(sys::FILE_INFO, file_name);
+        let name_len = (size - name_offset) / mem::size_of::<u16>();
+        let name_chars = unsafe { slice::from_raw_parts(info.file_name.as_ptr(), name_len) };
+        let name_chars = name_chars.split(|&c| c == 0).next().unwrap_or_default();
+        let name = char::decode_utf16(name_chars.iter().copied())
+            .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+            .collect();
+
+        Self {
+            name,
+            size: info.file_size,
+            attribute: info.attribute,
+        }
+    }
+
+    pub fn is_directory(&self) -> bool {
+        self.attribute & sys::FILE_DIRECTORY != 0
     }
 }
 
@@ -161,6 +364,31 @@ impl Read for File {
     }
 }
 
+impl Write for File {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, io::Error> {
+        let write = unsafe { (**self.ptr).write };
+
+        let mut buf_size = buf.len();
+        let status = write(*self.ptr, &mut buf_size, buf.as_ptr().cast());
+        if status != sys::SUCCESS {
+            return Err(io::Error::Device);
+        }
+
+        Ok(buf_size)
+    }
+
+    fn flush(&mut self) -> Result<(), io::Error> {
+        let flush = unsafe { (**self.ptr).flush };
+
+        let status = flush(*self.ptr);
+        if status != sys::SUCCESS {
+            return Err(io::Error::Device);
+        }
+
+        Ok(())
+    }
+}
+
 impl Seek for File {
     fn seek(&mut self, pos: u64) -> Result<(), io::Error> {
         let set_position = unsafe { (**self.ptr).set_position };
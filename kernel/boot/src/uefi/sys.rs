@@ -67,7 +67,7 @@ pub struct BOOT_SERVICES {
     pub raise_tpl: *mut c_void,
     pub restore_tpl: *mut c_void,
     pub allocate_pages: ALLOCATE_PAGES,
-    pub free_pages: *mut c_void,
+    pub free_pages: FREE_PAGES,
     pub get_memory_map: GET_MEMORY_MAP,
     pub allocate_pool: ALLOCATE_POOL,
     pub free_pool: FREE_POOL,
@@ -92,7 +92,7 @@ pub struct BOOT_SERVICES {
     pub unload_image: *mut c_void,
     pub exit_boot_services: EXIT_BOOT_SERVICES,
     pub get_next_monotonic_count: *mut c_void,
-    pub stall: *mut c_void,
+    pub stall: STALL,
     pub set_watchdog_timer: *mut c_void,
     pub connect_controller: *mut c_void,
     pub disconnect_controller: *mut c_void,
@@ -101,7 +101,7 @@ pub struct BOOT_SERVICES {
     pub open_protocol_information: *mut c_void,
     pub protocols_per_handle: *mut c_void,
     pub locate_handle_buffer: *mut c_void,
-    pub locate_protocol: *mut c_void,
+    pub locate_protocol: LOCATE_PROTOCOL,
     pub install_multiple_protocol_interfaces: *mut c_void,
     pub uninstall_multiple_protocol_interfaces: *mut c_void,
     pub calculate_crc32: *mut c_void,
@@ -172,6 +172,8 @@ pub type ALLOCATE_PAGES = extern "efiapi" fn(
     buffer: *mut PHYSICAL_ADDRESS,
 ) -> STATUS;
 
+pub type FREE_PAGES = extern "efiapi" fn(memory: PHYSICAL_ADDRESS, pages: usize) -> STATUS;
+
 pub type GET_MEMORY_MAP = extern "efiapi" fn(
     memory_map_size: *mut usize,
     memory_map: *mut c_void,
@@ -194,11 +196,22 @@ pub type HANDLE_PROTOCOL = extern "efiapi" fn(
     interface: *mut *mut c_void,
 ) -> STATUS;
 
+pub type LOCATE_PROTOCOL = extern "efiapi" fn(
+    protocol: *const GUID,
+    registration: *mut c_void,
+    interface: *mut *mut c_void,
+) -> STATUS;
+
 // 7.4 Image Services
 // ------------------
 
 pub type EXIT_BOOT_SERVICES = extern "efiapi" fn(image_handle: HANDLE, map_key: usize) -> STATUS;
 
+// 7.5 Miscellaneous Boot Services
+// -------------------------------
+
+pub type STALL = extern "efiapi" fn(microseconds: usize) -> STATUS;
+
 // 8.3 Time Services
 // -----------------
 
@@ -266,6 +279,70 @@ pub struct SIMPLE_TEXT_OUTPUT_PROTOCOL {
 pub type TEXT_STRING =
     extern "efiapi" fn(this: *mut SIMPLE_TEXT_OUTPUT_PROTOCOL, string: *const u16) -> STATUS;
 
+// 12.9 Graphics Output Protocol
+// -----------------------------
+
+pub const GRAPHICS_OUTPUT_PROTOCOL_GUID: GUID = [
+    0xde, 0xa9, 0x42, 0x90, 0xdc, 0x23, 0x38, 0x4a, 0x96, 0xfb, 0x7a, 0xde, 0xd0, 0x80, 0x51, 0x6a,
+];
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct GRAPHICS_OUTPUT_PROTOCOL {
+    pub query_mode: GRAPHICS_OUTPUT_PROTOCOL_QUERY_MODE,
+    pub set_mode: GRAPHICS_OUTPUT_PROTOCOL_SET_MODE,
+    pub blt: *mut c_void,
+    pub mode: *mut GRAPHICS_OUTPUT_PROTOCOL_MODE,
+}
+
+#[derive(Debug)]
+#[repr(C)]
+pub struct GRAPHICS_OUTPUT_PROTOCOL_MODE {
+    pub max_mode: u32,
+    pub mode: u32,
+    pub info: *mut GRAPHICS_OUTPUT_MODE_INFORMATION,
+    pub size_of_info: usize,
+    pub frame_buffer_base: PHYSICAL_ADDRESS,
+    pub frame_buffer_size: usize,
+}
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct GRAPHICS_OUTPUT_MODE_INFORMATION {
+    pub version: u32,
+    pub horizontal_resolution: u32,
+    pub vertical_resolution: u32,
+    pub pixel_format: GRAPHICS_PIXEL_FORMAT,
+    pub pixel_information: PIXEL_BITMASK,
+    pub pixels_per_scan_line: u32,
+}
+
+pub type GRAPHICS_PIXEL_FORMAT = u32;
+
+pub const PixelRedGreenBlueReserved8BitPerColor: GRAPHICS_PIXEL_FORMAT = 0;
+pub const PixelBlueGreenRedReserved8BitPerColor: GRAPHICS_PIXEL_FORMAT = 1;
+pub const PixelBitMask: GRAPHICS_PIXEL_FORMAT = 2;
+pub const PixelBltOnly: GRAPHICS_PIXEL_FORMAT = 3;
+
+#[derive(Clone, Copy, Debug)]
+#[repr(C)]
+pub struct PIXEL_BITMASK {
+    pub red_mask: u32,
+    pub green_mask: u32,
+    pub blue_mask: u32,
+    pub reserved_mask: u32,
+}
+
+pub type GRAPHICS_OUTPUT_PROTOCOL_QUERY_MODE = extern "efiapi" fn(
+    this: *mut GRAPHICS_OUTPUT_PROTOCOL,
+    mode_number: u32,
+    size_of_info: *mut usize,
+    info: *mut *mut GRAPHICS_OUTPUT_MODE_INFORMATION,
+) -> STATUS;
+
+pub type GRAPHICS_OUTPUT_PROTOCOL_SET_MODE =
+    extern "efiapi" fn(this: *mut GRAPHICS_OUTPUT_PROTOCOL, mode_number: u32) -> STATUS;
+
 // 13.4 Simple File System Protocol
 // --------------------------------
 
@@ -298,17 +375,19 @@ pub struct FILE_PROTOCOL {
     pub revision: u64,
     pub open: FILE_OPEN,
     pub close: FILE_CLOSE,
-    pub delete: *mut c_void,
+    pub delete: FILE_DELETE,
     pub read: FILE_READ,
-    pub write: *mut c_void,
+    pub write: FILE_WRITE,
     pub get_position: FILE_GET_POSITION,
     pub set_position: FILE_SET_POSITION,
     pub get_info: FILE_GET_INFO,
     pub set_info: *mut c_void,
-    pub flush: *mut c_void,
+    pub flush: FILE_FLUSH,
 }
 
 pub const FILE_MODE_READ: u64 = 0x0000000000000001;
+pub const FILE_MODE_WRITE: u64 = 0x0000000000000002;
+pub const FILE_MODE_CREATE: u64 = 0x8000000000000000;
 
 pub type FILE_OPEN = extern "efiapi" fn(
     this: *mut FILE_PROTOCOL,
@@ -320,17 +399,27 @@ pub type FILE_OPEN = extern "efiapi" fn(
 
 pub type FILE_CLOSE = extern "efiapi" fn(this: *mut FILE_PROTOCOL) -> STATUS;
 
+pub type FILE_DELETE = extern "efiapi" fn(this: *mut FILE_PROTOCOL) -> STATUS;
+
 pub type FILE_READ = extern "efiapi" fn(
     this: *mut FILE_PROTOCOL,
     buffer_size: *mut usize,
     buffer: *mut c_void,
 ) -> STATUS;
 
+pub type FILE_WRITE = extern "efiapi" fn(
+    this: *mut FILE_PROTOCOL,
+    buffer_size: *mut usize,
+    buffer: *const c_void,
+) -> STATUS;
+
 pub type FILE_GET_POSITION =
     extern "efiapi" fn(this: *mut FILE_PROTOCOL, position: *mut u64) -> STATUS;
 
 pub type FILE_SET_POSITION = extern "efiapi" fn(this: *mut FILE_PROTOCOL, position: u64) -> STATUS;
 
+pub type FILE_FLUSH = extern "efiapi" fn(this: *mut FILE_PROTOCOL) -> STATUS;
+
 pub type FILE_GET_INFO = extern "efiapi" fn(
     this: *mut FILE_PROTOCOL,
     information_type: *const GUID,
@@ -355,7 +444,10 @@ pub struct FILE_INFO {
     pub file_name: [u16; 0],
 }
 
+pub const FILE_DIRECTORY: u64 = 0x0000000000000010;
+
 // Appendix D
 
 pub const SUCCESS: STATUS = 0;
 pub const BUFFER_TOO_SMALL: STATUS = (1 << 63) | 5;
+pub const NOT_FOUND: STATUS = (1 << 63) | 14;
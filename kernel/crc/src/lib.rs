@@ -4,11 +4,7 @@
 
 /// Calculate the CRC32 checksum for the given data.
 pub fn crc32(data: &[u8]) -> u32 {
-    let mut crc = Crc32::new();
-    for byte in data {
-        crc.update(*byte);
-    }
-    crc.finish()
+    Crc32::digest(data)
 }
 
 /// Incremental CRC32 calculator.
@@ -27,6 +23,20 @@ impl Crc32 {
     pub fn finish(self) -> u32 {
         !self.0
     }
+
+    /// Calculate the CRC32 checksum for the given data.
+    pub fn digest(data: &[u8]) -> u32 {
+        let mut crc = Self::new();
+        for byte in data {
+            crc.update(*byte);
+        }
+        crc.finish()
+    }
+
+    /// Check whether the CRC32 checksum of the given data matches `expected`.
+    pub fn verify(data: &[u8], expected: u32) -> bool {
+        Self::digest(data) == expected
+    }
 }
 
 impl Default for Crc32 {
@@ -89,4 +99,19 @@ mod tests {
             assert_eq!(crc32(input), *expected, "input={input:?}");
         }
     }
+
+    #[test]
+    fn test_digest_verify() {
+        // The standard CRC-32 check value.
+        assert_eq!(Crc32::digest(b"123456789"), 0xcbf43926);
+        assert!(Crc32::verify(b"123456789", 0xcbf43926));
+        assert!(!Crc32::verify(b"123456780", 0xcbf43926));
+
+        // Incremental calculation matches the one-shot digest.
+        let mut crc = Crc32::new();
+        for byte in b"message digest" {
+            crc.update(*byte);
+        }
+        assert_eq!(crc.finish(), Crc32::digest(b"message digest"));
+    }
 }
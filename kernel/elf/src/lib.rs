@@ -11,32 +11,60 @@ use core::mem;
 
 use kstd::io::{Read, Seek};
 
+/// Errors found while validating an ELF file.
+#[derive(Debug)]
+pub enum ElfError {
+    /// A segment's file data extends beyond the end of the file.
+    SegmentOutOfBounds { offset: u64, size: u64 },
+    /// A segment's file size exceeds its memory size.
+    SegmentTooLarge { file_size: u64, memory_size: u64 },
+    /// A segment's alignment is not a power of two, or its address and offset are not congruent
+    /// modulo the alignment.
+    SegmentMisaligned { vaddr: u64, offset: u64, align: u64 },
+}
+
 pub struct ElfFile<R> {
     reader: R,
+    /// Size of the file, in bytes.
+    len: u64,
     header: Ehdr,
 }
 
 impl<R: Read + Seek> ElfFile<R> {
-    pub fn open(mut reader: R) -> Self {
-        let mut buffer = vec![0; mem::size_of::<Ehdr>()];
+    /// Open the ELF file of the given size, in bytes, provided by `reader`.
+    pub fn open(mut reader: R, len: u64) -> Self {
         reader.seek(0).unwrap();
-        reader.read_exact(&mut buffer).unwrap();
+        let buffer = reader.read_exact_vec(mem::size_of::<Ehdr>()).unwrap();
         let header = Ehdr::parse(&buffer);
 
-        Self { reader, header }
+        Self {
+            reader,
+            len,
+            header,
+        }
     }
 
     pub fn entry(&self) -> u64 {
         self.header.entry
     }
 
-    pub fn program_headers(&mut self) -> impl Iterator<Item = Phdr> + '_ {
+    /// Whether this is a position-independent executable, which needs to be relocated.
+    pub fn is_dynamic(&self) -> bool {
+        self.header.type_ == ET_DYN
+    }
+
+    /// Iterate over the program headers.
+    ///
+    /// Each header is validated before it is returned.
+    pub fn program_headers(&mut self) -> impl Iterator<Item = Result<Phdr, ElfError>> + '_ {
         self.reader.seek(self.header.phoff).unwrap();
 
         let mut buffer = vec![0; mem::size_of::<Phdr>()];
         (0..self.header.phnum).map(move |_| {
             self.reader.read_exact(&mut buffer).unwrap();
-            Phdr::parse(&buffer)
+            let phdr = Phdr::parse(&buffer);
+            phdr.validate(self.len)?;
+            Ok(phdr)
         })
     }
 
@@ -50,11 +78,27 @@ impl<R: Read + Seek> ElfFile<R> {
         })
     }
 
+    /// Read the contents of the given segment into `buffer`.
+    ///
+    /// The part of the segment that isn't backed by file data (e.g. `.bss`) is zeroed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buffer` is smaller than the segment's memory size.
     pub fn read_segment(&mut self, phdr: &Phdr, buffer: &mut [u8]) {
-        let buffer = &mut buffer[..phdr.filesz as usize];
+        assert!(
+            buffer.len() as u64 >= phdr.memsz,
+            "segment buffer too small: {} < {}",
+            buffer.len(),
+            phdr.memsz,
+        );
+
+        let (file_part, zero_part) =
+            buffer[..phdr.memsz as usize].split_at_mut(phdr.filesz as usize);
 
         self.reader.seek(phdr.offset).unwrap();
-        self.reader.read_exact(buffer).unwrap();
+        self.reader.read_exact(file_part).unwrap();
+        zero_part.fill(0);
     }
 
     pub fn read_section(&mut self, shdr: &Shdr, buffer: &mut [u8]) {
@@ -91,11 +135,50 @@ impl<R: Read + Seek> ElfFile<R> {
         let sh_strtab = self.section_headers().nth(strtab_idx)?;
         assert_eq!(sh_strtab.type_, SHT_STRTAB);
 
-        let mut strtab = vec![0; sh_strtab.size as usize];
-        self.read_section(&sh_strtab, &mut strtab);
+        self.reader.seek(sh_strtab.offset).unwrap();
+        let strtab = self.reader.read_exact_vec(sh_strtab.size as usize).unwrap();
+
+        Some(strtab)
+    }
+
+    /// Iterate over the entries of the dynamic relocation section, if any.
+    pub fn dynamic_relocations(&mut self) -> Option<impl Iterator<Item = Rela> + '_> {
+        let sh_rela = self.section_headers().find(|sh| sh.type_ == SHT_RELA)?;
+        assert_eq!(sh_rela.entsize as usize, mem::size_of::<Rela>());
+        let num_relocations = sh_rela.size / sh_rela.entsize;
+
+        self.reader.seek(sh_rela.offset).unwrap();
+
+        let mut buffer = vec![0; mem::size_of::<Rela>()];
+        let iter = (0..num_relocations).map(move |_| {
+            self.reader.read_exact(&mut buffer).unwrap();
+            Rela::parse(&buffer)
+        });
+
+        Some(iter)
+    }
+
+    /// Read the section header string table, which contains the section names.
+    pub fn section_strtab(&mut self) -> Option<Vec<u8>> {
+        let strtab_idx = usize::from(self.header.shstrndx);
+        if strtab_idx == SHN_UNDEF {
+            return None;
+        }
+
+        let sh_strtab = self.section_headers().nth(strtab_idx)?;
+        assert_eq!(sh_strtab.type_, SHT_STRTAB);
+
+        self.reader.seek(sh_strtab.offset).unwrap();
+        let strtab = self.reader.read_exact_vec(sh_strtab.size as usize).unwrap();
 
         Some(strtab)
     }
+
+    /// Find the section with the given name.
+    pub fn section_by_name(&mut self, name: &CStr) -> Option<Shdr> {
+        let strtab = self.section_strtab()?;
+        self.section_headers().find(|sh| sh.name(&strtab) == name)
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -133,7 +216,11 @@ impl Ehdr {
         let header = unsafe { (*ptr).clone() };
         assert_eq!(&header.ident[..4], b"\x7fELF");
         assert_eq!(header.ident[4], ELFCLASS64);
-        assert_eq!(header.type_, ET_EXEC);
+        assert!(
+            header.type_ == ET_EXEC || header.type_ == ET_DYN,
+            "unsupported ELF type: {}",
+            header.type_,
+        );
         assert_eq!(header.machine, EM_AARCH64);
         assert_eq!(usize::from(header.ehsize), mem::size_of::<Ehdr>());
         assert_eq!(usize::from(header.phentsize), mem::size_of::<Phdr>());
@@ -145,6 +232,7 @@ impl Ehdr {
 
 const ELFCLASS64: u8 = 2;
 const ET_EXEC: u16 = 2;
+const ET_DYN: u16 = 3;
 const EM_AARCH64: u16 = 183;
 
 #[derive(Clone, Debug)]
@@ -175,6 +263,40 @@ impl Phdr {
         unsafe { (*ptr).clone() }
     }
 
+    /// Check that the segment lies within a file of the given size, and is consistently aligned.
+    pub fn validate(&self, file_len: u64) -> Result<(), ElfError> {
+        let end = self.offset.checked_add(self.filesz);
+        if end.is_none_or(|end| end > file_len) {
+            return Err(ElfError::SegmentOutOfBounds {
+                offset: self.offset,
+                size: self.filesz,
+            });
+        }
+
+        if self.filesz > self.memsz {
+            return Err(ElfError::SegmentTooLarge {
+                file_size: self.filesz,
+                memory_size: self.memsz,
+            });
+        }
+
+        // An alignment of 0 or 1 means no alignment constraint.
+        let misaligned = match self.align {
+            0 | 1 => false,
+            align if !align.is_power_of_two() => true,
+            align => self.vaddr % align != self.offset % align,
+        };
+        if misaligned {
+            return Err(ElfError::SegmentMisaligned {
+                vaddr: self.vaddr,
+                offset: self.offset,
+                align: self.align,
+            });
+        }
+
+        Ok(())
+    }
+
     pub fn is_load(&self) -> bool {
         self.type_ == PT_LOAD
     }
@@ -238,10 +360,31 @@ impl Shdr {
     pub fn is_strtab(&self) -> bool {
         self.type_ == SHT_STRTAB
     }
+
+    /// Extract the section's name from the given section header `strtab`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the section's name is not contained in the given `strtab`.
+    pub fn name<'a>(&self, strtab: &'a [u8]) -> &'a CStr {
+        let idx = self.name as usize;
+        CStr::from_bytes_until_nul(&strtab[idx..]).unwrap()
+    }
+
+    pub fn address(&self) -> u64 {
+        self.addr
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
 }
 
+const SHN_UNDEF: usize = 0;
+
 const SHT_SYMTAB: u32 = 2;
 const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
 
 #[derive(Clone, Debug)]
 #[repr(C)]
@@ -282,4 +425,325 @@ impl Sym {
     pub fn value(&self) -> u64 {
         self.value
     }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Whether the symbol names a function or other executable code.
+    pub fn is_function(&self) -> bool {
+        self.info & 0xf == STT_FUNC
+    }
+
+    /// Whether the symbol names a data object, like a variable or an array.
+    pub fn is_object(&self) -> bool {
+        self.info & 0xf == STT_OBJECT
+    }
+}
+
+const STT_OBJECT: u8 = 1;
+const STT_FUNC: u8 = 2;
+
+/// Relocation that adds the load bias to the addend.
+pub const R_AARCH64_RELATIVE: u32 = 1027;
+
+#[derive(Clone, Debug)]
+#[repr(C)]
+pub struct Rela {
+    offset: u64,
+    info: u64,
+    addend: i64,
+}
+
+impl Rela {
+    /// Parse the given raw data as a [`Rela`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if `data` has the wrong size or alignment.
+    fn parse(data: &[u8]) -> Self {
+        assert_eq!(data.len(), mem::size_of::<Self>());
+
+        let ptr: *const Self = data.as_ptr().cast();
+        assert!(ptr.is_aligned());
+
+        unsafe { (*ptr).clone() }
+    }
+
+    /// Return the virtual address the relocation applies to.
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn addend(&self) -> i64 {
+        self.addend
+    }
+
+    pub fn r_type(&self) -> u32 {
+        self.info as u32
+    }
+
+    pub fn r_sym(&self) -> u32 {
+        (self.info >> 32) as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::slice;
+
+    use kstd::io::Cursor;
+
+    use super::*;
+
+    const SHT_PROGBITS: u32 = 1;
+
+    fn as_bytes<T>(value: &T) -> &[u8] {
+        let ptr: *const u8 = (value as *const T).cast();
+        unsafe { slice::from_raw_parts(ptr, mem::size_of::<T>()) }
+    }
+
+    fn align_to_8(data: &mut Vec<u8>) {
+        data.resize(data.len().next_multiple_of(8), 0);
+    }
+
+    /// Build a minimal ELF file containing the given `(name, type, contents)` sections, followed
+    /// by a section header string table.
+    fn build_elf(sections: &[(&str, u32, &[u8])]) -> ElfFile<Cursor<Vec<u8>>> {
+        let mut shstrtab = vec![0];
+        let mut name_offsets = Vec::new();
+        for name in sections.iter().map(|(name, ..)| *name).chain([".shstrtab"]) {
+            name_offsets.push(shstrtab.len() as u32);
+            shstrtab.extend(name.bytes().chain([0]));
+        }
+
+        let contents = sections
+            .iter()
+            .map(|(_, type_, contents)| (*type_, *contents))
+            .chain([(SHT_STRTAB, &shstrtab[..])]);
+
+        let mut data = vec![0; mem::size_of::<Ehdr>()];
+        let null_shdr = Shdr {
+            name: 0,
+            type_: 0,
+            flags: 0,
+            addr: 0,
+            offset: 0,
+            size: 0,
+            link: 0,
+            info: 0,
+            addralign: 0,
+            entsize: 0,
+        };
+        let mut shdrs = vec![null_shdr.clone()];
+        for ((type_, contents), name) in contents.zip(name_offsets) {
+            shdrs.push(Shdr {
+                name,
+                type_,
+                addr: 0x1000 * shdrs.len() as u64,
+                offset: data.len() as u64,
+                size: contents.len() as u64,
+                addralign: 1,
+                entsize: match type_ {
+                    SHT_RELA => mem::size_of::<Rela>() as u64,
+                    _ => 0,
+                },
+                ..null_shdr.clone()
+            });
+            data.extend(contents);
+        }
+
+        align_to_8(&mut data);
+        let shoff = data.len() as u64;
+        for shdr in &shdrs {
+            data.extend(as_bytes(shdr));
+        }
+
+        let mut ident = [0; 16];
+        ident[..4].copy_from_slice(b"\x7fELF");
+        ident[4] = ELFCLASS64;
+        let ehdr = Ehdr {
+            ident,
+            type_: ET_EXEC,
+            machine: EM_AARCH64,
+            version: 1,
+            entry: 0x1000,
+            phoff: 0,
+            shoff,
+            flags: 0,
+            ehsize: mem::size_of::<Ehdr>() as u16,
+            phentsize: mem::size_of::<Phdr>() as u16,
+            phnum: 0,
+            shentsize: mem::size_of::<Shdr>() as u16,
+            shnum: shdrs.len() as u16,
+            shstrndx: (shdrs.len() - 1) as u16,
+        };
+        data[..mem::size_of::<Ehdr>()].copy_from_slice(as_bytes(&ehdr));
+
+        let len = data.len() as u64;
+        ElfFile::open(Cursor::new(data), len)
+    }
+
+    #[test]
+    fn test_section_names() {
+        let mut elf = build_elf(&[
+            (".text", SHT_PROGBITS, &[1, 2, 3, 4]),
+            (".rodata", SHT_PROGBITS, &[5, 6]),
+        ]);
+
+        let strtab = elf.section_strtab().unwrap();
+        let names: Vec<_> = elf.section_headers().map(|sh| sh.name(&strtab)).collect();
+        assert_eq!(names, [c"", c".text", c".rodata", c".shstrtab"]);
+
+        let rodata = elf.section_by_name(c".rodata").unwrap();
+        assert_eq!(rodata.address(), 0x2000);
+        assert_eq!(rodata.size(), 2);
+
+        let mut buf = [0; 2];
+        elf.read_section(&rodata, &mut buf);
+        assert_eq!(buf, [5, 6]);
+
+        assert!(elf.section_by_name(c".data").is_none());
+    }
+
+    #[test]
+    fn test_read_segment_zeroes_bss() {
+        let mut elf = build_elf(&[(".data", SHT_PROGBITS, &[1, 2, 3, 4])]);
+        let data = elf.section_by_name(c".data").unwrap();
+
+        let phdr = Phdr {
+            type_: PT_LOAD,
+            flags: PF_W,
+            offset: data.offset,
+            vaddr: 0x1000,
+            paddr: 0x1000,
+            filesz: 4,
+            memsz: 10,
+            align: 0x1000,
+        };
+
+        // Bytes beyond the segment's memory size are left alone.
+        let mut buf = [0xff; 12];
+        elf.read_segment(&phdr, &mut buf);
+        assert_eq!(buf, [1, 2, 3, 4, 0, 0, 0, 0, 0, 0, 0xff, 0xff]);
+    }
+
+    #[test]
+    fn test_validate_phdr() {
+        let phdr = Phdr {
+            type_: PT_LOAD,
+            flags: 0,
+            offset: 0x1010,
+            vaddr: 0x40_1010,
+            paddr: 0,
+            filesz: 0x100,
+            memsz: 0x200,
+            align: 0x1000,
+        };
+        assert!(phdr.validate(0x1110).is_ok());
+
+        assert!(matches!(
+            phdr.validate(0x110f),
+            Err(ElfError::SegmentOutOfBounds { .. })
+        ));
+
+        let overflowing = Phdr {
+            offset: u64::MAX,
+            ..phdr.clone()
+        };
+        assert!(matches!(
+            overflowing.validate(u64::MAX),
+            Err(ElfError::SegmentOutOfBounds { .. })
+        ));
+
+        let too_large = Phdr {
+            memsz: 0x80,
+            ..phdr.clone()
+        };
+        assert!(matches!(
+            too_large.validate(0x2000),
+            Err(ElfError::SegmentTooLarge { .. })
+        ));
+
+        let misaligned = Phdr {
+            vaddr: 0x40_1000,
+            ..phdr.clone()
+        };
+        assert!(matches!(
+            misaligned.validate(0x2000),
+            Err(ElfError::SegmentMisaligned { .. })
+        ));
+
+        let bad_align = Phdr {
+            align: 0x1001,
+            ..phdr.clone()
+        };
+        assert!(matches!(
+            bad_align.validate(0x2000),
+            Err(ElfError::SegmentMisaligned { .. })
+        ));
+    }
+
+    #[test]
+    fn test_dynamic_relocations() {
+        let relas = [
+            Rela {
+                offset: 0x1000,
+                info: u64::from(R_AARCH64_RELATIVE),
+                addend: 0x2000,
+            },
+            Rela {
+                offset: 0x1008,
+                info: 5 << 32 | 257,
+                addend: -8,
+            },
+        ];
+        let mut contents = Vec::new();
+        for rela in &relas {
+            contents.extend(as_bytes(rela));
+        }
+
+        let mut elf = build_elf(&[(".text", SHT_PROGBITS, &[0; 16])]);
+        assert!(elf.dynamic_relocations().is_none());
+
+        let mut elf = build_elf(&[(".rela.dyn", SHT_RELA, &contents)]);
+        let parsed: Vec<_> = elf.dynamic_relocations().unwrap().collect();
+        assert_eq!(parsed.len(), 2);
+
+        assert_eq!(parsed[0].offset(), 0x1000);
+        assert_eq!(parsed[0].r_type(), R_AARCH64_RELATIVE);
+        assert_eq!(parsed[0].r_sym(), 0);
+        assert_eq!(parsed[0].addend(), 0x2000);
+
+        assert_eq!(parsed[1].offset(), 0x1008);
+        assert_eq!(parsed[1].r_type(), 257);
+        assert_eq!(parsed[1].r_sym(), 5);
+        assert_eq!(parsed[1].addend(), -8);
+    }
+
+    #[test]
+    fn test_symbol_types() {
+        let sym = |info| Sym {
+            name: 0,
+            info,
+            other: 0,
+            shndx: 1,
+            value: 0x1000,
+            size: 8,
+        };
+
+        // The binding lives in the upper nibble and doesn't affect the type.
+        let global_func = sym(1 << 4 | STT_FUNC);
+        assert!(global_func.is_function());
+        assert!(!global_func.is_object());
+
+        let local_object = sym(STT_OBJECT);
+        assert!(local_object.is_object());
+        assert!(!local_object.is_function());
+
+        let section = sym(3);
+        assert!(!section.is_function());
+        assert!(!section.is_object());
+        assert_eq!(section.size(), 8);
+    }
 }
@@ -3,6 +3,8 @@
 
 use core::panic::PanicInfo;
 
+use aarch64::debug::Registers;
+
 use kernel::log;
 
 /// # Safety
@@ -15,10 +17,15 @@ pub unsafe fn _start(bootinfo: boot_info::ffi::BootInfo) -> ! {
 
 #[panic_handler]
 fn panic(panic: &PanicInfo<'_>) -> ! {
+    let regs = Registers::capture();
+
     log!("PANIC: {}", panic.message());
     if let Some(loc) = panic.location() {
         log!("  in file '{}' at line {}", loc.file(), loc.line());
     }
 
+    log!("registers:\n{regs}");
+    kernel::log::backtrace();
+
     aarch64::halt();
 }
@@ -3,18 +3,35 @@
 mod syscall;
 
 use core::arch::global_asm;
+use core::fmt;
 
 use aarch64::instruction::isb;
-use aarch64::register::{ESR_EL1, FAR_EL1, VBAR_EL1};
+use aarch64::memory::VA;
+use aarch64::register::{ESR_EL1, EsrDecoded, FAR_EL1, FaultStatus, VBAR_EL1};
 
-use crate::log;
+use crate::memory::virt::{self, KSTACK_GUARD_SIZE};
+use crate::{interrupt, log, sched};
 
 unsafe extern "C" {
     #[link_name = "exception_vectors"]
     static EXCEPTION_VECTORS: u8;
+    #[link_name = "_kstack_overflow_end"]
+    static KSTACK_OVERFLOW_END: u8;
 }
 
-global_asm!(include_str!("vector.S"));
+/// Size of the stack exception handlers switch to after a kernel stack overflow.
+const KSTACK_OVERFLOW_SIZE: usize = 8 << 10;
+
+global_asm!(
+    include_str!("vector.S"),
+    frame_size = const size_of::<ExceptionStack>(),
+    current_stack = sym sched::CURRENT_STACK,
+    overflow_stack_size = const KSTACK_OVERFLOW_SIZE,
+);
+
+// Interrupt mask bits in the saved SPSR.
+const SPSR_I: u64 = 1 << 7;
+const SPSR_F: u64 = 1 << 6;
 
 /// Initialize exception handling.
 pub fn init() {
@@ -52,27 +69,89 @@ pub(super) struct ExceptionStack {
     x16: u64,
     x17: u64,
     x18: u64,
+    x19: u64,
+    x20: u64,
+    x21: u64,
+    x22: u64,
+    x23: u64,
+    x24: u64,
+    x25: u64,
+    x26: u64,
+    x27: u64,
+    x28: u64,
+    x29: u64,
     x30: u64,
+    sp_el0: u64,
 }
 
+// The register offsets in `vector.S` depend on this layout.
+const _: () = assert!(size_of::<ExceptionStack>() == 34 * 8);
+
 #[unsafe(no_mangle)]
 pub extern "C" fn handle_unhandled(stack: &mut ExceptionStack) {
     let esr = ESR_EL1::read();
     let far = FAR_EL1::read();
 
+    // Masked interrupts indicate that the exception was taken inside a critical section.
+    let spsr = stack.spsr;
+    let irq_masked = spsr & SPSR_I != 0;
+    let fiq_masked = spsr & SPSR_F != 0;
+
+    let syndrome = Syndrome { esr, far };
+
     panic!(
-        "unhandled exception\n\
+        "unhandled exception: {syndrome}\n\
          ESR = {esr:#?}\n\
          FAR = {far:#?}\n\
+         interrupts masked: IRQ={irq_masked}, FIQ={fiq_masked}\n\
          stack = {stack:#018x?}"
     );
 }
 
+/// A decoded exception syndrome, with the fault address if it is valid.
+struct Syndrome {
+    esr: ESR_EL1,
+    far: FAR_EL1,
+}
+
+impl fmt::Display for Syndrome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let decoded = self.esr.decode();
+        write!(f, "{decoded}")?;
+
+        let far_valid = match decoded {
+            EsrDecoded::InstructionAbort { abort, .. } | EsrDecoded::DataAbort { abort, .. } => {
+                abort.far_valid
+            }
+            EsrDecoded::PcAlignment => true,
+            _ => false,
+        };
+        if far_valid {
+            write!(f, ", FAR={:#018x}", self.far.VA())?;
+        }
+        Ok(())
+    }
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn handle_exception_el1(stack: &mut ExceptionStack) {
     let esr = ESR_EL1::read();
 
+    if is_kstack_overflow(stack, esr.EC()) {
+        let syndrome = Syndrome {
+            esr,
+            far: FAR_EL1::read(),
+        };
+        let elr = stack.elr;
+        panic!(
+            "kernel stack overflow\n\
+             {syndrome}\n\
+             ELR = {elr:#018x}"
+        );
+    }
+
     match esr.EC() {
+        0x25 => data_abort_el1(stack, esr),
         0x3c => breakpoint(stack),
         ec => {
             log!("unhandled exception from EL1 (EC={ec})");
@@ -81,6 +160,45 @@ pub extern "C" fn handle_exception_el1(stack: &mut ExceptionStack) {
     }
 }
 
+/// Check whether an EL1 exception was caused by a kernel stack overflow.
+///
+/// That is the case if the exception vector had to switch to the overflow stack, or if a data
+/// abort hit the guard page below the stack of the running task.
+fn is_kstack_overflow(stack: &ExceptionStack, ec: u64) -> bool {
+    let overflow_end = &raw const KSTACK_OVERFLOW_END as u64;
+    let overflow_start = overflow_end - KSTACK_OVERFLOW_SIZE as u64;
+    let frame = stack as *const ExceptionStack as u64;
+    if (overflow_start..overflow_end).contains(&frame) {
+        return true;
+    }
+
+    let guard_end = sched::current_stack().start;
+    let guard_start = guard_end - KSTACK_GUARD_SIZE as u64;
+    let far = FAR_EL1::read().VA();
+    ec == 0x25 && (guard_start..guard_end).contains(&far)
+}
+
+/// Handle a data abort taken from EL1.
+///
+/// Write permission faults on copy-on-write pages are resolved, and the faulting instruction is
+/// retried. All other aborts are fatal.
+fn data_abort_el1(stack: &mut ExceptionStack, esr: ESR_EL1) {
+    if let EsrDecoded::DataAbort {
+        abort, write: true, ..
+    } = esr.decode()
+        && let FaultStatus::Permission { .. } = abort.fault
+        && abort.far_valid
+    {
+        let va = VA::new(FAR_EL1::read().VA());
+        if virt::handle_cow_fault(va) {
+            return;
+        }
+    }
+
+    log!("unhandled data abort from EL1");
+    handle_unhandled(stack);
+}
+
 #[unsafe(no_mangle)]
 pub extern "C" fn handle_exception_el0(stack: &mut ExceptionStack) {
     let esr = ESR_EL1::read();
@@ -95,17 +213,33 @@ pub extern "C" fn handle_exception_el0(stack: &mut ExceptionStack) {
     }
 }
 
+#[unsafe(no_mangle)]
+pub extern "C" fn handle_irq(_stack: &mut ExceptionStack) {
+    interrupt::dispatch();
+}
+
 fn breakpoint(stack: &mut ExceptionStack) {
     log!("skipping breakpoint");
     stack.elr += 4;
 }
 
-fn svc(stack: &ExceptionStack) {
+/// Dispatch a syscall.
+///
+/// The syscall number is passed in the SVC immediate, arguments in x0-x5. The return value is
+/// written back to x0, with errors returned as negated error numbers.
+fn svc(stack: &mut ExceptionStack) {
     let esr = ESR_EL1::read();
     let syscall_nr = esr.ISS() & 0xffff;
 
     match syscall_nr {
         0 => syscall::print(stack),
-        _ => panic!("invalid syscall nr: {syscall_nr}"),
+        1 => syscall::sbrk(stack),
+        2 => syscall::mmap(stack),
+        3 => syscall::exit(stack),
+        4 => syscall::yield_(stack),
+        _ => {
+            log!("unknown syscall nr: {syscall_nr}");
+            syscall::set_error(stack, syscall::ENOSYS);
+        }
     }
 }
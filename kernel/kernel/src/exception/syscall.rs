@@ -1,28 +1,110 @@
+use alloc::string::String;
 use alloc::vec;
 use alloc::vec::Vec;
-use core::{ptr, str};
+use core::ptr;
+
+use aarch64::halt;
+use aarch64::memory::{PAGE_SIZE, VA, user_va_to_pa};
 
 use crate::exception::ExceptionStack;
-use crate::log;
-use crate::memory::virt::KERNEL_START;
+use crate::process::{self, MmapError, USER_END};
+use crate::{log, sched};
+
+/// Error number for requests that exceed the available memory or address space.
+pub(super) const ENOMEM: u64 = 12;
+/// Error number for invalid userspace pointers.
+pub(super) const EFAULT: u64 = 14;
+/// Error number for invalid arguments.
+pub(super) const EINVAL: u64 = 22;
+/// Error number for unknown syscalls.
+pub(super) const ENOSYS: u64 = 38;
 
-pub(super) fn print(stack: &ExceptionStack) {
+/// Return the error `errno` from a syscall.
+pub(super) fn set_error(stack: &mut ExceptionStack, errno: u64) {
+    stack.x0 = errno.wrapping_neg();
+}
+
+pub(super) fn print(stack: &mut ExceptionStack) {
     let ptr = stack.x0 as *const u8;
     let len = stack.x1 as usize;
 
-    let bytes = copy_from_user(ptr, len);
-    let s = str::from_utf8(&bytes).unwrap();
+    let Some(bytes) = copy_from_user(ptr, len) else {
+        set_error(stack, EFAULT);
+        return;
+    };
+    // Userspace may pass arbitrary bytes, so replace invalid UTF-8 rather than rejecting it.
+    let s = String::from_utf8_lossy(&bytes);
 
     log::log_args(format_args!("{s}"), "user");
+    stack.x0 = 0;
+}
+
+pub(super) fn sbrk(stack: &mut ExceptionStack) {
+    let increment = stack.x0 as isize;
+
+    match process::with_current(|proc| proc.sbrk(increment)) {
+        Some(old_break) => stack.x0 = old_break.into_u64(),
+        None => set_error(stack, ENOMEM),
+    }
+}
+
+pub(super) fn mmap(stack: &mut ExceptionStack) {
+    let hint = VA::new(stack.x0);
+    let len = stack.x1 as usize;
+    let prot = stack.x2 as u32;
+
+    match process::with_current(|proc| proc.mmap(hint, len, prot)) {
+        Ok(start) => stack.x0 = start.into_u64(),
+        Err(MmapError::InvalidArgument) => set_error(stack, EINVAL),
+        Err(MmapError::NoSpace) => set_error(stack, ENOMEM),
+    }
+}
+
+pub(super) fn yield_(stack: &mut ExceptionStack) {
+    sched::yield_now();
+    stack.x0 = 0;
+}
+
+pub(super) fn exit(stack: &ExceptionStack) -> ! {
+    let code = stack.x0 as i32;
+    log!("user process exited with code {code}");
+
+    // There is no other process to switch to yet.
+    halt();
 }
 
 /// Copy user memory into kernel space.
-fn copy_from_user(ptr: *const u8, len: usize) -> Vec<u8> {
-    let end = (ptr as u64).checked_add(len as u64).unwrap();
-    assert!(end < KERNEL_START.into());
+///
+/// Returns `None` if the range isn't entirely readable by userspace.
+fn copy_from_user(ptr: *const u8, len: usize) -> Option<Vec<u8>> {
+    if !user_readable(VA::new(ptr as u64), len) {
+        return None;
+    }
 
     let mut buf = vec![0; len];
+    // SAFETY: The source range is mapped, and we hold no references to userspace memory.
     unsafe { ptr::copy_nonoverlapping(ptr, buf.as_mut_ptr(), len) };
 
-    buf
+    Some(buf)
+}
+
+/// Check that `[start, start + len)` lies within the userspace range and that all its pages are
+/// mapped readable for EL0.
+fn user_readable(start: VA, len: usize) -> bool {
+    let Some(end) = start.into_u64().checked_add(len as u64) else {
+        return false;
+    };
+    if end > USER_END.into_u64() {
+        return false;
+    }
+
+    let mut page = start.into_u64() & !(PAGE_SIZE as u64 - 1);
+    while page < end {
+        if user_va_to_pa(VA::new(page)).is_none() {
+            return false;
+        }
+        page += PAGE_SIZE as u64;
+    }
+
+    true
 }
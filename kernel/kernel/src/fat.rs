@@ -0,0 +1,364 @@
+//! Read-only FAT32 filesystem driver.
+//!
+//! Only 8.3 file names are supported. Long file name entries are skipped, so files must be looked
+//! up by their short names.
+
+use alloc::vec;
+use alloc::vec::Vec;
+
+use kstd::block::BlockDevice;
+use kstd::io;
+
+const BOOT_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const GPT_SIGNATURE: [u8; 8] = *b"EFI PART";
+const MBR_TYPE_GPT: u8 = 0xee;
+
+const DIR_ENTRY_SIZE: usize = 32;
+const ATTR_VOLUME_ID: u8 = 0x08;
+const ATTR_DIRECTORY: u8 = 0x10;
+const ATTR_LONG_NAME: u8 = 0x0f;
+const ENTRY_FREE: u8 = 0xe5;
+const ENTRY_END: u8 = 0x00;
+
+const CLUSTER_MASK: u32 = 0x0fff_ffff;
+const CLUSTER_MIN: u32 = 2;
+const CLUSTER_EOC: u32 = 0x0fff_fff8;
+
+#[derive(Debug)]
+pub enum Error {
+    /// The device doesn't contain a FAT32 volume.
+    NotFat32,
+    /// A path component doesn't exist.
+    NotFound,
+    /// A non-final path component is not a directory.
+    NotADirectory,
+    /// The path refers to a directory.
+    IsADirectory,
+    /// The filesystem structures are inconsistent.
+    Corrupt,
+    /// The filesystem structures point beyond the end of the device.
+    OutOfBounds,
+}
+
+/// Open the file at the given path on a FAT32 volume.
+///
+/// The volume is expected either at the start of the device, or in the device's first MBR or GPT
+/// partition.
+pub fn open<'a, D: BlockDevice>(dev: &'a D, path: &str) -> Result<File<'a, D>, Error> {
+    let volume = Volume::mount(dev)?;
+
+    let mut dir = volume.root_cluster;
+    let mut components = path.split('/').filter(|c| !c.is_empty()).peekable();
+    while let Some(name) = components.next() {
+        let entry = volume.find_entry(dev, dir, name)?;
+        let is_last = components.peek().is_none();
+
+        match (entry.is_dir, is_last) {
+            (true, false) => dir = entry.cluster,
+            (false, true) => {
+                return Ok(File {
+                    dev,
+                    volume,
+                    first_cluster: entry.cluster,
+                    size: entry.size,
+                    pos: 0,
+                    cached: None,
+                    buf: Vec::new(),
+                });
+            }
+            (true, true) => return Err(Error::IsADirectory),
+            (false, false) => return Err(Error::NotADirectory),
+        }
+    }
+
+    Err(Error::IsADirectory)
+}
+
+/// Geometry of a mounted FAT32 volume.
+///
+/// All sector numbers are in units of device blocks.
+#[derive(Clone, Copy, Debug)]
+struct Volume {
+    /// Size of a device block, in bytes.
+    block_size: usize,
+    /// Size of a cluster, in device blocks.
+    cluster_sectors: u64,
+    /// First sector of the first FAT.
+    fat_start: u64,
+    /// First sector of the data region.
+    data_start: u64,
+    root_cluster: u32,
+}
+
+impl Volume {
+    fn mount<D: BlockDevice>(dev: &D) -> Result<Self, Error> {
+        let block_size = dev.block_size();
+        let start = find_volume_start(dev)?;
+
+        let mut bpb = vec![0; block_size];
+        read_blocks(dev, start, &mut bpb)?;
+
+        if !is_fat32_bpb(&bpb) {
+            return Err(Error::NotFat32);
+        }
+
+        let bytes_per_sector = usize::from(read_u16(&bpb, 11));
+        let sectors_per_cluster = u64::from(bpb[13]);
+        let reserved_sectors = u64::from(read_u16(&bpb, 14));
+        let num_fats = u64::from(bpb[16]);
+        let fat_size = u64::from(read_u32(&bpb, 36));
+        let root_cluster = read_u32(&bpb, 44);
+
+        if bytes_per_sector < block_size
+            || !bytes_per_sector.is_power_of_two()
+            || !sectors_per_cluster.is_power_of_two()
+        {
+            return Err(Error::NotFat32);
+        }
+
+        // Convert filesystem sectors to device blocks.
+        let ratio = (bytes_per_sector / block_size) as u64;
+        let fat_start = start + reserved_sectors * ratio;
+        let data_start = fat_start + num_fats * fat_size * ratio;
+
+        Ok(Self {
+            block_size,
+            cluster_sectors: sectors_per_cluster * ratio,
+            fat_start,
+            data_start,
+            root_cluster,
+        })
+    }
+
+    fn cluster_size(&self) -> usize {
+        self.cluster_sectors as usize * self.block_size
+    }
+
+    fn read_cluster<D: BlockDevice>(
+        &self,
+        dev: &D,
+        cluster: u32,
+        buf: &mut [u8],
+    ) -> Result<(), Error> {
+        let sector = self.data_start + u64::from(cluster - CLUSTER_MIN) * self.cluster_sectors;
+        read_blocks(dev, sector, buf)
+    }
+
+    /// Return the cluster following `cluster` in its chain, or `None` at the end of the chain.
+    fn next_cluster<D: BlockDevice>(&self, dev: &D, cluster: u32) -> Result<Option<u32>, Error> {
+        let offset = u64::from(cluster) * 4;
+        let block_size = self.block_size as u64;
+        let sector = self.fat_start + offset / block_size;
+
+        let mut buf = vec![0; self.block_size];
+        read_blocks(dev, sector, &mut buf)?;
+
+        let next = read_u32(&buf, (offset % block_size) as usize) & CLUSTER_MASK;
+        match next {
+            CLUSTER_EOC.. => Ok(None),
+            CLUSTER_MIN.. => Ok(Some(next)),
+            _ => Err(Error::Corrupt),
+        }
+    }
+
+    /// Look up the entry with the given name in the directory starting at `dir`.
+    fn find_entry<D: BlockDevice>(&self, dev: &D, dir: u32, name: &str) -> Result<DirEntry, Error> {
+        let short_name = to_short_name(name).ok_or(Error::NotFound)?;
+
+        let mut buf = vec![0; self.cluster_size()];
+        let mut cluster = Some(dir);
+        while let Some(c) = cluster {
+            if c < CLUSTER_MIN {
+                return Err(Error::Corrupt);
+            }
+
+            self.read_cluster(dev, c, &mut buf)?;
+
+            for raw in buf.as_chunks::<DIR_ENTRY_SIZE>().0 {
+                let attr = raw[11];
+                match raw[0] {
+                    ENTRY_END => return Err(Error::NotFound),
+                    ENTRY_FREE => continue,
+                    _ if attr == ATTR_LONG_NAME || attr & ATTR_VOLUME_ID != 0 => continue,
+                    _ if raw[..11] != short_name => continue,
+                    _ => (),
+                }
+
+                let cluster_hi = u32::from(read_u16(raw, 20));
+                let cluster_lo = u32::from(read_u16(raw, 26));
+                return Ok(DirEntry {
+                    cluster: cluster_hi << 16 | cluster_lo,
+                    size: u64::from(read_u32(raw, 28)),
+                    is_dir: attr & ATTR_DIRECTORY != 0,
+                });
+            }
+
+            cluster = self.next_cluster(dev, c)?;
+        }
+
+        Err(Error::NotFound)
+    }
+}
+
+struct DirEntry {
+    cluster: u32,
+    size: u64,
+    is_dir: bool,
+}
+
+/// A file opened on a FAT32 volume.
+pub struct File<'a, D> {
+    dev: &'a D,
+    volume: Volume,
+    first_cluster: u32,
+    size: u64,
+    pos: u64,
+    /// Index in the cluster chain and number of the cluster currently held in `buf`.
+    cached: Option<(u64, u32)>,
+    buf: Vec<u8>,
+}
+
+impl<D: BlockDevice> File<'_, D> {
+    /// Return the file size, in bytes.
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Load the cluster with the given index in the file's cluster chain into `buf`.
+    fn load_cluster(&mut self, index: u64) -> Result<(), Error> {
+        let (mut i, mut cluster) = match self.cached {
+            Some((i, _)) if i == index => return Ok(()),
+            Some((i, c)) if i < index => (i, c),
+            _ => (0, self.first_cluster),
+        };
+
+        while i < index {
+            cluster = self
+                .volume
+                .next_cluster(self.dev, cluster)?
+                .ok_or(Error::Corrupt)?;
+            i += 1;
+        }
+
+        if cluster < CLUSTER_MIN {
+            return Err(Error::Corrupt);
+        }
+
+        self.buf.resize(self.volume.cluster_size(), 0);
+        self.volume.read_cluster(self.dev, cluster, &mut self.buf)?;
+        self.cached = Some((index, cluster));
+
+        Ok(())
+    }
+}
+
+impl<D: BlockDevice> io::Read for File<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, io::Error> {
+        let cluster_size = self.volume.cluster_size() as u64;
+        let remaining = (self.size - self.pos) as usize;
+        let len = buf.len().min(remaining);
+
+        let mut done = 0;
+        while done < len {
+            let index = self.pos / cluster_size;
+            let offset = (self.pos % cluster_size) as usize;
+            self.load_cluster(index)
+                .map_err(|_| io::Error::UnexpectedEof)?;
+
+            let n = (len - done).min(self.buf.len() - offset);
+            buf[done..done + n].copy_from_slice(&self.buf[offset..offset + n]);
+
+            done += n;
+            self.pos += n as u64;
+        }
+
+        Ok(done)
+    }
+}
+
+impl<D: BlockDevice> io::Seek for File<'_, D> {
+    fn seek(&mut self, pos: u64) -> Result<(), io::Error> {
+        if pos > self.size {
+            return Err(io::Error::SeekOutOfBounds);
+        }
+
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+/// Find the first sector of the FAT volume on the given device.
+fn find_volume_start<D: BlockDevice>(dev: &D) -> Result<u64, Error> {
+    let mut sector = vec![0; dev.block_size()];
+    read_blocks(dev, 0, &mut sector)?;
+
+    if sector.len() < 512 || sector[510..512] != BOOT_SIGNATURE {
+        return Err(Error::NotFat32);
+    }
+    if is_fat32_bpb(&sector) {
+        return Ok(0);
+    }
+
+    // Not a volume boot record, so this must be an MBR. Use the first partition.
+    let part_type = sector[0x1be + 4];
+    let part_start = read_u32(&sector, 0x1be + 8);
+    match part_type {
+        0 => Err(Error::NotFat32),
+        MBR_TYPE_GPT => {
+            read_blocks(dev, 1, &mut sector)?;
+            if sector[..8] != GPT_SIGNATURE {
+                return Err(Error::NotFat32);
+            }
+
+            let entries_lba = read_u64(&sector, 72);
+            read_blocks(dev, entries_lba, &mut sector)?;
+            Ok(read_u64(&sector, 32))
+        }
+        _ => Ok(u64::from(part_start)),
+    }
+}
+
+/// Read blocks from the device, failing instead of panicking if they lie beyond its end.
+fn read_blocks<D: BlockDevice>(dev: &D, lba: u64, buf: &mut [u8]) -> Result<(), Error> {
+    let blocks = (buf.len() / dev.block_size()) as u64;
+    match lba.checked_add(blocks) {
+        Some(end) if end <= dev.block_count() => {
+            dev.read_block(lba, buf);
+            Ok(())
+        }
+        _ => Err(Error::OutOfBounds),
+    }
+}
+
+fn is_fat32_bpb(sector: &[u8]) -> bool {
+    sector[510..512] == BOOT_SIGNATURE && &sector[82..90] == b"FAT32   "
+}
+
+/// Convert a file name into the space-padded 8.3 form used in directory entries.
+///
+/// Returns `None` if the name isn't a valid 8.3 name.
+fn to_short_name(name: &str) -> Option<[u8; 11]> {
+    let (base, ext) = name.split_once('.').unwrap_or((name, ""));
+    if base.is_empty() || base.len() > 8 || ext.len() > 3 || !name.is_ascii() {
+        return None;
+    }
+
+    let mut short = [b' '; 11];
+    short[..base.len()].copy_from_slice(base.as_bytes());
+    short[8..8 + ext.len()].copy_from_slice(ext.as_bytes());
+    short.make_ascii_uppercase();
+
+    Some(short)
+}
+
+fn read_u16(buf: &[u8], offset: usize) -> u16 {
+    u16::from_le_bytes(buf[offset..offset + 2].try_into().unwrap())
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
@@ -0,0 +1,148 @@
+//! A text console rendering into the graphics framebuffer.
+
+mod font;
+
+use core::fmt;
+
+use boot_info::{Framebuffer, PixelFormat};
+
+use crate::memory::mmio::{self, Mmio, MmioRegion};
+
+/// Foreground color, as `(red, green, blue)`.
+const FOREGROUND: (u8, u8, u8) = (0xc0, 0xc0, 0xc0);
+/// Background color, as `(red, green, blue)`.
+const BACKGROUND: (u8, u8, u8) = (0x00, 0x00, 0x00);
+
+/// A text console rendering glyphs from a built-in bitmap font into a framebuffer.
+///
+/// Lines that don't fit the screen are wrapped. Once the cursor reaches the bottom of the screen,
+/// the console scrolls by half a screen at a time. Framebuffer memory is mapped non-cacheable, so
+/// copying it is slow, and scrolling in bigger steps amortizes that cost.
+pub struct FramebufferConsole {
+    mmio: MmioRegion,
+    /// Bytes per scan line.
+    pitch: usize,
+    foreground: u32,
+    background: u32,
+    /// Size of the screen, in characters.
+    cols: usize,
+    rows: usize,
+    /// Position of the cursor, in characters.
+    col: usize,
+    row: usize,
+}
+
+impl FramebufferConsole {
+    /// Create a console on the given framebuffer, and clear the screen.
+    ///
+    /// # Safety
+    ///
+    /// `fb` must describe a valid framebuffer.
+    /// There must be no concurrent owner of the framebuffer memory.
+    pub unsafe fn new(fb: Framebuffer) -> Self {
+        let mmio = unsafe { mmio::map_framebuffer(fb.base, fb.size()) };
+        let mut console = Self {
+            mmio,
+            pitch: fb.stride as usize * Framebuffer::BYTES_PER_PIXEL,
+            foreground: pixel(fb.format, FOREGROUND),
+            background: pixel(fb.format, BACKGROUND),
+            cols: fb.width as usize / font::WIDTH,
+            rows: fb.height as usize / font::HEIGHT,
+            col: 0,
+            row: 0,
+        };
+
+        console.clear_rows(0, console.rows);
+        console
+    }
+
+    fn write_char(&mut self, c: char) {
+        match c {
+            '\n' => self.newline(),
+            '\r' => self.col = 0,
+            c => {
+                if self.col == self.cols {
+                    self.newline();
+                }
+
+                let glyph = match c {
+                    ' '..='~' => c as usize - 0x20,
+                    _ => '?' as usize - 0x20,
+                };
+                self.draw_glyph(glyph);
+                self.col += 1;
+            }
+        }
+    }
+
+    fn newline(&mut self) {
+        self.col = 0;
+        self.row += 1;
+
+        if self.row == self.rows {
+            let keep = self.rows / 2;
+            self.scroll(self.rows - keep);
+            self.row = keep;
+        }
+    }
+
+    fn draw_glyph(&mut self, glyph: usize) {
+        let x = self.col * font::WIDTH;
+        let y = self.row * font::HEIGHT;
+
+        for (dy, bits) in font::GLYPHS[glyph].into_iter().enumerate() {
+            let line = (y + dy) * self.pitch;
+            for dx in 0..font::WIDTH {
+                let set = bits & (0x80 >> dx) != 0;
+                let color = if set {
+                    self.foreground
+                } else {
+                    self.background
+                };
+                let offset = line + (x + dx) * Framebuffer::BYTES_PER_PIXEL;
+                unsafe { self.mmio.write32(offset, color) };
+            }
+        }
+    }
+
+    /// Move the screen contents up by `n` text rows, and clear the freed rows.
+    fn scroll(&mut self, n: usize) {
+        let row_bytes = font::HEIGHT * self.pitch;
+        let moved = (self.rows - n) * row_bytes;
+
+        for offset in (0..moved).step_by(4) {
+            unsafe {
+                let value = self.mmio.read32(offset + n * row_bytes);
+                self.mmio.write32(offset, value);
+            }
+        }
+
+        self.clear_rows(self.rows - n, self.rows);
+    }
+
+    /// Clear the text rows in `[start, end)`.
+    fn clear_rows(&mut self, start: usize, end: usize) {
+        let row_bytes = font::HEIGHT * self.pitch;
+        for offset in (start * row_bytes..end * row_bytes).step_by(4) {
+            unsafe { self.mmio.write32(offset, self.background) };
+        }
+    }
+}
+
+impl fmt::Write for FramebufferConsole {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        for c in s.chars() {
+            self.write_char(c);
+        }
+        Ok(())
+    }
+}
+
+/// Encode a color as a pixel value in the given format.
+fn pixel(format: PixelFormat, (r, g, b): (u8, u8, u8)) -> u32 {
+    let bytes = match format {
+        PixelFormat::Rgb => [r, g, b, 0],
+        PixelFormat::Bgr => [b, g, r, 0],
+    };
+    u32::from_le_bytes(bytes)
+}
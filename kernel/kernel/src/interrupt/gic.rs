@@ -0,0 +1,126 @@
+//! Driver for the GICv2 interrupt controller.
+
+use aarch64::memory::PA;
+
+use crate::memory::mmio::{self, Mmio, MmioRegion};
+
+// Distributor registers.
+const GICD_CTLR: usize = 0x000;
+const GICD_TYPER: usize = 0x004;
+const GICD_ISENABLER: usize = 0x100;
+const GICD_ICENABLER: usize = 0x180;
+const GICD_ICPENDR: usize = 0x280;
+const GICD_IPRIORITYR: usize = 0x400;
+const GICD_ITARGETSR: usize = 0x800;
+const GICD_SGIR: usize = 0xf00;
+
+// CPU interface registers.
+const GICC_CTLR: usize = 0x000;
+const GICC_PMR: usize = 0x004;
+const GICC_BPR: usize = 0x008;
+const GICC_IAR: usize = 0x00c;
+const GICC_EOIR: usize = 0x010;
+
+const GICD_SIZE: usize = 0x1000;
+const GICC_SIZE: usize = 0x2000;
+
+/// The first shared peripheral interrupt. Lower INTIDs are banked per CPU.
+const SPI_START: u32 = 32;
+
+/// Priority assigned to all interrupts. Lower values are more urgent.
+const DEFAULT_PRIORITY: u8 = 0xa0;
+
+/// INTIDs at and above this value are special and never dispatched.
+pub(super) const SPECIAL_START: u32 = 1020;
+
+pub(super) struct Gic {
+    dist: MmioRegion,
+    cpu: MmioRegion,
+    /// Number of INTIDs supported by the distributor.
+    num_intids: u32,
+}
+
+impl Gic {
+    /// # Safety
+    ///
+    /// `dist_pa` and `cpu_pa` must reference a GICv2 distributor and CPU interface, respectively.
+    /// There must be no concurrent owner of those.
+    pub unsafe fn new(dist_pa: PA, cpu_pa: PA) -> Self {
+        let dist = unsafe { mmio::map_region(dist_pa, GICD_SIZE) };
+        let cpu = unsafe { mmio::map_region(cpu_pa, GICC_SIZE) };
+
+        let typer: u32 = unsafe { dist.read(GICD_TYPER) };
+        let lines = 32 * ((typer & 0x1f) + 1);
+
+        Self {
+            dist,
+            cpu,
+            num_intids: lines.min(SPECIAL_START),
+        }
+    }
+
+    pub fn num_intids(&self) -> u32 {
+        self.num_intids
+    }
+
+    /// Initialize the distributor and the CPU interface of the current CPU.
+    ///
+    /// All interrupts are left disabled, with the same priority and, for SPIs, routed to CPU 0.
+    pub fn init(&mut self) {
+        unsafe {
+            self.dist.write::<u32>(GICD_CTLR, 0);
+
+            for n in 0..self.num_intids.div_ceil(32) as usize {
+                self.dist.write::<u32>(GICD_ICENABLER + n * 4, !0);
+                self.dist.write::<u32>(GICD_ICPENDR + n * 4, !0);
+            }
+
+            let priorities = u32::from_ne_bytes([DEFAULT_PRIORITY; 4]);
+            for intid in (0..self.num_intids).step_by(4) {
+                self.dist
+                    .write(GICD_IPRIORITYR + intid as usize, priorities);
+            }
+            for intid in (SPI_START..self.num_intids).step_by(4) {
+                self.dist
+                    .write::<u32>(GICD_ITARGETSR + intid as usize, 0x01010101);
+            }
+
+            self.dist.write::<u32>(GICD_CTLR, 1);
+
+            // Don't mask any priorities, and don't use preemption.
+            self.cpu.write::<u32>(GICC_PMR, 0xff);
+            self.cpu.write::<u32>(GICC_BPR, 0);
+            self.cpu.write::<u32>(GICC_CTLR, 1);
+        }
+    }
+
+    /// Enable forwarding of the given interrupt.
+    pub fn enable(&mut self, intid: u32) {
+        assert!(intid < self.num_intids, "invalid INTID: {intid}");
+
+        let offset = GICD_ISENABLER + (intid / 32) as usize * 4;
+        unsafe { self.dist.write::<u32>(offset, 1 << (intid % 32)) };
+    }
+
+    /// Send a software generated interrupt to the current CPU.
+    pub fn send_sgi_to_self(&mut self, intid: u32) {
+        assert!(intid < 16, "invalid SGI: {intid}");
+
+        // Target list filter 0b10: forward only to the requesting CPU.
+        let sgir = (0b10 << 24) | intid;
+        unsafe { self.dist.write::<u32>(GICD_SGIR, sgir) };
+    }
+
+    /// Acknowledge the highest priority pending interrupt.
+    ///
+    /// Returns the raw IAR value, which must be passed to [`Gic::end`] once the interrupt has
+    /// been handled.
+    pub fn acknowledge(&mut self) -> u32 {
+        unsafe { self.cpu.read(GICC_IAR) }
+    }
+
+    /// Signal the end of handling an interrupt.
+    pub fn end(&mut self, iar: u32) {
+        unsafe { self.cpu.write(GICC_EOIR, iar) };
+    }
+}
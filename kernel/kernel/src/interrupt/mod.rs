@@ -0,0 +1,187 @@
+//! Interrupt controller support and IRQ dispatch.
+
+mod gic;
+
+use core::hint;
+use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use aarch64::memory::PA;
+use aarch64::{Stopwatch, interrupt};
+use kstd::sync::IrqMutex;
+
+use crate::memory::pa_to_va;
+use crate::{log, warn};
+
+use self::gic::{Gic, SPECIAL_START};
+
+/// A handler for a specific interrupt.
+///
+/// Handlers run in IRQ context, with interrupts masked.
+pub type Handler = fn();
+
+static GIC: IrqMutex<Option<Gic>> = IrqMutex::new(None);
+static HANDLERS: IrqMutex<[Option<Handler>; SPECIAL_START as usize]> =
+    IrqMutex::new([None; SPECIAL_START as usize]);
+
+/// Initialize the interrupt controller and unmask IRQs.
+///
+/// Returns whether interrupts were initialized. If the system has an unsupported interrupt
+/// controller, this logs a warning and leaves IRQs masked.
+///
+/// # Safety
+///
+/// RSDP pointer must be valid, as must be all the referenced ACPI structures.
+pub unsafe fn init(acpi_rsdp: *const acpi::RSDP) -> bool {
+    log!("initializing interrupts");
+
+    let Some((dist_pa, cpu_pa)) = (unsafe { find_gic(acpi_rsdp) }) else {
+        return false;
+    };
+    log!("  GIC distributor at {dist_pa:#}, CPU interface at {cpu_pa:#}");
+
+    // SAFETY: Addresses come from the MADT, and nobody else accesses the GIC.
+    let mut gic = unsafe { Gic::new(dist_pa, cpu_pa) };
+    gic.init();
+    log!("  {} interrupts supported", gic.num_intids());
+
+    let mut guard = GIC.lock();
+    assert!(guard.is_none(), "interrupts already initialized");
+    *guard = Some(gic);
+    drop(guard);
+
+    // SAFETY: All interrupts are disabled at the distributor until explicitly enabled.
+    unsafe { interrupt::unmask_irq() };
+
+    verify_irq_delivery();
+
+    true
+}
+
+/// Verify that IRQs reach their handlers, by sending an SGI to the current CPU.
+///
+/// Misconfigured interrupt routing otherwise only shows up as devices that silently stop working.
+/// A missing SGI is only reported, since the system may still be usable without interrupts.
+fn verify_irq_delivery() {
+    const SGI: u32 = 0;
+    const TIMEOUT: Duration = Duration::from_millis(10);
+
+    static RECEIVED: AtomicBool = AtomicBool::new(false);
+
+    register_handler(SGI, || RECEIVED.store(true, Ordering::Release));
+    enable_irq(SGI);
+
+    GIC.lock().as_mut().unwrap().send_sgi_to_self(SGI);
+
+    let watch = Stopwatch::start();
+    while watch.elapsed() < TIMEOUT {
+        if RECEIVED.load(Ordering::Acquire) {
+            return;
+        }
+        hint::spin_loop();
+    }
+
+    warn!("SGI {SGI} not delivered within {TIMEOUT:?}");
+}
+
+/// Enable forwarding of the given interrupt.
+///
+/// A handler should be registered before the interrupt gets enabled.
+pub fn enable_irq(intid: u32) {
+    let mut gic = GIC.lock();
+    gic.as_mut().expect("interrupts initialized").enable(intid);
+}
+
+/// Register a handler for the given interrupt.
+///
+/// # Panics
+///
+/// Panics if a handler is already registered for `intid`.
+pub fn register_handler(intid: u32, handler: Handler) {
+    assert!(intid < SPECIAL_START, "invalid INTID: {intid}");
+
+    let mut handlers = HANDLERS.lock();
+    let slot = &mut handlers[intid as usize];
+    assert!(
+        slot.is_none(),
+        "handler already registered for INTID {intid}"
+    );
+    *slot = Some(handler);
+}
+
+/// Dispatch a pending IRQ to its handler.
+///
+/// Called from the IRQ exception vectors.
+pub(crate) fn dispatch() {
+    let iar = GIC
+        .lock()
+        .as_mut()
+        .expect("interrupts initialized")
+        .acknowledge();
+    let intid = iar & 0x3ff;
+
+    // The interrupt was withdrawn before we could acknowledge it.
+    if intid >= SPECIAL_START {
+        return;
+    }
+
+    let handler = HANDLERS.lock()[intid as usize];
+    match handler {
+        Some(handler) => handler(),
+        None => panic!("unhandled IRQ: {intid}"),
+    }
+
+    GIC.lock().as_mut().unwrap().end(iar);
+}
+
+/// Find the GIC distributor and CPU interface base addresses in the MADT.
+///
+/// Returns `None`, after logging a warning, if the ACPI tables don't describe a GICv2.
+///
+/// # Safety
+///
+/// RSDP pointer must be valid, as must be all the referenced ACPI structures.
+unsafe fn find_gic(acpi_rsdp: *const acpi::RSDP) -> Option<(PA, PA)> {
+    let xsdt =
+        match unsafe { acpi::Xsdt::with_phys_offset(acpi_rsdp, pa_to_va(PA::new(0)).into_u64()) } {
+            Ok(xsdt) => xsdt,
+            Err(error) => {
+                warn!("invalid ACPI tables ({error:?}), interrupts disabled");
+                return None;
+            }
+        };
+    let Some(madt_ptr) = xsdt.find_table(b"APIC") else {
+        warn!("no MADT table, interrupts disabled");
+        return None;
+    };
+    let madt = unsafe { &*madt_ptr.cast::<acpi::MADT>() };
+
+    let mut dist_pa = None;
+    let mut cpu_pa = None;
+    for entry in unsafe { madt.interrupt_controllers() } {
+        match entry {
+            acpi::MadtEntry::Gicd(gicd) => {
+                match gicd.gic_version {
+                    // Version 0 means the version isn't specified by the firmware.
+                    0 | 2 => (),
+                    version => {
+                        warn!("unsupported GIC version {version}, interrupts disabled");
+                        return None;
+                    }
+                }
+                dist_pa = Some(PA::new(gicd.physical_base_address));
+            }
+            // Only the boot CPU is supported, so use the first CPU interface.
+            acpi::MadtEntry::Gicc(gicc) if cpu_pa.is_none() => {
+                cpu_pa = Some(PA::new(gicc.physical_base_address));
+            }
+            _ => (),
+        }
+    }
+
+    let (Some(dist_pa), Some(cpu_pa)) = (dist_pa, cpu_pa) else {
+        warn!("no GIC distributor or CPU interface in the MADT, interrupts disabled");
+        return None;
+    };
+    Some((dist_pa, cpu_pa))
+}
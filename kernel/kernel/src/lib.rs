@@ -7,18 +7,32 @@ extern crate alloc;
 pub mod log;
 
 mod exception;
+mod fat;
+mod fbcon;
+mod interrupt;
 mod memory;
+#[cfg(feature = "monitor")]
+mod monitor;
 mod pci;
 mod process;
+mod sched;
+mod time;
 mod uart;
 mod userimg;
+mod virtio;
 
 use core::arch::naked_asm;
+use core::str;
+use core::sync::atomic::{AtomicBool, Ordering};
 
+use aarch64::Stopwatch;
 use boot_info::BootInfo;
 
 use crate::memory::virt::{KSTACK_END, pa_to_va};
 
+/// Whether to run the boot tests, set by the `boottest` command line option.
+static BOOT_TESTS: AtomicBool = AtomicBool::new(false);
+
 /// The kernel entry point.
 ///
 /// This is a tiny assembly stub that runs before `kernel_main` to set up the kernel stack.
@@ -47,41 +61,175 @@ pub unsafe extern "C" fn start(bootinfo: boot_info::ffi::BootInfo) -> ! {
 /// The provided `bootinfo` must contain correct memory addresses.
 unsafe extern "C" fn kernel_main(bootinfo: boot_info::ffi::BootInfo) -> ! {
     let acpi_rsdp_ptr: *const acpi::RSDP;
+    let uart_info: Option<boot_info::Uart>;
+    let framebuffer: Option<boot_info::Framebuffer>;
+    let mut options = Options::default();
 
     // SAFETY: `bootinfo` references boot memory, which is valid until `memory::init` runs, which
     // invalidates it by reclaiming all boot memory.
     unsafe {
         let bootinfo = BootInfo::from_ffi(bootinfo);
 
-        log::init(bootinfo.uart);
+        if let Some(uart) = bootinfo.uart {
+            log::init(uart);
+        }
         log!("enterned kernel");
 
-        log_bootinfo(&bootinfo);
-
         acpi_rsdp_ptr = pa_to_va(bootinfo.acpi_rsdp).as_ptr();
+        uart_info = bootinfo.uart;
+        framebuffer = bootinfo.framebuffer;
+        log_banner(&*acpi_rsdp_ptr);
+
+        log_bootinfo(&bootinfo);
+        if let Some(cmdline) = bootinfo.cmdline {
+            options = apply_cmdline(cmdline);
+        }
 
         exception::init();
+
+        let phase = Stopwatch::start();
         memory::init(bootinfo.memory);
+        log!("memory init took {:?}", phase.elapsed());
+
+        if let Some(fb) = framebuffer {
+            log::init_framebuffer(fb);
+        }
+    }
+
+    #[cfg(feature = "bench")]
+    memory::phys::bench_frame_map();
+
+    let irqs = unsafe { interrupt::init(acpi_rsdp_ptr) };
+    if irqs {
+        time::init();
+    }
+    sched::init();
+
+    if irqs && let Some(irq) = uart_info.and_then(|uart| uart.irq()) {
+        uart::init_console(irq);
+    }
+
+    let phase = Stopwatch::start();
+    let pci_functions = unsafe { pci::discover(acpi_rsdp_ptr) };
+    log!("PCI discovery took {:?}", phase.elapsed());
+    if options.pci_summary {
+        pci::log_summary(&pci_functions);
+    }
+
+    if options.probe_disk {
+        probe_disk(&pci_functions);
     }
 
-    unsafe { pci::discover(acpi_rsdp_ptr) };
+    #[cfg(feature = "monitor")]
+    monitor::run(&pci_functions);
 
     process::run();
 }
 
+/// Log a line identifying the running kernel build and the platform it runs on.
+fn log_banner(rsdp: &acpi::RSDP) {
+    let oem_id = str::from_utf8(&rsdp.oem_id).unwrap_or("unknown");
+    log!(
+        "TeaOS kernel version={} git={} profile={} platform={:?}",
+        env!("CARGO_PKG_VERSION"),
+        env!("TEAOS_GIT_HASH"),
+        if cfg!(debug_assertions) {
+            "debug"
+        } else {
+            "release"
+        },
+        oem_id.trim_end(),
+    );
+}
+
+/// Kernel command line options that take effect later during boot.
+#[derive(Default)]
+struct Options {
+    probe_disk: bool,
+    pci_summary: bool,
+}
+
+/// Apply the kernel command line options, and return those that take effect later.
+///
+/// Supported options:
+///  * `loglevel=<level>`: set the minimum log level (`trace`, `debug`, `info`, `warn`, `error`)
+///  * `probedisk`: look for the kernel image on a virtio block device
+///  * `pcisummary`: log a summary of the discovered PCI functions, for boot tests
+///  * `boottest`: run the boot tests
+fn apply_cmdline(cmdline: &str) -> Options {
+    let mut options = Options::default();
+    for option in cmdline.split_whitespace() {
+        if let Some(value) = option.strip_prefix("loglevel=") {
+            match value.parse() {
+                Ok(level) => log::set_level(level),
+                Err(()) => warn!("invalid log level: {value}"),
+            }
+        } else if option == "probedisk" {
+            options.probe_disk = true;
+        } else if option == "pcisummary" {
+            options.pci_summary = true;
+        } else if option == "boottest" {
+            BOOT_TESTS.store(true, Ordering::Relaxed);
+        }
+    }
+    options
+}
+
+/// Look for the kernel image on the first virtio block device.
+fn probe_disk(pci_functions: &[pci::Function]) {
+    let Some(disk) = virtio::blk::probe(pci_functions) else {
+        warn!("no virtio block device found");
+        return;
+    };
+
+    match fat::open(&disk, "/kernel") {
+        Ok(file) => log!("found kernel on disk: {} bytes", file.size()),
+        Err(error) => warn!("failed to open kernel on disk: {error:?}"),
+    }
+}
+
+/// Whether to run the boot tests.
+///
+/// Boot tests exercise kernel APIs during boot, and each logs a `boot-test: <name> ok` line when
+/// it passes. `cargo xtask boot-test` checks for these lines.
+fn boot_tests_enabled() -> bool {
+    BOOT_TESTS.load(Ordering::Relaxed)
+}
+
 fn log_bootinfo(bootinfo: &BootInfo<'_>) {
     let BootInfo {
         memory,
         uart,
         acpi_rsdp,
+        framebuffer,
+        cmdline,
+        modules,
     } = bootinfo;
 
     log!("bootinfo.memory:");
-    log!("     start        pages    type");
-    log!("  ------------------------------");
+    log!("     start        pages          attributes  type");
+    log!("  -------------------------------------------------");
     for block in memory.blocks {
-        log!("  {:#012}  {:8}  {}", block.start, block.pages, block.type_);
+        log!(
+            "  {:#012}  {:8}  {:#018x}  {}",
+            block.start,
+            block.pages,
+            block.attributes,
+            block.type_,
+        );
     }
     log!("bootinfo.uart: {uart:?}");
     log!("bootinfo.acpi_rsdp: {acpi_rsdp:#}");
+    log!("bootinfo.framebuffer: {framebuffer:?}");
+    log!("bootinfo.cmdline: {cmdline:?}");
+    log!("bootinfo.modules:");
+    for module in *modules {
+        log!(
+            "  {}: {:#} ({} pages, {} bytes)",
+            module.name(),
+            module.base,
+            module.pages,
+            module.size,
+        );
+    }
 }
@@ -1,29 +1,117 @@
 //! Print logging support.
 
 use core::fmt::{self, Write};
+use core::hint;
+use core::str::FromStr;
+use core::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 
+use kstd::sync::IrqMutex;
+use kstd::time::Timestamp;
+
+use crate::fbcon::FramebufferConsole;
 use crate::memory::mmio;
+use crate::sched;
 use crate::uart::Uart;
 
 static mut LOGGER: Logger = Logger::new();
+/// The log UART.
+///
+/// This is also accessed by the console RX interrupt handler, so unlike the other log backends it
+/// is protected by an [`IrqMutex`].
+static UART: IrqMutex<Option<Uart>> = IrqMutex::new(None);
+
+/// Minimum level of messages logged through the leveled macros.
+static LEVEL: AtomicU8 = AtomicU8::new(Level::Info as u8);
+/// Whether log lines are prefixed with the uptime.
+static TIMESTAMPS: AtomicBool = AtomicBool::new(true);
+
+/// Severity of a log message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+#[repr(u8)]
+pub enum Level {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    const ALL: [Self; 5] = [
+        Self::Trace,
+        Self::Debug,
+        Self::Info,
+        Self::Warn,
+        Self::Error,
+    ];
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Trace => "TRACE",
+            Self::Debug => "DEBUG",
+            Self::Info => "INFO",
+            Self::Warn => "WARN",
+            Self::Error => "ERROR",
+        }
+    }
+}
 
+impl fmt::Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.pad(self.as_str())
+    }
+}
+
+impl FromStr for Level {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|l| l.as_str().eq_ignore_ascii_case(s))
+            .ok_or(())
+    }
+}
+
+/// Set the minimum level of messages logged through the leveled macros.
+///
+/// Messages logged with [`log!`](crate::log!) are not filtered.
+pub fn set_level(level: Level) {
+    LEVEL.store(level as u8, Ordering::Relaxed);
+}
+
+/// Enable or disable the uptime prefix of log lines.
+pub fn set_timestamps(enabled: bool) {
+    TIMESTAMPS.store(enabled, Ordering::Relaxed);
+}
+
+/// Whether messages of the given level are currently logged.
+pub fn enabled(level: Level) -> bool {
+    level as u8 >= LEVEL.load(Ordering::Relaxed)
+}
+
+/// The log backends, each written to when present.
+///
+/// The UART isn't part of this, see [`UART`].
 struct Logger {
-    uart: Option<Uart>,
+    framebuffer: Option<FramebufferConsole>,
 }
 
 impl Logger {
     const fn new() -> Self {
-        Self { uart: None }
+        Self { framebuffer: None }
     }
 }
 
 impl Write for Logger {
     fn write_str(&mut self, s: &str) -> fmt::Result {
-        if let Some(uart) = &mut self.uart {
-            uart.write_str(s)
-        } else {
-            Ok(())
+        if let Some(uart) = UART.lock().as_mut() {
+            uart.write_str(s)?;
+        }
+        if let Some(framebuffer) = &mut self.framebuffer {
+            framebuffer.write_str(s)?;
         }
+        Ok(())
     }
 }
 
@@ -36,12 +124,25 @@ pub unsafe fn init(uart_info: boot_info::Uart) {
     let mmio = unsafe { mmio::claim_page(uart_info.base()) };
     let uart = match uart_info {
         boot_info::Uart::Pl011 { .. } => unsafe { Uart::pl011(mmio) },
-        boot_info::Uart::Uart16550 { .. } => unsafe { Uart::uart16550(mmio) },
+        boot_info::Uart::Uart16550 { reg_width, .. } => unsafe { Uart::uart16550(mmio, reg_width) },
     };
 
+    *UART.lock() = Some(uart);
+}
+
+/// Additionally log to a console on the given framebuffer.
+///
+/// Requires virtual memory to be initialized, to map the framebuffer.
+///
+/// # Safety
+///
+/// The given framebuffer configuration must be correct.
+pub unsafe fn init_framebuffer(fb: boot_info::Framebuffer) {
+    let console = unsafe { FramebufferConsole::new(fb) };
+
     unsafe {
         let logger = &raw mut LOGGER;
-        (*logger).uart = Some(uart);
+        (*logger).framebuffer = Some(console);
     }
 }
 
@@ -52,12 +153,72 @@ pub fn write(args: fmt::Arguments) {
     }
 }
 
+/// Read a byte from the log UART, if one is available.
+pub fn read_byte() -> Option<u8> {
+    UART.lock().as_mut()?.read_byte()
+}
+
+/// Enable the RX interrupt of the log UART.
+pub fn enable_rx_interrupt() {
+    if let Some(uart) = UART.lock().as_mut() {
+        uart.enable_rx_interrupt();
+    }
+}
+
+/// Read a byte from the log UART, waiting until one is available.
+///
+/// Returns `None` if logging isn't initialized.
+pub fn read_byte_blocking() -> Option<u8> {
+    // Don't hold the lock while waiting, to keep interrupts unmasked.
+    loop {
+        let mut uart = UART.lock();
+        if let Some(b) = uart.as_mut()?.read_byte() {
+            return Some(b);
+        }
+        drop(uart);
+        hint::spin_loop();
+    }
+}
+
+/// Log a backtrace of the caller, by walking the frame pointer chain on the stack of the running
+/// task.
+#[inline(never)]
+pub fn backtrace() {
+    crate::log!("backtrace:");
+    aarch64::debug::backtrace(sched::current_stack(), |lr| {
+        crate::log!("  {lr:#018x}");
+    });
+}
+
+#[inline(never)]
+pub fn log_level_args(level: Level, args: fmt::Arguments, module: &str) {
+    write_line(Some(level), args, module);
+}
+
 #[inline(never)]
 pub fn log_args(args: fmt::Arguments, module: &str) {
-    let time = aarch64::uptime().as_millis();
+    write_line(None, args, module);
+}
+
+/// Write a log line, prefixed with the uptime and level, if any.
+///
+/// The prefix looks like `[  1.234 INFO] `, with empty parts left out.
+fn write_line(level: Option<Level>, args: fmt::Arguments, module: &str) {
+    let time = TIMESTAMPS
+        .load(Ordering::Relaxed)
+        .then(|| Timestamp(aarch64::uptime()));
+
     unsafe {
         let logger = &raw mut LOGGER;
-        writeln!(&mut *logger, "{time} [{module}] {args}").unwrap();
+        let logger = &mut *logger;
+        match (time, level) {
+            (Some(time), Some(level)) => write!(logger, "[{time} {level}] "),
+            (Some(time), None) => write!(logger, "[{time}] "),
+            (None, Some(level)) => write!(logger, "[{level}] "),
+            (None, None) => Ok(()),
+        }
+        .unwrap();
+        writeln!(logger, "[{module}] {args}").unwrap();
     }
 }
 
@@ -68,3 +229,40 @@ macro_rules! log {
         $crate::log::log_args(format_args!($($arg)*), module);
     }};
 }
+
+/// Log a message at the given level, if that level is enabled.
+#[macro_export]
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)*) => {{
+        let level = $level;
+        if $crate::log::enabled(level) {
+            let module = module_path!();
+            $crate::log::log_level_args(level, format_args!($($arg)*), module);
+        }
+    }};
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => { $crate::log_at!($crate::log::Level::Trace, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { $crate::log_at!($crate::log::Level::Debug, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { $crate::log_at!($crate::log::Level::Info, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { $crate::log_at!($crate::log::Level::Warn, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { $crate::log_at!($crate::log::Level::Error, $($arg)*) };
+}
@@ -5,11 +5,26 @@ use aarch64::memory::{PAGE_SIZE, VA};
 use freelist::{ALIGN, FreeList, round_up_align};
 use kstd::sync::Mutex;
 
+use crate::error;
 use crate::memory::virt::{self, KHEAP_SIZE, KHEAP_START, PageNr};
 
+/// Minimum amount of free memory at the end of the heap before [`trim_heap`] returns it to the PMM.
+///
+/// Keeping some slack avoids unmapping and remapping pages when allocations go back and forth
+/// across a page boundary.
+const TRIM_THRESHOLD: usize = 64 * PAGE_SIZE;
+
 #[global_allocator]
 static HEAP_ALLOCATOR: LockedHeapAllocator = LockedHeapAllocator::new();
 
+/// Return free pages at the end of the heap to the PMM, if there are enough of them.
+///
+/// This takes the VMM lock, so unlike the allocator itself it must not be called with the VMM
+/// locked. Freeing memory never shrinks the heap by itself.
+pub fn trim_heap() {
+    HEAP_ALLOCATOR.0.lock().trim();
+}
+
 struct LockedHeapAllocator(Mutex<HeapAllocator>);
 
 impl LockedHeapAllocator {
@@ -20,10 +35,15 @@ impl LockedHeapAllocator {
 
 unsafe impl GlobalAlloc for LockedHeapAllocator {
     unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        assert!(layout.align() <= ALIGN);
-        match self.0.lock().alloc(layout.size()) {
+        let mut heap = self.0.lock();
+        match heap.alloc(layout.size(), layout.align()) {
             Some(ptr) => ptr.as_ptr(),
-            None => ptr::null_mut(),
+            None => {
+                // Returning null makes the default alloc error handler panic, which halts the
+                // kernel. Log what we know first, so the failure is diagnosable.
+                heap.log_alloc_failure(layout);
+                ptr::null_mut()
+            }
         }
     }
 
@@ -33,28 +53,61 @@ unsafe impl GlobalAlloc for LockedHeapAllocator {
     }
 }
 
+/// Strategy for choosing the free block an allocation is carved from.
+#[derive(Clone, Copy, Debug)]
+enum FitStrategy {
+    /// Use the first block that fits. Fast, but fragments large blocks.
+    FirstFit,
+    /// Use the smallest block that fits. Slower, but reduces fragmentation.
+    BestFit,
+}
+
 struct HeapAllocator {
     freelist: FreeList,
     heap_break: VA,
+    strategy: FitStrategy,
 }
 
 impl HeapAllocator {
     const fn new() -> Self {
+        let strategy = if cfg!(feature = "heap-best-fit") {
+            FitStrategy::BestFit
+        } else {
+            FitStrategy::FirstFit
+        };
+
         Self {
             freelist: FreeList::new(),
             heap_break: KHEAP_START,
+            strategy,
         }
     }
 
-    fn alloc(&mut self, size: usize) -> Option<NonNull<u8>> {
+    fn alloc(&mut self, size: usize, align: usize) -> Option<NonNull<u8>> {
         let size = round_up_align(size);
 
-        match self.freelist.carve(size) {
+        match self.carve(size, align) {
             Some(ptr) => Some(ptr),
-            None => match self.grow(size) {
-                Ok(()) => self.freelist.carve(size),
-                Err(()) => None,
-            },
+            None => {
+                // Grow by enough to fit an aligned block, even if the new memory doesn't start
+                // at the required alignment.
+                let padding = if align > ALIGN { align } else { 0 };
+                match self.grow(size + padding) {
+                    Ok(()) => self.carve(size, align),
+                    Err(()) => None,
+                }
+            }
+        }
+    }
+
+    fn carve(&mut self, size: usize, align: usize) -> Option<NonNull<u8>> {
+        if align > ALIGN {
+            return self.freelist.carve_aligned(size, align);
+        }
+
+        match self.strategy {
+            FitStrategy::FirstFit => self.freelist.carve(size),
+            FitStrategy::BestFit => self.freelist.carve_best_fit(size),
         }
     }
 
@@ -65,8 +118,51 @@ impl HeapAllocator {
     unsafe fn free(&mut self, ptr: NonNull<u8>, size: usize) {
         let size = round_up_align(size);
         unsafe { self.freelist.insert(ptr, size) };
+    }
+
+    /// Return free pages at the end of the heap to the PMM, if there are at least
+    /// [`TRIM_THRESHOLD`] bytes of them.
+    fn trim(&mut self) {
+        let heap_end = NonNull::new(self.heap_break.as_mut_ptr()).unwrap();
+
+        // Only whole pages are trimmed off the freelist, so the remaining freelist nodes all live
+        // below the new break.
+        let Some(new_end) = self.freelist.trim_end(heap_end, PAGE_SIZE) else {
+            return;
+        };
+        let new_break = VA::new(new_end.as_ptr() as u64);
+
+        let trimmed = (self.heap_break.into_u64() - new_break.into_u64()) as usize;
+        if trimmed < TRIM_THRESHOLD {
+            // SAFETY: The memory was just removed from the freelist and is still mapped.
+            unsafe { self.freelist.insert(new_end, trimmed) };
+            return;
+        }
+
+        let mut vpn = PageNr::from_va(new_break);
+        while vpn.va() < self.heap_break {
+            virt::unmap_page(vpn);
+            vpn += 1;
+        }
+
+        self.heap_break = new_break;
+    }
 
-        // TODO reclaim physical memory
+    fn log_alloc_failure(&self, layout: Layout) {
+        let mapped = self.heap_break.into_u64() - KHEAP_START.into_u64();
+
+        error!(
+            "heap allocation failed: size={:#x}, align={:#x}",
+            layout.size(),
+            layout.align(),
+        );
+        error!("  heap mapped: {mapped:#x} of {KHEAP_SIZE:#x} bytes");
+
+        let stats = self.freelist.stats();
+        error!(
+            "  freelist: {:#x} bytes free in {} blocks, largest block {:#x} bytes",
+            stats.total_free, stats.block_count, stats.largest_block,
+        );
     }
 
     fn grow(&mut self, size: usize) -> Result<(), ()> {
@@ -1,33 +1,122 @@
+use core::mem;
+
 use aarch64::memory::{PA, PAGE_SIZE, VA, va_to_pa};
 
 use crate::memory::phys::FrameNr;
+use crate::memory::virt::{MemoryClass, PageNr};
 use crate::memory::{pa_to_va, virt};
 
-#[derive(Debug)]
-pub struct MmioPage {
-    base: VA,
-}
+/// Volatile access to memory-mapped device registers.
+///
+/// All accesses are bounds-checked against the mapped registers.
+pub trait Mmio {
+    /// Return the address of the first mapped register.
+    fn base(&self) -> VA;
+
+    /// Return the size of the mapped registers, in bytes.
+    fn size(&self) -> usize;
 
-impl MmioPage {
     /// # Safety
     ///
     /// `offset` must point to a readable MMIO register of type `T`.
-    pub unsafe fn read<T: Copy>(&self, offset: usize) -> T {
-        debug_assert!(offset < PAGE_SIZE);
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register doesn't lie within the mapped registers.
+    unsafe fn read<T: Copy>(&self, offset: usize) -> T {
+        self.check_bounds::<T>(offset);
 
-        let va = self.base + offset;
+        let va = self.base() + offset;
         unsafe { va.as_ptr::<T>().read_volatile() }
     }
 
     /// # Safety
     ///
     /// `offset` must point to a writable MMIO register of type `T`.
-    pub unsafe fn write<T: Copy>(&mut self, offset: usize, val: T) {
-        debug_assert!(offset < PAGE_SIZE);
+    ///
+    /// # Panics
+    ///
+    /// Panics if the register doesn't lie within the mapped registers.
+    unsafe fn write<T: Copy>(&mut self, offset: usize, val: T) {
+        self.check_bounds::<T>(offset);
 
-        let va = self.base + offset;
+        let va = self.base() + offset;
         unsafe { va.as_mut_ptr::<T>().write_volatile(val) }
     }
+
+    /// # Safety
+    ///
+    /// See [`Mmio::read`].
+    unsafe fn read8(&self, offset: usize) -> u8 {
+        unsafe { self.read(offset) }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Mmio::read`].
+    unsafe fn read16(&self, offset: usize) -> u16 {
+        unsafe { self.read(offset) }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Mmio::read`].
+    unsafe fn read32(&self, offset: usize) -> u32 {
+        unsafe { self.read(offset) }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Mmio::write`].
+    unsafe fn write8(&mut self, offset: usize, val: u8) {
+        unsafe { self.write(offset, val) }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Mmio::write`].
+    unsafe fn write16(&mut self, offset: usize, val: u16) {
+        unsafe { self.write(offset, val) }
+    }
+
+    /// # Safety
+    ///
+    /// See [`Mmio::write`].
+    unsafe fn write32(&mut self, offset: usize, val: u32) {
+        unsafe { self.write(offset, val) }
+    }
+
+    fn check_bounds<T>(&self, offset: usize) {
+        let size = self.size();
+        let end = offset.checked_add(mem::size_of::<T>());
+        assert!(
+            end.is_some_and(|end| end <= size),
+            "MMIO access out of bounds: offset={offset:#x}, size={size:#x}",
+        );
+    }
+}
+
+#[derive(Debug)]
+pub struct MmioPage {
+    base: VA,
+    pa: PA,
+}
+
+impl MmioPage {
+    /// Return the physical address of the page.
+    pub fn pa(&self) -> PA {
+        self.pa
+    }
+}
+
+impl Mmio for MmioPage {
+    fn base(&self) -> VA {
+        self.base
+    }
+
+    fn size(&self) -> usize {
+        PAGE_SIZE
+    }
 }
 
 /// Claim the given MMIO page.
@@ -37,14 +126,143 @@ impl MmioPage {
 /// `pa` must reference an MMIO page frame.
 /// There must be no concurrent owner of that MMIO page.
 pub unsafe fn claim_page(pa: PA) -> MmioPage {
+    unsafe { claim_page_as(pa, MemoryClass::Device) }
+}
+
+/// Claim the given MMIO page for read-only access.
+///
+/// If the page isn't mapped yet, it is mapped read-only, so writes through the returned
+/// [`MmioPage`] fault.
+///
+/// # Safety
+///
+/// `pa` must reference an MMIO page frame.
+/// There must be no concurrent owner of that MMIO page.
+pub unsafe fn claim_page_ro(pa: PA) -> MmioPage {
+    unsafe { claim_page_as(pa, MemoryClass::DeviceReadOnly) }
+}
+
+/// # Safety
+///
+/// `pa` must reference an MMIO page frame.
+/// There must be no concurrent owner of that MMIO page.
+unsafe fn claim_page_as(pa: PA, class: MemoryClass) -> MmioPage {
     assert!(pa.is_page_aligned());
 
     let va = pa_to_va(pa);
 
     if va_to_pa(va).is_none() {
         let pfn = FrameNr::from_pa(pa);
-        virt::map_mmio_page(pfn);
+        virt::map_mmio_page(pfn, class);
     }
 
-    MmioPage { base: va }
+    MmioPage { base: va, pa }
+}
+
+/// A region of MMIO registers, mapped into a reserved window of the kernel address space.
+#[derive(Debug)]
+pub struct MmioRegion {
+    base: VA,
+    size: usize,
+    /// Window pages to unmap on drop, or `None` if the region stays mapped.
+    window: Option<(PageNr, usize)>,
+}
+
+impl MmioRegion {
+    /// Map `pages` MMIO pages, starting at `pa`, into a reserved window of the kernel address
+    /// space.
+    ///
+    /// Unlike regions returned by [`map_region`], the returned region is unmapped again when it is
+    /// dropped. The window range itself is never released though, so callers should keep the
+    /// region around rather than mapping it repeatedly.
+    ///
+    /// # Safety
+    ///
+    /// `pa` must reference `pages` pages of MMIO registers.
+    /// There must be no concurrent owner of those pages.
+    pub unsafe fn map(pa: PA, pages: usize) -> Self {
+        assert!(pa.is_page_aligned());
+        assert!(pages > 0, "empty MMIO region");
+
+        let window = virt::reserve_range(pages);
+        let vpn = PageNr::from_va(window);
+        virt::map_mmio_pages(vpn, FrameNr::from_pa(pa), pages, MemoryClass::Device);
+
+        Self {
+            base: window,
+            size: pages * PAGE_SIZE,
+            window: Some((vpn, pages)),
+        }
+    }
+}
+
+impl Drop for MmioRegion {
+    fn drop(&mut self) {
+        if let Some((vpn, pages)) = self.window {
+            for i in 0..pages {
+                virt::unmap_page(vpn + i as u64);
+            }
+        }
+    }
+}
+
+impl Mmio for MmioRegion {
+    fn base(&self) -> VA {
+        self.base
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+/// Map the given MMIO region into a reserved window of the kernel address space.
+///
+/// `pa` doesn't need to be page-aligned. The returned region starts at the register referenced by
+/// `pa`.
+///
+/// # Safety
+///
+/// `pa` must reference a region of `size` bytes of MMIO registers.
+/// There must be no concurrent owner of that region.
+pub unsafe fn map_region(pa: PA, size: usize) -> MmioRegion {
+    unsafe { map_region_as(pa, size, MemoryClass::Device) }
+}
+
+/// Map the given framebuffer memory into a reserved window of the kernel address space.
+///
+/// Like [`map_region`], but maps the memory as [`MemoryClass::Framebuffer`].
+///
+/// # Safety
+///
+/// `pa` must reference `size` bytes of framebuffer memory.
+/// There must be no concurrent owner of that memory.
+pub unsafe fn map_framebuffer(pa: PA, size: usize) -> MmioRegion {
+    unsafe { map_region_as(pa, size, MemoryClass::Framebuffer) }
+}
+
+/// # Safety
+///
+/// `pa` must reference a region of `size` bytes of MMIO registers.
+/// There must be no concurrent owner of that region.
+unsafe fn map_region_as(pa: PA, size: usize, class: MemoryClass) -> MmioRegion {
+    assert!(size > 0, "empty MMIO region");
+
+    let offset = pa.into_u64() as usize % PAGE_SIZE;
+    let start = PA::new(pa.into_u64() - offset as u64);
+    let pages = (offset + size).div_ceil(PAGE_SIZE);
+
+    let window = virt::reserve_range(pages);
+    virt::map_mmio_range(
+        PageNr::from_va(window),
+        FrameNr::from_pa(start),
+        pages,
+        class,
+    );
+
+    MmioRegion {
+        base: window + offset,
+        size,
+        window: None,
+    }
 }
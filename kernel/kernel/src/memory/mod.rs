@@ -6,11 +6,14 @@ pub mod virt;
 
 mod heap;
 
-use crate::log;
+use crate::{debug, log};
 
+use aarch64::Stopwatch;
 use aarch64::memory::paging::disable_ttbr0;
-use boot_info::MemoryType;
+use aarch64::memory::{PAGE_SIZE, VA, va_to_pa};
+use boot_info::{MemoryBlock, MemoryType};
 
+pub use self::heap::trim_heap;
 pub use self::virt::pa_to_va;
 
 /// Initialize the memory subsystem.
@@ -26,33 +29,86 @@ pub use self::virt::pa_to_va;
 pub unsafe fn init(info: boot_info::Memory<'_>) {
     log!("initializing memory management");
 
-    log!("  seeding PMM with unused blocks");
+    debug!("  seeding PMM with unused blocks");
+    let phase = Stopwatch::start();
     for block in info.blocks {
         if block.type_ == MemoryType::Unused {
             // SAFETY: Block is unused, according to the boot info.
             unsafe { phys::seed(block.start, block.pages) };
         }
     }
+    debug!("    took {:?}", phase.elapsed());
 
-    log!("  initializing VMM");
+    debug!("  initializing VMM");
+    let phase = Stopwatch::start();
     // SAFETY: No references to TTBR1 page tables exist.
     unsafe { virt::init() };
+    debug!("    took {:?}", phase.elapsed());
+
+    if crate::boot_tests_enabled() {
+        virt::boot_test_unmap();
+        virt::boot_test_protect();
+        virt::boot_test_lookup();
+        virt::boot_test_map_shared();
+        virt::boot_test_cow();
+        phys::boot_test_contiguous();
+        #[cfg(feature = "frame-poison")]
+        phys::boot_test_poison();
+    }
 
     // Taking over the boot memory will make the bootinfo invalid, so copy what we still need and
     // then drop it.
     let memory_blocks = info.blocks.to_vec();
     drop(info);
 
-    log!("  disabling boot page tables");
+    debug!("  disabling boot page tables");
     // SAFETY: Not using any TTBR0 mappings anymore.
     unsafe { disable_ttbr0() };
+    verify_ttbr0_disabled(&memory_blocks);
 
-    log!("  claiming boot memory");
+    debug!("  claiming boot memory");
+    let phase = Stopwatch::start();
+    // The loader's page tables live in boot memory. `virt::init` copied their mappings into
+    // PMM-owned page tables, so none of them should be referenced anymore. Reclaiming a live page
+    // table would silently corrupt the kernel address space, so double-check that.
+    let table_frames = virt::page_table_frames();
     for block in memory_blocks {
         if block.type_ == MemoryType::Boot {
+            let end = block.start + block.pages * PAGE_SIZE;
+            let idx = table_frames.partition_point(|pa| *pa < block.start);
+            if let Some(pa) = table_frames.get(idx) {
+                assert!(*pa >= end, "live page table in boot memory: {pa:?}");
+            }
+
             // SAFETY: Block hasn't been given to the PMM before and is now unused since we've
             // taken over all boot memory.
             unsafe { phys::seed(block.start, block.pages) };
         }
     }
+    debug!("    took {:?}", phase.elapsed());
+
+    let stats = phys::stats();
+    log!(
+        "  physical memory: total={} free={} allocated={} frames",
+        stats.total_frames,
+        stats.free_frames,
+        stats.allocated_frames,
+    );
+}
+
+/// Verify that no TTBR0 mappings are active anymore.
+///
+/// This is a one-time boot sanity check. UEFI identity-maps all memory through TTBR0, so boot
+/// memory addresses used to be translatable. Once TTBR0 is disabled, translating one of them must
+/// fault. If it doesn't, code holding a low-half pointer might continue to work by accident until
+/// the underlying memory is reused.
+fn verify_ttbr0_disabled(blocks: &[MemoryBlock]) {
+    if !cfg!(debug_assertions) {
+        return;
+    }
+
+    if let Some(block) = blocks.iter().find(|b| b.type_ == MemoryType::Boot) {
+        let va = VA::new(block.start.into_u64());
+        assert!(va_to_pa(va).is_none(), "TTBR0 mapping still active: {va:?}");
+    }
 }
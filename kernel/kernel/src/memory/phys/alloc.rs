@@ -1,17 +1,28 @@
+#[cfg(feature = "frame-poison")]
+use aarch64::memory::PAGE_SIZE;
 use kstd::sync::Mutex;
 
 use super::{FrameNr, pa_to_va};
 
 static ALLOC: Mutex<FrameAllocator> = Mutex::new(FrameAllocator::new());
 
+/// Byte pattern free frames are filled with, if the `frame-poison` feature is enabled.
+#[cfg(feature = "frame-poison")]
+pub(super) const POISON: u8 = 0xaa;
+
 /// A physical page frame allocator.
 struct FrameAllocator {
     freelist: Option<FrameNr>,
+    /// Number of frames in the freelist.
+    free_count: usize,
 }
 
 impl FrameAllocator {
     pub(super) const fn new() -> Self {
-        Self { freelist: None }
+        Self {
+            freelist: None,
+            free_count: 0,
+        }
     }
 
     fn alloc(&mut self) -> FrameNr {
@@ -27,16 +38,77 @@ impl FrameAllocator {
         // from the list of free frames, so no other readers or writers exist.
         let next_pfn = unsafe { va.as_mut_ptr::<Option<FrameNr>>().read() };
         self.freelist = next_pfn;
+        self.free_count -= 1;
+
+        #[cfg(feature = "frame-poison")]
+        check_poison(pfn);
 
         pfn
     }
 
+    /// Allocate `count` consecutive page frames, returning the lowest one.
+    ///
+    /// The freelist doesn't track runs of free frames, so this scans it for `count` adjacent
+    /// entries with descending frame numbers. That's the order in which [`Self::free`] leaves
+    /// frames that were freed in ascending order, like the ones passed to `seed`.
+    fn alloc_contiguous(&mut self, count: usize) -> Option<FrameNr> {
+        assert!(count > 0, "invalid frame count: 0");
+
+        let next = |pfn: FrameNr| {
+            let va = pa_to_va(pfn.pa());
+            // SAFETY: Reading what was previously written in `Self::free`.
+            unsafe { va.as_mut_ptr::<Option<FrameNr>>().read() }
+        };
+
+        // The link pointing to the start of the current candidate run.
+        let mut link: *mut Option<FrameNr> = &mut self.freelist;
+        loop {
+            // SAFETY: `link` points either to `self.freelist` or into a free frame.
+            let start = unsafe { link.read() }?;
+
+            let mut pfn = start;
+            let mut len = 1;
+            while len < count {
+                match next(pfn) {
+                    Some(n) if n.0 + 1 == pfn.0 => {
+                        pfn = n;
+                        len += 1;
+                    }
+                    _ => break,
+                }
+            }
+
+            if len == count {
+                // Unlink the whole run. `pfn` is its last and lowest frame.
+                //
+                // SAFETY: `link` points either to `self.freelist` or into a free frame.
+                unsafe { link.write(next(pfn)) };
+                self.free_count -= count;
+
+                #[cfg(feature = "frame-poison")]
+                for i in 0..count {
+                    check_poison(FrameNr(pfn.0 + i as u64));
+                }
+
+                return Some(pfn);
+            }
+
+            link = pa_to_va(start.pa()).as_mut_ptr();
+        }
+    }
+
     /// # Safety
     ///
     /// `pfn` must identify an unused page frame.
     unsafe fn free(&mut self, pfn: FrameNr) {
         let va = pa_to_va(pfn.pa());
 
+        #[cfg(feature = "frame-poison")]
+        {
+            // SAFETY: Frame is unused, so no other readers or writers exist.
+            unsafe { va.as_mut_ptr::<u8>().write_bytes(POISON, PAGE_SIZE) };
+        }
+
         // Insert the frame into the freelist.
         let next_frame = self.freelist;
         // SAFETY: Destination is page-aligned and points to a physical memory page. Frame is
@@ -44,7 +116,29 @@ impl FrameAllocator {
         unsafe { va.as_mut_ptr::<Option<FrameNr>>().write(next_frame) };
 
         self.freelist = Some(pfn);
+        self.free_count += 1;
+    }
+}
+
+/// Check that a frame just taken from the freelist still holds the poison pattern, and poison the
+/// freelist link as well.
+///
+/// # Panics
+///
+/// Panics if the frame was written to while it was free.
+#[cfg(feature = "frame-poison")]
+fn check_poison(pfn: FrameNr) {
+    const LINK_SIZE: usize = core::mem::size_of::<Option<FrameNr>>();
+
+    let va = pa_to_va(pfn.pa());
+    // SAFETY: Frame was just taken from the freelist, so no other readers or writers exist.
+    let buf = unsafe { &mut *va.as_mut_ptr::<[u8; PAGE_SIZE]>() };
+
+    if let Some(offset) = buf[LINK_SIZE..].iter().position(|b| *b != POISON) {
+        let offset = LINK_SIZE + offset;
+        panic!("free frame modified: {pfn:?}, offset {offset:#x}");
     }
+    buf[..LINK_SIZE].fill(POISON);
 }
 
 /// Allocate a page frame.
@@ -52,6 +146,16 @@ pub(super) fn alloc_frame() -> FrameNr {
     ALLOC.lock().alloc()
 }
 
+/// Allocate `count` physically contiguous page frames, returning the lowest one.
+pub(super) fn alloc_contiguous_frames(count: usize) -> Option<FrameNr> {
+    ALLOC.lock().alloc_contiguous(count)
+}
+
+/// Return the number of free page frames.
+pub(super) fn free_frame_count() -> usize {
+    ALLOC.lock().free_count
+}
+
 /// Free the given page frame.
 ///
 /// # Safety
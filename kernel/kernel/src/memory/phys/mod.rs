@@ -2,6 +2,7 @@
 
 mod alloc;
 
+use ::alloc::vec::Vec;
 use core::{fmt, mem};
 use core::num::NonZeroU8;
 use core::sync::atomic::{self, AtomicU32, Ordering};
@@ -9,7 +10,7 @@ use core::sync::atomic::{self, AtomicU32, Ordering};
 use aarch64::memory::{PA, PAGE_SHIFT, PAGE_SIZE};
 use kstd::sync::Mutex;
 
-use self::alloc::{alloc_frame, free_frame};
+use self::alloc::{alloc_contiguous_frames, alloc_frame, free_frame, free_frame_count};
 use super::pa_to_va;
 
 static PMM: Mutex<PhysMemoryManager> = Mutex::new(PhysMemoryManager::new());
@@ -61,6 +62,19 @@ impl Frame {
     }
 }
 
+/// A counted reference to an allocated page frame.
+///
+/// A frame stays allocated as long as its refcount is non-zero. Two kinds of users contribute to
+/// the refcount:
+///
+///  * Every live `FrameRef` holds one reference, taken when it is created or cloned and released
+///    when it is dropped. Cloning a `FrameRef` is how a frame is shared between users.
+///  * Every page table entry mapping the frame holds one reference, its map count. Page table
+///    entries can't own a `FrameRef`, so mapping code takes and releases these references
+///    explicitly through [`FrameRef::inc_map`] and [`FrameRef::dec_map`].
+///
+/// A frame can therefore stay mapped after all `FrameRef`s to it were dropped, and it is freed once
+/// the last mapping is removed.
 pub struct FrameRef {
     frame: *const Frame,
 }
@@ -122,6 +136,14 @@ impl FrameRef {
     }
 }
 
+impl Clone for FrameRef {
+    fn clone(&self) -> Self {
+        // SAFETY: `self` holds a counted reference, so the frame is still tracked in the
+        // `FrameMap`.
+        unsafe { Self::new(self.frame()) }
+    }
+}
+
 impl Drop for FrameRef {
     fn drop(&mut self) {
         let frame = self.frame();
@@ -140,23 +162,77 @@ impl Drop for FrameRef {
     }
 }
 
+/// A run of physically contiguous page frames.
+///
+/// The frames are freed together when the range is dropped.
+pub struct FrameRefRange {
+    frames: Vec<FrameRef>,
+}
+
+impl FrameRefRange {
+    /// Return the physical address of the first frame.
+    pub fn pa(&self) -> PA {
+        self.frames[0].pa()
+    }
+
+    /// Return the number of frames in the range.
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+}
+
+/// Physical memory statistics.
+///
+/// Frames that are neither free nor allocated hold PMM metadata, like the `FrameMap` level pages.
+#[derive(Clone, Copy, Debug)]
+pub struct PhysStats {
+    /// Number of frames given to the PMM.
+    pub total_frames: usize,
+    /// Number of frames available for allocation.
+    pub free_frames: usize,
+    /// Number of frames currently allocated.
+    pub allocated_frames: usize,
+}
+
 /// Physical memory manager.
 struct PhysMemoryManager {
     frames: FrameMap,
+    /// Number of frames given to the PMM through `seed`.
+    total_frames: usize,
+    /// Number of frames in `frames`.
+    allocated_frames: usize,
 }
 
 impl PhysMemoryManager {
     const fn new() -> Self {
         Self {
             frames: FrameMap::new(),
+            total_frames: 0,
+            allocated_frames: 0,
         }
     }
 
     fn alloc(&mut self) -> FrameRef {
         let pfn = alloc_frame();
+        self.track(pfn)
+    }
+
+    /// Allocate `count` contiguous frames, pushing their references into `frames`.
+    fn alloc_contiguous(&mut self, count: usize, frames: &mut Vec<FrameRef>) -> Option<()> {
+        let base = alloc_contiguous_frames(count)?;
+        for i in 0..count {
+            let pfn = FrameNr(base.0 + i as u64);
+            frames.push(self.track(pfn));
+        }
+        Some(())
+    }
+
+    /// Start tracking a newly allocated frame.
+    fn track(&mut self, pfn: FrameNr) -> FrameRef {
         let frame = Frame::new(pfn);
         let old = self.frames.insert(pfn, frame);
         assert!(old.is_none());
+        self.allocated_frames += 1;
 
         self.get_alloc_frame(pfn).expect("inserted above")
     }
@@ -170,6 +246,7 @@ impl PhysMemoryManager {
             Some(frame) => assert_eq!(frame.refcount.load(Ordering::Acquire), 0),
             None => panic!("attempt to free unallocated frame: {pfn:?}"),
         }
+        self.allocated_frames -= 1;
 
         // SAFETY: Frame is known to have zero references.
         unsafe { free_frame(pfn) };
@@ -267,11 +344,89 @@ impl FrameMap {
     }
 }
 
+/// Benchmark `FrameMap` inserts and lookups, and log the results.
+///
+/// The benchmark uses a separate map and PFNs beyond any RAM, so it doesn't interfere with the
+/// PMM. Like all `FrameMap` level pages, the ones it allocates are never freed.
+#[cfg(feature = "bench")]
+pub fn bench_frame_map() {
+    use aarch64::Stopwatch;
+    use core::hint::black_box;
+
+    use crate::log;
+
+    const COUNT: u64 = 4096;
+    const BASE: u64 = 1 << 35;
+
+    let mut map = FrameMap::new();
+    let per_op = |phase: Stopwatch| phase.elapsed().as_nanos() / u128::from(COUNT);
+
+    let phase = Stopwatch::start();
+    for i in 0..COUNT {
+        let pfn = FrameNr(BASE + i);
+        black_box(map.insert(pfn, Frame::new(pfn)));
+    }
+    log!("bench: frame map insert: {} ns/op", per_op(phase));
+
+    let phase = Stopwatch::start();
+    for i in 0..COUNT {
+        black_box(map.get(black_box(FrameNr(BASE + i))));
+    }
+    log!("bench: frame map lookup (hit): {} ns/op", per_op(phase));
+
+    // Spread the lookups so each one ends in a different level 4 node, most of them missing.
+    let phase = Stopwatch::start();
+    for i in 0..COUNT {
+        black_box(map.get(black_box(FrameNr(BASE + i * 256))));
+    }
+    log!("bench: frame map lookup (sparse): {} ns/op", per_op(phase));
+
+    let phase = Stopwatch::start();
+    for i in 0..COUNT {
+        black_box(map.remove(FrameNr(BASE + i)));
+    }
+    log!("bench: frame map remove: {} ns/op", per_op(phase));
+}
+
 /// Allocate a page frame.
 pub fn alloc() -> FrameRef {
     PMM.lock().alloc()
 }
 
+/// Allocate `count` physically contiguous page frames.
+///
+/// Returns `None` if no large enough run of free frames exists.
+///
+/// # Panics
+///
+/// Panics if `count` is zero.
+pub fn alloc_contiguous(count: usize) -> Option<FrameRefRange> {
+    // Allocate the reference vector up front, since growing the heap needs the PMM lock.
+    let mut frames = Vec::with_capacity(count);
+    PMM.lock().alloc_contiguous(count, &mut frames)?;
+    Some(FrameRefRange { frames })
+}
+
+/// Allocate `count` physically contiguous page frames filled with zeroes.
+///
+/// Returns `None` if no large enough run of free frames exists.
+///
+/// # Panics
+///
+/// Panics if `count` is zero.
+pub fn alloc_contiguous_zero(count: usize) -> Option<FrameRefRange> {
+    let mut range = alloc_contiguous(count)?;
+    for frame in &mut range.frames {
+        frame.with_contents(|buf| buf.fill(0));
+    }
+    Some(range)
+}
+
+/// Whether the PMM lock is currently held.
+pub(super) fn is_locked() -> bool {
+    PMM.try_lock().is_none()
+}
+
 /// Allocate a page frame filled with zeroes.
 pub fn alloc_zero() -> FrameRef {
     let mut frame = alloc();
@@ -279,6 +434,71 @@ pub fn alloc_zero() -> FrameRef {
     frame
 }
 
+/// Allocate a run of contiguous frames and check that their addresses are consecutive, and that
+/// the PMM statistics account for them.
+pub(super) fn boot_test_contiguous() {
+    let before = stats();
+
+    let range = alloc_contiguous(4).expect("contiguous frames available");
+    assert_eq!(range.len(), 4);
+
+    let base = range.pa();
+    for (i, frame) in range.frames.iter().enumerate() {
+        assert_eq!(frame.pa(), base + i * PAGE_SIZE, "frames not contiguous");
+    }
+
+    // The reference vector lives on the heap, which might grow or shrink in the process, so the
+    // counts can move by more than the range itself.
+    let during = stats();
+    assert!(during.free_frames <= before.free_frames - 4);
+    assert!(during.allocated_frames >= before.allocated_frames + 4);
+
+    drop(range);
+    let after = stats();
+    assert!(after.free_frames >= during.free_frames + 4);
+    assert!(after.allocated_frames <= during.allocated_frames - 4);
+
+    crate::log!("boot-test: alloc_contiguous ok base={base:?}");
+}
+
+/// Free a frame and allocate it again, checking that it was poisoned in between, and that
+/// `alloc_zero` still clears it.
+#[cfg(feature = "frame-poison")]
+pub(super) fn boot_test_poison() {
+    let frame = alloc();
+    let pa = frame.pa();
+    drop(frame);
+
+    // The freelist is LIFO, so we get the same frame back.
+    let mut frame = alloc();
+    assert_eq!(frame.pa(), pa, "freed frame not reallocated");
+    frame.with_contents(|buf| {
+        assert!(
+            buf.iter().all(|b| *b == self::alloc::POISON),
+            "freed frame not poisoned"
+        );
+    });
+    drop(frame);
+
+    let mut frame = alloc_zero();
+    assert_eq!(frame.pa(), pa, "freed frame not reallocated");
+    frame.with_contents(|buf| {
+        assert!(buf.iter().all(|b| *b == 0), "frame not zeroed");
+    });
+
+    crate::log!("boot-test: frame poison ok");
+}
+
+/// Return physical memory statistics.
+pub fn stats() -> PhysStats {
+    let pmm = PMM.lock();
+    PhysStats {
+        total_frames: pmm.total_frames,
+        free_frames: free_frame_count(),
+        allocated_frames: pmm.allocated_frames,
+    }
+}
+
 /// Return a reference to an allocated frame.
 pub(super) fn get_alloc_frame(pfn: FrameNr) -> Option<FrameRef> {
     PMM.lock().get_alloc_frame(pfn)
@@ -290,6 +510,8 @@ pub(super) fn get_alloc_frame(pfn: FrameNr) -> Option<FrameRef> {
 ///
 /// The provided range must describe a valid RAM range. All memory in this range must be unused.
 pub(super) unsafe fn seed(start: PA, pages: usize) {
+    PMM.lock().total_frames += pages;
+
     let mut pa = start;
     for _ in 0..pages {
         let pfn = FrameNr::from_pa(pa);
@@ -2,25 +2,98 @@
 //!
 //! The kernel lives in high virtual memory:
 //!
-//!  0xffff000000000000 - 0xffff0000ffffffff    kernel code + data
+//!  0xffff000000000000 - 0xffff0000ffffefff    kernel code + data
+//!  0xffff0000fffff000 - 0xffff0000ffffffff    stack guard (4 KiB)
 //!  0xffff000100000000 - 0xffff000100003fff    stack (16 KiB)
 //!  0xffff000200000000 - 0xffff0002ffffffff    heap (4 GiB)
 //!  0xffff000300000000 - 0xffff0003ffffffff    userimg (4 GiB)
+//!  0xffff000400000000 - 0xffff0007ffffffff    reserved windows (16 GiB)
+//!  0xffff000800000000 - 0xffff0008ffffffff    task stacks (4 GiB)
 //!  0xffff100000000000 - 0xffffffffffffffff    physmap (240 TiB)
+//!
+//! The stack is always pinned: It is part of the kernel image, so the loader maps it eagerly, and
+//! it must never be unmapped, demand-paged, or reclaimed. Exception handlers run on this stack, so
+//! a fault on a stack access would recurse. `virt::init` asserts that the stack is fully mapped.
+//!
+//! The guard page below the stack is never mapped, so a stack overflow faults instead of silently
+//! corrupting kernel data. The synchronous EL1 exception vector switches to a separate overflow
+//! stack when the kernel stack is exhausted, so the fault can be reported.
+//!
+//! Stacks of spawned tasks are mapped on demand into slots of the task stack region. Each slot
+//! starts with an unmapped guard page of the same size as the kernel stack guard, followed by the
+//! stack, so task stack overflows are caught the same way.
 
 use core::arch::global_asm;
 use core::ffi::c_void;
 
-use aarch64::memory::VA;
+use aarch64::memory::{PAGE_SIZE, VA};
 
 pub const KERNEL_START: VA = VA::new(0xffff_0000_0000_0000);
+pub const KERNEL_SIZE: usize = (4 << 30) - KSTACK_GUARD_SIZE;
 pub const KSTACK_START: VA = VA::new(0xffff_0001_0000_0000);
 pub const KSTACK_SIZE: usize = 16 << 10;
+pub const KSTACK_GUARD_START: VA = VA::new(KSTACK_START.into_u64() - KSTACK_GUARD_SIZE as u64);
+pub const KSTACK_GUARD_SIZE: usize = PAGE_SIZE;
 pub const KHEAP_START: VA = VA::new(0xffff_0002_0000_0000);
 pub const KHEAP_SIZE: usize = 4 << 30;
 pub const USERIMG_START: VA = VA::new(0xffff_0003_0000_0000);
 pub const USERIMG_SIZE: usize = 4 << 30;
+pub const KWINDOW_START: VA = VA::new(0xffff_0004_0000_0000);
+pub const KWINDOW_SIZE: usize = 16 << 30;
+pub const KTASK_STACKS_START: VA = VA::new(0xffff_0008_0000_0000);
+pub const KTASK_STACKS_SIZE: usize = 4 << 30;
+pub const KTASK_STACK_SIZE: usize = 16 << 10;
+/// Size of a task stack slot, holding a guard page and the stack above it.
+pub const KTASK_STACK_SLOT_SIZE: usize = KSTACK_GUARD_SIZE + KTASK_STACK_SIZE;
 pub const PHYSMAP_START: VA = VA::new(0xffff_1000_0000_0000);
+pub const PHYSMAP_SIZE: usize = 240 << 40;
+
+/// All regions of the kernel virtual memory layout, as `(start, size)` pairs.
+const REGIONS: [(u64, usize); 7] = [
+    (KERNEL_START.into_u64(), KERNEL_SIZE),
+    (
+        KSTACK_GUARD_START.into_u64(),
+        KSTACK_GUARD_SIZE + KSTACK_SIZE,
+    ),
+    (KHEAP_START.into_u64(), KHEAP_SIZE),
+    (USERIMG_START.into_u64(), USERIMG_SIZE),
+    (KWINDOW_START.into_u64(), KWINDOW_SIZE),
+    (KTASK_STACKS_START.into_u64(), KTASK_STACKS_SIZE),
+    (PHYSMAP_START.into_u64(), PHYSMAP_SIZE),
+];
+
+// Check that the layout regions lie in the kernel VA range and don't overlap.
+const _: () = {
+    let mut i = 0;
+    while i < REGIONS.len() {
+        let (start, end) = region_bounds(REGIONS[i]);
+        assert!(
+            start >= KERNEL_START.into_u64() as u128,
+            "region outside kernel VA range"
+        );
+        assert!(end <= 1 << 64, "region outside kernel VA range");
+
+        let mut j = i + 1;
+        while j < REGIONS.len() {
+            let (other_start, other_end) = region_bounds(REGIONS[j]);
+            assert!(
+                end <= other_start || other_end <= start,
+                "overlapping regions"
+            );
+            j += 1;
+        }
+
+        i += 1;
+    }
+};
+
+/// Return the bounds of the given region as a `[start, end)` range.
+///
+/// Computed in `u128` so regions ending at the top of the address space don't overflow.
+const fn region_bounds((start, size): (u64, usize)) -> (u128, u128) {
+    let start = start as u128;
+    (start, start + size as u128)
+}
 
 global_asm!(
     r#"
@@ -4,12 +4,16 @@ mod layout;
 mod page_map;
 mod page_table;
 
+use alloc::vec::Vec;
 use core::fmt;
 use core::ops::{Add, AddAssign, Sub, SubAssign};
 
 use aarch64::instruction::{dsb_ishst, isb};
-use aarch64::memory::paging::{Flags, load_ttbr1, tlb_invalidate_all};
-use aarch64::memory::{PA, PAGE_SHIFT, VA};
+use aarch64::memory::paging::{
+    AccessPermissions, Flags, MairIndexes, Shareability, load_ttbr1, tlb_invalidate,
+    tlb_invalidate_all,
+};
+use aarch64::memory::{BLOCK_SIZE, PA, PAGE_SHIFT, PAGE_SIZE, VA, va_to_pa};
 use kstd::sync::Mutex;
 
 use crate::memory::phys::{self, FrameNr, FrameRef};
@@ -71,11 +75,73 @@ impl fmt::Debug for PageNr {
     }
 }
 
+/// The class of memory mapped by a page.
+///
+/// The memory class determines the memory attributes and, where applicable, the access
+/// permissions of a mapping.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MemoryClass {
+    /// Normal, cacheable memory.
+    Normal,
+    /// Device memory.
+    Device,
+    /// Device memory that can only be read.
+    ///
+    /// Useful for device regions that should never be written, to turn accidental writes into
+    /// faults.
+    DeviceReadOnly,
+    /// Normal, non-cacheable memory, for framebuffers.
+    ///
+    /// Unlike device memory, this allows merging writes and unaligned accesses, while writes
+    /// still reach the display without cache maintenance. Falls back to device memory if the
+    /// firmware didn't configure a non-cacheable memory attribute.
+    Framebuffer,
+}
+
+impl MemoryClass {
+    /// Return the MAIR attribute index for this memory class.
+    pub fn mair_index(&self, mair: &MairIndexes) -> u8 {
+        match self {
+            Self::Normal => mair.normal,
+            Self::Device | Self::DeviceReadOnly => mair.device,
+            Self::Framebuffer => mair.normal_nc.unwrap_or(mair.device),
+        }
+    }
+
+    /// Return the shareability domain for this memory class.
+    pub fn shareability(&self) -> Shareability {
+        match self {
+            Self::Normal => Shareability::Inner,
+            Self::Device | Self::DeviceReadOnly | Self::Framebuffer => Shareability::Outer,
+        }
+    }
+
+    /// Return the kernel access permissions for this memory class.
+    pub fn access_permissions(&self) -> AccessPermissions {
+        match self {
+            Self::Normal | Self::Device | Self::Framebuffer => AccessPermissions::PrivRW,
+            Self::DeviceReadOnly => AccessPermissions::PrivRO,
+        }
+    }
+}
+
 struct VirtMemoryManager {
     kernel_map: KernelPageMap,
+    /// Start of the not yet reserved part of the window region.
+    window_break: VA,
 }
 
 impl VirtMemoryManager {
+    fn reserve_range(&mut self, pages: usize) -> VA {
+        let start = self.window_break;
+        let end = start + pages * PAGE_SIZE;
+        let limit = KWINDOW_START + KWINDOW_SIZE;
+        assert!(end <= limit, "window region exhausted");
+
+        self.window_break = end;
+        start
+    }
+
     fn map_data_page(&mut self, vpn: PageNr, frame: FrameRef) {
         let flags = Flags::default().privileged_execute_never(true);
         self.kernel_map.map_ram_page(vpn, frame, flags);
@@ -87,9 +153,75 @@ impl VirtMemoryManager {
         isb();
     }
 
-    fn map_mmio_page(&mut self, vpn: PageNr, pfn: FrameNr) {
+    fn map_cow_page(&mut self, vpn: PageNr, frame: FrameRef) {
         let flags = Flags::default().privileged_execute_never(true);
-        self.kernel_map.map_mmio_page(vpn, pfn, flags);
+        self.kernel_map.map_cow_page(vpn, frame, flags);
+
+        // Wait for the new mapping to become visible.
+        dsb_ishst();
+        isb();
+    }
+
+    /// Give the copy-on-write page `vpn` its own copy of the frame it maps, and make it writable.
+    ///
+    /// Returns `false` if the page isn't mapped copy-on-write.
+    fn resolve_cow_fault(&mut self, vpn: PageNr) -> bool {
+        let Some(pfn) = self.kernel_map.cow_frame(vpn) else {
+            return false;
+        };
+        let old = phys::get_alloc_frame(pfn).expect("COW page maps an allocated frame");
+
+        let mut new = phys::alloc();
+        let src = pa_to_va(old.pa()).as_ptr::<[u8; PAGE_SIZE]>();
+        // SAFETY: The frame is still mapped copy-on-write, so nobody writes to it through a
+        //         kernel mapping.
+        new.with_contents(|buf| buf.copy_from_slice(unsafe { &*src }));
+
+        // Break-before-make: The old mapping must be gone from the TLB before the new one is
+        // inserted. If this was the last mapping of the old frame, it is freed when `old` is
+        // dropped.
+        self.unmap_page(vpn);
+        self.map_data_page(vpn, new);
+        true
+    }
+
+    fn map_mmio_block(&mut self, vpn: PageNr, pa: PA, class: MemoryClass) {
+        let flags = Flags::default().privileged_execute_never(true);
+        self.kernel_map.map_block_2m(vpn, pa, class, flags);
+
+        // Wait for the new mapping to become visible.
+        dsb_ishst();
+        isb();
+    }
+
+    fn unmap_page(&mut self, vpn: PageNr) {
+        // Only the heap, window and task stack regions are mapped through `map_data_page` and
+        // `map_mmio_page`. Other regions, like the kernel image or the physmap, are not
+        // map-counted and must stay mapped.
+        let va = vpn.va();
+        let in_heap = va >= KHEAP_START && va < KHEAP_START + KHEAP_SIZE;
+        let in_window = va >= KWINDOW_START && va < KWINDOW_START + KWINDOW_SIZE;
+        let in_task_stacks =
+            va >= KTASK_STACKS_START && va < KTASK_STACKS_START + KTASK_STACKS_SIZE;
+        assert!(
+            in_heap || in_window || in_task_stacks,
+            "page {vpn:?} can't be unmapped"
+        );
+
+        // SAFETY: Heap, window and task stack pages are mapped through `map_ram_page` or
+        //         `map_mmio_page`.
+        unsafe { self.kernel_map.unmap_page(vpn) };
+        tlb_invalidate(va, PAGE_SIZE);
+    }
+
+    fn protect_page(&mut self, vpn: PageNr, flags: Flags) {
+        self.kernel_map.protect(vpn, flags);
+        tlb_invalidate(vpn.va(), PAGE_SIZE);
+    }
+
+    fn map_mmio_page(&mut self, vpn: PageNr, pfn: FrameNr, class: MemoryClass) {
+        let flags = Flags::default().privileged_execute_never(true);
+        self.kernel_map.map_mmio_page(vpn, pfn, class, flags);
 
         // Wait for the new mapping to become visible.
         // Note that we don't need to TLBI here, since there wasn't a valid mapping for the VA before
@@ -123,7 +255,79 @@ pub(super) unsafe fn init() {
     // that still point to the old page tables.
     tlb_invalidate_all();
 
-    *vmm = Some(VirtMemoryManager { kernel_map });
+    verify_kstack_pinned();
+
+    *vmm = Some(VirtMemoryManager {
+        kernel_map,
+        window_break: KWINDOW_START,
+    });
+}
+
+/// Verify that the kernel stack is fully mapped, and its guard page isn't.
+///
+/// The exception handlers run on the kernel stack, so a fault while accessing the stack would
+/// fault again recursively. The stack must therefore never be demand-paged or reclaimed.
+fn verify_kstack_pinned() {
+    let kstack_end = VA::new(&raw const KSTACK_END as u64);
+    assert_eq!(
+        kstack_end,
+        KSTACK_START + KSTACK_SIZE,
+        "unexpected kernel stack end"
+    );
+
+    let mut va = KSTACK_START;
+    while va < kstack_end {
+        assert!(
+            va_to_pa(va).is_some(),
+            "kernel stack page not mapped: {va:?}"
+        );
+        va += PAGE_SIZE;
+    }
+
+    let guard = KSTACK_GUARD_START;
+    assert!(
+        va_to_pa(guard).is_none(),
+        "kernel stack guard page mapped: {guard:?}"
+    );
+}
+
+/// Return the base addresses of all page tables backing the kernel page map, in ascending order.
+pub fn page_table_frames() -> Vec<PA> {
+    let vmm = VMM.lock();
+    vmm.as_ref()
+        .expect("vmm initialized")
+        .kernel_map
+        .table_frames()
+}
+
+/// Look up the physical address and mapping flags of a kernel page.
+///
+/// Returns `None` if the page isn't mapped.
+pub fn lookup(vpn: PageNr) -> Option<(PA, Flags)> {
+    let vmm = VMM.lock();
+    vmm.as_ref()
+        .expect("vmm initialized")
+        .kernel_map
+        .lookup(vpn)
+}
+
+/// Describe the kernel page map, passing one line per contiguous run of mapped pages to `out`.
+///
+/// See [`KernelPageMap::dump`] for the format.
+#[cfg(feature = "monitor")]
+pub fn dump_kernel_map(out: impl FnMut(fmt::Arguments<'_>)) {
+    let vmm = VMM.lock();
+    vmm.as_ref().expect("vmm initialized").kernel_map.dump(out);
+}
+
+/// Reserve a range of `pages` virtual pages in the kernel address space.
+///
+/// The returned range is not backed by any mappings, but it is guaranteed to be disjoint from all
+/// other reserved ranges, so the caller is free to map into it. Reserved ranges are never
+/// released.
+pub fn reserve_range(pages: usize) -> VA {
+    let mut vmm = VMM.lock();
+    vmm.as_mut().expect("vmm initialized").reserve_range(pages)
 }
 
 pub fn map_data_page(vpn: PageNr) {
@@ -135,12 +339,290 @@ pub fn map_data_page(vpn: PageNr) {
         .map_data_page(vpn, frame);
 }
 
-pub fn map_mmio_page(pfn: FrameNr) {
+/// Map an already allocated frame to the given kernel page.
+///
+/// Unlike [`map_data_page`], this doesn't allocate a new frame, so the same frame can be mapped at
+/// several pages by passing clones of its [`FrameRef`]. The mapping holds its own reference to the
+/// frame, which is released by [`unmap_page`].
+pub fn map_shared(vpn: PageNr, frame: FrameRef) {
+    let mut vmm = VMM.lock();
+    vmm.as_mut()
+        .expect("vmm initialized")
+        .map_data_page(vpn, frame);
+}
+
+/// Map an already allocated frame to the given kernel page, copy-on-write.
+///
+/// The page is mapped read-only. The first write to it faults, and [`handle_cow_fault`] then
+/// replaces the mapping with a writable one to a private copy of the frame. Reads keep sharing
+/// the frame with its other users.
+pub fn map_cow(vpn: PageNr, frame: FrameRef) {
+    let mut vmm = VMM.lock();
+    vmm.as_mut()
+        .expect("vmm initialized")
+        .map_cow_page(vpn, frame);
+}
+
+/// Handle a write permission fault at `va` in the kernel address space.
+///
+/// If the faulting page is mapped copy-on-write, it is given a writable copy of its frame and
+/// `true` is returned, so the faulting access can be retried. Otherwise the fault is a genuine
+/// protection violation and `false` is returned.
+///
+/// Faults taken while the VMM or PMM lock is held are never resolved, and `false` is returned.
+pub fn handle_cow_fault(va: VA) -> bool {
+    let page_mask = PAGE_SIZE as u64 - 1;
+    let vpn = PageNr::from_va(VA::new(va.into_u64() & !page_mask));
+
+    // This runs for every kernel write permission fault, including ones taken inside the memory
+    // managers. Locking them again would panic inside the fault handler and hide the original
+    // fault, so we bail out and let the caller report it as unhandled. Resolving a COW fault needs
+    // both locks, so such a fault couldn't be resolved anyway.
+    let Some(mut vmm) = VMM.try_lock() else {
+        return false;
+    };
+    if phys::is_locked() {
+        return false;
+    }
+    let Some(vmm) = vmm.as_mut() else {
+        return false;
+    };
+    vmm.resolve_cow_fault(vpn)
+}
+
+/// Unmap a page from the kernel heap or window regions.
+///
+/// If the page maps RAM, its frame is freed once it has no other users.
+///
+/// # Panics
+///
+/// Panics if the page isn't mapped, or outside the heap and window regions.
+pub fn unmap_page(vpn: PageNr) {
+    let mut vmm = VMM.lock();
+    vmm.as_mut().expect("vmm initialized").unmap_page(vpn);
+}
+
+/// Change the access permissions of a mapped kernel page.
+///
+/// Only the access permission and execute-never bits are taken from `flags`; the memory
+/// attributes of the mapping stay unchanged.
+///
+/// # Panics
+///
+/// Panics if the page isn't mapped, or is part of a block mapping.
+pub fn protect_page(vpn: PageNr, flags: Flags) {
+    let mut vmm = VMM.lock();
+    vmm.as_mut()
+        .expect("vmm initialized")
+        .protect_page(vpn, flags);
+}
+
+/// Map a page into a fresh window slot, unmap it again, and check that the translation is gone.
+pub(super) fn boot_test_unmap() {
+    let va = reserve_range(1);
+    let vpn = PageNr::from_va(va);
+
+    map_data_page(vpn);
+    assert!(va_to_pa(va).is_some(), "page not mapped: {va:?}");
+
+    unmap_page(vpn);
+    assert!(va_to_pa(va).is_none(), "page still mapped: {va:?}");
+
+    crate::log!("boot-test: unmap_page ok");
+}
+
+/// Map a writable page, make it read-only, and check that writes are no longer permitted.
+pub(super) fn boot_test_protect() {
+    use aarch64::memory::paging::AccessPermissions;
+    use aarch64::memory::va_to_pa_write;
+
+    let va = reserve_range(1);
+    let vpn = PageNr::from_va(va);
+
+    map_data_page(vpn);
+    assert!(va_to_pa_write(va).is_some(), "page not writable: {va:?}");
+
+    let flags = Flags::default()
+        .access_permissions(AccessPermissions::PrivRO)
+        .privileged_execute_never(true);
+    protect_page(vpn, flags);
+    assert!(va_to_pa(va).is_some(), "page not readable: {va:?}");
+    assert!(va_to_pa_write(va).is_none(), "page still writable: {va:?}");
+
+    unmap_page(vpn);
+
+    crate::log!("boot-test: protect_page ok");
+}
+
+/// Map a page with specific flags, and check that `lookup` reports them, for pages as well as for
+/// block mappings.
+pub(super) fn boot_test_lookup() {
+    let va = reserve_range(1);
+    let vpn = PageNr::from_va(va);
+
+    map_data_page(vpn);
+    let flags = Flags::default()
+        .access_permissions(AccessPermissions::PrivRO)
+        .privileged_execute_never(true);
+    protect_page(vpn, flags);
+
+    let class = MemoryClass::Normal;
+    let expected = flags
+        .access_flag(true)
+        .unprivileged_execute_never(true)
+        .attr_idx(class.mair_index(&MairIndexes::read()))
+        .shareability(class.shareability());
+    let (pa, found) = lookup(vpn).expect("page mapped");
+    assert_eq!(Some(pa), va_to_pa(va), "lookup disagrees with AT");
+    assert_eq!(found, expected, "lookup returned wrong flags");
+
+    unmap_page(vpn);
+    assert!(lookup(vpn).is_none(), "page still mapped");
+
+    // Most of the physmap is mapped with blocks, so this usually exercises the block path too.
+    let block_va = pa_to_va(pa);
+    let (block_pa, _) = lookup(PageNr::from_va(block_va)).expect("physmap mapped");
+    assert_eq!(
+        Some(block_pa),
+        va_to_pa(block_va),
+        "block lookup disagrees with AT"
+    );
+
+    crate::log!("boot-test: lookup ok");
+}
+
+/// Map one frame at two window pages, and check that writes through one mapping are visible
+/// through the other, and that the frame outlives its first mapping.
+pub(super) fn boot_test_map_shared() {
+    let frame = phys::alloc_zero();
+    let pa = frame.pa();
+
+    let va = reserve_range(2);
+    let vpn = PageNr::from_va(va);
+    map_shared(vpn, frame.clone());
+    map_shared(vpn + 1, frame);
+
+    assert_eq!(va_to_pa(va), Some(pa));
+    assert_eq!(va_to_pa(va + PAGE_SIZE), Some(pa));
+
+    let a = va.as_mut_ptr::<u64>();
+    let b = (va + PAGE_SIZE).as_mut_ptr::<u64>();
+    // SAFETY: Both pages were mapped above and are not otherwise used.
+    let value = unsafe {
+        a.write_volatile(0x5eed);
+        b.read_volatile()
+    };
+    assert_eq!(value, 0x5eed, "write not visible in shared mapping");
+
+    // The second mapping keeps the frame allocated.
+    unmap_page(vpn);
+    assert!(
+        phys::get_alloc_frame(FrameNr::from_pa(pa)).is_some(),
+        "shared frame freed while mapped"
+    );
+    // SAFETY: The second page is still mapped.
+    assert_eq!(unsafe { b.read_volatile() }, 0x5eed);
+
+    unmap_page(vpn + 1);
+    assert!(
+        phys::get_alloc_frame(FrameNr::from_pa(pa)).is_none(),
+        "shared frame not freed after unmap"
+    );
+
+    crate::log!("boot-test: map_shared ok");
+}
+
+/// Map a frame copy-on-write at two window pages, write through one of them, and check that only
+/// the written page got a private copy of the frame.
+pub(super) fn boot_test_cow() {
+    let mut frame = phys::alloc();
+    frame.with_contents(|buf| buf.fill(0x11));
+    let pa = frame.pa();
+
+    let va = reserve_range(2);
+    let vpn = PageNr::from_va(va);
+    map_cow(vpn, frame.clone());
+    map_cow(vpn + 1, frame);
+
+    let a = va.as_mut_ptr::<u8>();
+    let b = (va + PAGE_SIZE).as_mut_ptr::<u8>();
+    // SAFETY: Both pages were mapped above and are not otherwise used. The write faults and is
+    //         resolved by the COW handler.
+    let (a0, a1, b0) = unsafe {
+        a.write_volatile(0x22);
+        (
+            a.read_volatile(),
+            a.add(1).read_volatile(),
+            b.read_volatile(),
+        )
+    };
+    assert_eq!((a0, a1), (0x22, 0x11), "COW copy has wrong contents");
+    assert_eq!(b0, 0x11, "write visible through other COW mapping");
+
+    let a_pa = va_to_pa(va).expect("COW page mapped");
+    assert_ne!(a_pa, pa, "written page still maps the shared frame");
+    assert_eq!(va_to_pa(va + PAGE_SIZE), Some(pa));
+
+    unmap_page(vpn);
+    unmap_page(vpn + 1);
+    assert!(
+        phys::get_alloc_frame(FrameNr::from_pa(pa)).is_none(),
+        "shared frame not freed after unmap"
+    );
+
+    crate::log!("boot-test: copy-on-write ok");
+}
+
+pub fn map_mmio_page(pfn: FrameNr, class: MemoryClass) {
     let va = pa_to_va(pfn.pa());
     let vpn = PageNr::from_va(va);
 
     let mut vmm = VMM.lock();
     vmm.as_mut()
         .expect("vmm initialized")
-        .map_mmio_page(vpn, pfn);
+        .map_mmio_page(vpn, pfn, class);
+}
+
+/// Map `pages` contiguous MMIO page frames, starting at `pfn`, to the virtual window pages
+/// starting at `vpn`, using only page mappings.
+///
+/// The pages can be unmapped again with [`unmap_page`].
+pub fn map_mmio_pages(vpn: PageNr, pfn: FrameNr, pages: usize, class: MemoryClass) {
+    let mut vmm = VMM.lock();
+    let vmm = vmm.as_mut().expect("vmm initialized");
+
+    for i in 0..pages {
+        let pa = pfn.pa() + i * PAGE_SIZE;
+        vmm.map_mmio_page(vpn + i as u64, FrameNr::from_pa(pa), class);
+    }
+}
+
+/// Map `pages` contiguous MMIO page frames, starting at `pfn`, to the virtual pages starting at
+/// `vpn`.
+///
+/// Parts of the range that are suitably aligned are mapped with [`BLOCK_SIZE`] blocks, so the
+/// range can't be unmapped again. Use [`map_mmio_pages`] for ranges that should be unmappable.
+pub fn map_mmio_range(vpn: PageNr, pfn: FrameNr, pages: usize, class: MemoryClass) {
+    const BLOCK_PAGES: usize = BLOCK_SIZE / PAGE_SIZE;
+
+    let mut vmm = VMM.lock();
+    let vmm = vmm.as_mut().expect("vmm initialized");
+
+    let mut vpn = vpn;
+    let mut pa = pfn.pa();
+    let mut remaining = pages;
+    while remaining > 0 {
+        let aligned = vpn.va().is_aligned_to(BLOCK_SIZE) && pa.is_aligned_to(BLOCK_SIZE);
+        if aligned && remaining >= BLOCK_PAGES {
+            vmm.map_mmio_block(vpn, pa, class);
+            vpn += BLOCK_PAGES as u64;
+            pa += BLOCK_SIZE;
+            remaining -= BLOCK_PAGES;
+        } else {
+            vmm.map_mmio_page(vpn, FrameNr::from_pa(pa), class);
+            vpn += 1;
+            pa += PAGE_SIZE;
+            remaining -= 1;
+        }
+    }
 }
@@ -1,11 +1,17 @@
-use aarch64::memory::paging::{AccessPermissions, Flags, MairIndexes, Shareability};
-use aarch64::memory::{PA, VA};
+use alloc::vec::Vec;
+#[cfg(feature = "monitor")]
+use core::fmt;
+
+#[cfg(feature = "monitor")]
+use aarch64::memory::PAGE_SIZE;
+use aarch64::memory::paging::{AccessPermissions, Flags, MairIndexes};
+use aarch64::memory::{BLOCK_SIZE, PA, VA};
 use aarch64::register::TTBR1_EL1;
 
 use crate::memory::phys::{self, FrameNr, FrameRef};
 
-use super::PageNr;
-use super::page_table::{PageDesc, PageTable, PageTableRef};
+use super::page_table::{BlockDesc, Mapping, PageDesc, PageTable, PageTableRef, SW_COW};
+use super::{MemoryClass, PageNr};
 
 /// A virtual memory page map.
 pub struct PageMap {
@@ -26,10 +32,11 @@ impl PageMap {
     }
 
     pub fn map_ram_page(&mut self, vpn: PageNr, frame: FrameRef, flags: Flags) {
+        let class = MemoryClass::Normal;
         let flags = flags
             .access_flag(true)
-            .attr_idx(self.mair_idx.normal)
-            .shareability(Shareability::Inner);
+            .attr_idx(class.mair_index(&self.mair_idx))
+            .shareability(class.shareability());
         let desc = PageDesc::new(frame.pa(), flags);
 
         frame.inc_map();
@@ -37,6 +44,76 @@ impl PageMap {
         unsafe { self.insert(vpn, desc) }
     }
 
+    /// Remove the mapping of a page.
+    ///
+    /// If the page maps an allocated frame, its map count is released. The caller is responsible
+    /// for invalidating the TLB entry for `vpn`.
+    ///
+    /// # Safety
+    ///
+    /// If the page maps an allocated frame, it must have been mapped with its map count
+    /// incremented, like [`PageMap::map_ram_page`] does.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page isn't mapped.
+    pub unsafe fn unmap_page(&mut self, vpn: PageNr) {
+        let desc = self
+            .remove(vpn)
+            .unwrap_or_else(|| panic!("page {vpn:?} not mapped"));
+
+        // Frames not known to the PMM (e.g. MMIO frames) aren't map-counted.
+        let pfn = FrameNr::from_pa(desc.output_addr());
+        if let Some(frame) = phys::get_alloc_frame(pfn) {
+            // SAFETY: Caller ensures that `inc_map` was called when the page was mapped.
+            unsafe { frame.dec_map() };
+        }
+    }
+
+    /// Change the access permissions of a mapped page.
+    ///
+    /// Only the access permission and execute-never bits are taken from `flags`. The memory
+    /// attributes stay unchanged, since changing them on a live mapping would require
+    /// break-before-make. The caller is responsible for invalidating the TLB entry for `vpn`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the page isn't mapped.
+    pub fn protect(&mut self, vpn: PageNr, flags: Flags) {
+        self.update(vpn, |desc| desc.with_permissions(flags))
+            .unwrap_or_else(|| panic!("page {vpn:?} not mapped"));
+    }
+
+    /// Return the valid page descriptor for `vpn`, if any.
+    pub(super) fn lookup(&self, vpn: PageNr) -> Option<PageDesc> {
+        let l1 = self.level0.get(vpn)?;
+        let l2 = l1.get(vpn)?;
+        let l3 = l2.get(vpn)?;
+        l3.get(vpn)
+    }
+
+    /// Replace the valid page descriptor for `vpn` with `f(desc)`, returning the old descriptor.
+    fn update(&mut self, vpn: PageNr, f: impl FnOnce(PageDesc) -> PageDesc) -> Option<PageDesc> {
+        let mut l1 = self.level0.get_mut(vpn)?;
+        let mut l2 = l1.get_mut(vpn)?;
+        let mut l3 = l2.get_mut(vpn)?;
+
+        let desc = l3.get(vpn)?;
+        l3.set(vpn, f(desc));
+        Some(desc)
+    }
+
+    /// Clear the page descriptor for `vpn`, returning the old descriptor if it was valid.
+    fn remove(&mut self, vpn: PageNr) -> Option<PageDesc> {
+        let mut l1 = self.level0.get_mut(vpn)?;
+        let mut l2 = l1.get_mut(vpn)?;
+        let mut l3 = l2.get_mut(vpn)?;
+
+        let desc = l3.get(vpn)?;
+        l3.clear(vpn);
+        Some(desc)
+    }
+
     /// # Safety
     ///
     /// The caller must ensure that map counting is handled correctly for the mapped frame, either
@@ -54,12 +131,40 @@ impl PageMap {
 
         l3.set(vpn, desc);
     }
+
+    /// # Safety
+    ///
+    /// The caller must ensure that the block is never unmapped again. Block mappings aren't map
+    /// counted.
+    unsafe fn insert_block(&mut self, vpn: PageNr, desc: BlockDesc) {
+        assert!(
+            vpn.va().is_aligned_to(BLOCK_SIZE),
+            "unaligned block: {vpn:?}"
+        );
+
+        let l0 = &mut self.level0;
+        let mut l1 = l0.get_or_insert(vpn);
+        let mut l2 = l1.get_or_insert(vpn);
+
+        assert!(
+            l2.get(vpn).is_none() && l2.get_block(vpn).is_none(),
+            "block {vpn:?} already mapped"
+        );
+
+        l2.set_block(vpn, desc);
+    }
 }
 
 impl Drop for PageMap {
     fn drop(&mut self) {
         let start_vpn = PageNr::from_va(VA::new(0));
-        self.level0.walk(start_vpn, |vpn, desc| {
+        self.level0.walk(start_vpn, |vpn, mapping| {
+            // Blocks are inserted through `PageMap::insert_block`, which requires that they are
+            // never unmapped.
+            let Mapping::Page(desc) = mapping else {
+                return;
+            };
+
             let base = desc.output_addr();
             let pfn = FrameNr::from_pa(base);
             let frame = phys::get_alloc_frame(pfn).unwrap_or_else(|| {
@@ -92,33 +197,189 @@ impl KernelPageMap {
 
         let mut map = PageMap::new();
         let start_vpn = PageNr::from_va(VA::new(0));
-        pt.walk(start_vpn, |vpn, desc| {
+        pt.walk(start_vpn, |vpn, mapping| match mapping {
             // SAFETY: Page is never unmapped again.
-            unsafe { map.insert(vpn, desc) }
+            Mapping::Page(desc) => unsafe { map.insert(vpn, desc) },
+            // SAFETY: Block is never unmapped again.
+            Mapping::Block(desc) => unsafe { map.insert_block(vpn, desc) },
         });
 
         Self(map)
     }
 
+    /// Return the base addresses of all page tables in this map, in ascending order.
+    pub fn table_frames(&self) -> Vec<PA> {
+        let mut frames = Vec::new();
+        self.0.level0.walk_tables(|pa| frames.push(pa));
+        frames.sort_unstable();
+        frames
+    }
+
     pub fn map_ram_page(&mut self, vpn: PageNr, frame: FrameRef, flags: Flags) {
-        let flags = flags
-            .access_permissions(AccessPermissions::PrivRW)
-            .unprivileged_execute_never(true);
+        let flags = self.class_flags(MemoryClass::Normal, flags);
         self.0.map_ram_page(vpn, frame, flags);
     }
 
-    pub fn map_mmio_page(&mut self, vpn: PageNr, pfn: FrameNr, flags: Flags) {
-        let flags = flags
-            .access_flag(true)
-            .attr_idx(self.0.mair_idx.device)
-            .shareability(Shareability::Outer)
-            .access_permissions(AccessPermissions::PrivRW)
-            .unprivileged_execute_never(true);
+    /// Describe all mappings in this map, one line per contiguous run of pages with the same
+    /// attributes, like `0xffff000000000000-0xffff000000008000 rw- xn normal`.
+    ///
+    /// Each line is passed to `out`, without a trailing newline.
+    #[cfg(feature = "monitor")]
+    pub fn dump(&self, mut out: impl FnMut(fmt::Arguments<'_>)) {
+        // The page tables only index the lower 48 bits of a VA.
+        const TTBR1_BASE: u64 = 0xffff << 48;
+
+        let mut run: Option<(u64, u64, DumpAttrs)> = None;
+        let mut emit = |(start, end, attrs): (u64, u64, DumpAttrs)| {
+            out(format_args!("{start:#018x}-{end:#018x} {attrs}"));
+        };
+
+        let start_vpn = PageNr::from_va(VA::new(0));
+        self.0.level0.walk(start_vpn, |vpn, mapping| {
+            let (flags, size) = match mapping {
+                Mapping::Page(desc) => (desc.flags(), PAGE_SIZE),
+                Mapping::Block(desc) => (desc.flags(), BLOCK_SIZE),
+            };
+            let attrs = DumpAttrs::new(flags, &self.0.mair_idx);
+            let start = TTBR1_BASE | vpn.va().into_u64();
+            let end = start + size as u64;
+
+            match &mut run {
+                Some((_, run_end, run_attrs)) if *run_end == start && *run_attrs == attrs => {
+                    *run_end = end;
+                }
+                _ => {
+                    if let Some(prev) = run.replace((start, end, attrs)) {
+                        emit(prev);
+                    }
+                }
+            }
+        });
+
+        if let Some(last) = run {
+            emit(last);
+        }
+    }
+
+    /// Translate `vpn` by walking the page tables in software, returning the physical address it
+    /// maps to and the flags of the mapping.
+    ///
+    /// Unlike [`aarch64::memory::va_to_pa`], this also works when the map isn't active. Pages
+    /// inside block mappings are reported with the flags of the block.
+    pub fn lookup(&self, vpn: PageNr) -> Option<(PA, Flags)> {
+        let l1 = self.0.level0.get(vpn)?;
+        let l2 = l1.get(vpn)?;
+        if let Some(block) = l2.get_block(vpn) {
+            let offset = vpn.va().into_u64() as usize % BLOCK_SIZE;
+            return Some((block.output_addr() + offset, block.flags()));
+        }
+
+        let desc = l2.get(vpn)?.get(vpn)?;
+        Some((desc.output_addr(), desc.flags()))
+    }
+
+    /// Map a RAM page read-only and mark it copy-on-write.
+    ///
+    /// Writes to the page fault. [`KernelPageMap::cow_frame`] tells these faults apart from
+    /// writes to pages that are read-only for good.
+    pub fn map_cow_page(&mut self, vpn: PageNr, frame: FrameRef, flags: Flags) {
+        let flags = self
+            .class_flags(MemoryClass::Normal, flags)
+            .access_permissions(AccessPermissions::PrivRO)
+            .software(SW_COW);
+        self.0.map_ram_page(vpn, frame, flags);
+    }
+
+    /// Return the frame mapped at `vpn`, if the page is mapped copy-on-write.
+    pub fn cow_frame(&self, vpn: PageNr) -> Option<FrameNr> {
+        let desc = self.0.lookup(vpn)?;
+        desc.is_cow().then(|| FrameNr::from_pa(desc.output_addr()))
+    }
+
+    /// Remove the mapping of a page.
+    ///
+    /// # Safety
+    ///
+    /// The page must have been mapped through [`KernelPageMap::map_ram_page`],
+    /// [`KernelPageMap::map_cow_page`] or [`KernelPageMap::map_mmio_page`].
+    pub unsafe fn unmap_page(&mut self, vpn: PageNr) {
+        // SAFETY: RAM pages are mapped through `PageMap::map_ram_page`. MMIO frames aren't
+        //         allocated frames.
+        unsafe { self.0.unmap_page(vpn) };
+    }
+
+    /// Change the access permissions of a mapped page.
+    ///
+    /// See [`PageMap::protect`]. Kernel pages always stay unprivileged execute-never.
+    pub fn protect(&mut self, vpn: PageNr, flags: Flags) {
+        self.0.protect(vpn, flags.unprivileged_execute_never(true));
+    }
+
+    pub fn map_mmio_page(&mut self, vpn: PageNr, pfn: FrameNr, class: MemoryClass, flags: Flags) {
+        let flags = self.class_flags(class, flags);
         let desc = PageDesc::new(pfn.pa(), flags);
 
-        // SAFETY: Page is never unmapped again.
+        // SAFETY: MMIO frames aren't allocated frames, so they aren't map counted.
         unsafe { self.0.insert(vpn, desc) }
     }
+
+    /// Map a [`BLOCK_SIZE`] block of memory that is never unmapped again.
+    ///
+    /// Both `vpn` and `pa` must be aligned to [`BLOCK_SIZE`].
+    pub fn map_block_2m(&mut self, vpn: PageNr, pa: PA, class: MemoryClass, flags: Flags) {
+        let flags = self.class_flags(class, flags);
+        let desc = BlockDesc::new(pa, flags);
+
+        // SAFETY: Block is never unmapped again.
+        unsafe { self.0.insert_block(vpn, desc) }
+    }
+
+    /// Apply the memory attributes and access permissions for the given [`MemoryClass`].
+    fn class_flags(&self, class: MemoryClass, flags: Flags) -> Flags {
+        flags
+            .access_flag(true)
+            .access_permissions(class.access_permissions())
+            .unprivileged_execute_never(true)
+            .attr_idx(class.mair_index(&self.0.mair_idx))
+            .shareability(class.shareability())
+    }
+}
+
+/// The mapping attributes shown by [`KernelPageMap::dump`].
+#[cfg(feature = "monitor")]
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct DumpAttrs {
+    writable: bool,
+    user: bool,
+    execute_never: bool,
+    device: bool,
+}
+
+#[cfg(feature = "monitor")]
+impl DumpAttrs {
+    fn new(flags: Flags, mair_idx: &MairIndexes) -> Self {
+        let bits = u64::from(flags);
+        let attr_idx = (bits >> 2) & 0b111;
+        Self {
+            writable: bits & (1 << 7) == 0,
+            user: bits & (1 << 6) != 0,
+            execute_never: bits & (1 << 53) != 0,
+            device: attr_idx == u64::from(mair_idx.device),
+        }
+    }
+}
+
+#[cfg(feature = "monitor")]
+impl fmt::Display for DumpAttrs {
+    /// Format as access permissions (`r`, `w`, and `u` if EL0 has access), execute permission at
+    /// EL1, and memory type.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let w = if self.writable { 'w' } else { '-' };
+        let u = if self.user { 'u' } else { '-' };
+        let x = if self.execute_never { "xn" } else { "x " };
+        let memory = if self.device { "device" } else { "normal" };
+        write!(f, "r{w}{u} {x} {memory}")
+    }
 }
 
 impl Drop for KernelPageMap {
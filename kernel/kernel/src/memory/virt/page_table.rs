@@ -6,7 +6,7 @@ use core::ops::{Deref, DerefMut};
 
 use aarch64::instruction::{dsb_ishst, isb};
 use aarch64::memory::paging::Flags;
-use aarch64::memory::{PA, PAGE_SIZE};
+use aarch64::memory::{BLOCK_SIZE, PA, PAGE_SIZE};
 
 use crate::memory::phys::FrameNr;
 use crate::memory::virt::PageNr;
@@ -41,6 +41,22 @@ impl<const L: u64> PageTable<L> {
     fn desc(&self) -> TableDesc {
         TableDesc::new(self.base)
     }
+
+    /// Return the block descriptor at the given index, if any.
+    ///
+    /// Only level 2 tables can contain block descriptors.
+    pub fn get_block<I>(&self, idx: I) -> Option<BlockDesc>
+    where
+        I: PageTableIndex<L>,
+    {
+        if L != 2 {
+            return None;
+        }
+
+        let ptr = pa_to_va(self.base).as_mut_ptr::<BlockDesc>();
+        let desc = unsafe { ptr.add(idx.index()).read_volatile() };
+        desc.valid().then_some(desc)
+    }
 }
 
 macro_rules! pt_table_level {
@@ -84,22 +100,35 @@ macro_rules! pt_table_level {
             where
                 I: PageTableIndex<$level>,
             {
+                assert!(self.get_block(idx).is_none(), "entry maps a block");
+
                 if self.get(idx).is_none() {
                     self.set(idx, PageTable::new());
                 }
                 self.get_mut(idx).unwrap()
             }
 
-            pub fn walk(&self, vpn: PageNr, mut f: impl FnMut(PageNr, PageDesc)) {
+            pub fn walk(&self, vpn: PageNr, mut f: impl FnMut(PageNr, Mapping)) {
                 let mut va = vpn.va();
                 let va_step: u64 = 1 << (39 - 9 * $level);
                 for idx in 0..Self::LEN {
                     if let Some(child) = self.get(idx) {
                         child.walk(PageNr::from_va(va), &mut f);
+                    } else if let Some(block) = self.get_block(idx) {
+                        f(PageNr::from_va(va), Mapping::Block(block));
                     }
                     va += va_step;
                 }
             }
+
+            pub fn walk_tables(&self, mut f: impl FnMut(PA)) {
+                f(self.base);
+                for idx in 0..Self::LEN {
+                    if let Some(child) = self.get(idx) {
+                        child.walk_tables(&mut f);
+                    }
+                }
+            }
         }
     };
 }
@@ -108,6 +137,21 @@ pt_table_level!(0 -> 1);
 pt_table_level!(1 -> 2);
 pt_table_level!(2 -> 3);
 
+impl PageTable<2> {
+    /// Map a block at the given index.
+    ///
+    /// The entry must not contain a valid table descriptor, which would otherwise be leaked.
+    pub fn set_block<I>(&mut self, idx: I, desc: BlockDesc)
+    where
+        I: PageTableIndex<2>,
+    {
+        debug_assert!(self.get(idx).is_none());
+
+        let ptr = pa_to_va(self.base).as_mut_ptr::<BlockDesc>();
+        unsafe { ptr.add(idx.index()).write_volatile(desc) };
+    }
+}
+
 impl PageTable<3> {
     pub fn get<I>(&self, idx: I) -> Option<PageDesc>
     where
@@ -126,15 +170,28 @@ impl PageTable<3> {
         unsafe { ptr.add(idx.index()).write_volatile(desc) };
     }
 
-    pub fn walk(&self, vpn: PageNr, mut f: impl FnMut(PageNr, PageDesc)) {
+    /// Invalidate the entry at the given index.
+    pub fn clear<I>(&mut self, idx: I)
+    where
+        I: PageTableIndex<3>,
+    {
+        let ptr = pa_to_va(self.base).as_mut_ptr::<u64>();
+        unsafe { ptr.add(idx.index()).write_volatile(0) };
+    }
+
+    pub fn walk(&self, vpn: PageNr, mut f: impl FnMut(PageNr, Mapping)) {
         let mut va = vpn.va();
         for idx in 0..Self::LEN {
             if let Some(desc) = self.get(idx) {
-                f(PageNr::from_va(va), desc);
+                f(PageNr::from_va(va), Mapping::Page(desc));
             }
             va += PAGE_SIZE;
         }
     }
+
+    pub fn walk_tables(&self, mut f: impl FnMut(PA)) {
+        f(self.base);
+    }
 }
 
 impl<const L: u64> Drop for PageTable<L> {
@@ -237,6 +294,9 @@ impl<const L: u64> PageTableIndex<L> for PageNr {
     }
 }
 
+/// Software descriptor bit marking a copy-on-write page, see [`Flags::software`].
+pub(super) const SW_COW: u8 = 0b0001;
+
 /// A page descriptor.
 #[derive(Clone, Copy, Debug, Default)]
 #[repr(transparent)]
@@ -254,6 +314,54 @@ impl PageDesc {
     pub fn output_addr(&self) -> PA {
         PA::new(self.0 & 0xfffffffff000)
     }
+
+    pub fn flags(&self) -> Flags {
+        Flags::from_descriptor(self.0)
+    }
+
+    /// Return whether the page is mapped copy-on-write.
+    pub fn is_cow(&self) -> bool {
+        let software = (self.0 >> 55) as u8 & 0b1111;
+        software & SW_COW != 0
+    }
+
+    /// Return a copy of this descriptor, with the access permission and execute-never bits taken
+    /// from `flags`.
+    pub fn with_permissions(self, flags: Flags) -> Self {
+        const MASK: u64 = (0b11 << 6) | (1 << 53) | (1 << 54);
+        Self((self.0 & !MASK) | (u64::from(flags) & MASK))
+    }
+}
+
+/// A block descriptor, mapping a [`BLOCK_SIZE`] region from a level 2 table.
+#[derive(Clone, Copy, Debug, Default)]
+#[repr(transparent)]
+pub(super) struct BlockDesc(u64);
+
+impl BlockDesc {
+    pub fn new(base: PA, flags: Flags) -> Self {
+        assert!(base.is_aligned_to(BLOCK_SIZE), "unaligned block: {base:?}");
+        Self(u64::from(base) | u64::from(flags) | 0b01)
+    }
+
+    fn valid(&self) -> bool {
+        self.0 & 0b11 == 0b01
+    }
+
+    pub fn output_addr(&self) -> PA {
+        PA::new(self.0 & 0xffffffe00000)
+    }
+
+    pub fn flags(&self) -> Flags {
+        Flags::from_descriptor(self.0)
+    }
+}
+
+/// A leaf entry found while walking a page table.
+#[derive(Clone, Copy, Debug)]
+pub(super) enum Mapping {
+    Page(PageDesc),
+    Block(BlockDesc),
 }
 
 /// A table descriptor.
@@ -0,0 +1,146 @@
+//! A debug monitor operated over the log UART.
+//!
+//! The monitor reads command lines from the UART and executes them. Supported commands:
+//!
+//!  * `mem`: print memory statistics
+//!  * `pagemap`: print the mapped ranges of the kernel page map
+//!  * `pci`: list discovered PCI functions, their BARs and capabilities
+//!  * `peek <addr>`: read the 64-bit word at the given physical address
+//!  * `reboot`: reset the system
+//!  * `continue`: leave the monitor and continue booting
+
+use aarch64::memory::{PA, va_to_pa};
+use aarch64::psci;
+
+use crate::log;
+use crate::memory::virt::PHYSMAP_SIZE;
+use crate::memory::{pa_to_va, phys, virt};
+use crate::pci::Function;
+use crate::uart;
+
+const LINE_MAX: usize = 64;
+
+const BACKSPACE: u8 = 0x08;
+const DELETE: u8 = 0x7f;
+
+macro_rules! println {
+    () => {{
+        log::write(format_args!("\n"));
+    }};
+    ($($arg:tt)*) => {{
+        log::write(format_args!("{}\n", format_args!($($arg)*)));
+    }};
+}
+
+/// Run the debug monitor, until the `continue` command is entered.
+pub fn run(pci_functions: &[Function]) {
+    println!("entering debug monitor");
+
+    let mut line = [0; LINE_MAX];
+    loop {
+        log::write(format_args!("> "));
+        let len = read_line(&mut line);
+
+        // `read_line` only accepts printable ASCII characters.
+        let line = core::str::from_utf8(&line[..len]).unwrap();
+        if line.trim() == "continue" {
+            return;
+        }
+
+        execute(line, pci_functions);
+    }
+}
+
+fn execute(line: &str, pci_functions: &[Function]) {
+    let mut args = line.split(' ').filter(|s| !s.is_empty());
+    let Some(cmd) = args.next() else {
+        return;
+    };
+
+    match cmd {
+        "mem" => cmd_mem(),
+        "pagemap" => virt::dump_kernel_map(|line| println!("{line}")),
+        "pci" => cmd_pci(pci_functions),
+        "peek" => match args.next().and_then(parse_u64) {
+            Some(addr) => cmd_peek(PA::new(addr)),
+            None => println!("usage: peek <addr>"),
+        },
+        "reboot" => psci::system_reset(),
+        _ => println!("unknown command: {cmd}"),
+    }
+}
+
+fn cmd_mem() {
+    let stats = phys::stats();
+    println!("total frames: {}", stats.total_frames);
+    println!("free frames: {}", stats.free_frames);
+    println!("allocated frames: {}", stats.allocated_frames);
+
+    let page_tables = virt::page_table_frames().len();
+    println!("kernel page tables: {page_tables}");
+}
+
+fn cmd_pci(functions: &[Function]) {
+    for func in functions {
+        println!("{func}");
+        for bar in func.bars() {
+            println!("  {bar}");
+        }
+        for cap in func.capabilities() {
+            println!("  capability {cap}");
+        }
+    }
+}
+
+fn cmd_peek(pa: PA) {
+    if !pa.is_aligned_to(8) {
+        println!("address not aligned: {pa}");
+        return;
+    }
+    if pa.into_u64() >= PHYSMAP_SIZE as u64 {
+        println!("address outside the physmap: {pa}");
+        return;
+    }
+
+    // Only read through existing mappings, so a bad address can't fault the kernel.
+    let va = pa_to_va(pa);
+    if va_to_pa(va).is_none() {
+        println!("address not mapped: {pa}");
+        return;
+    }
+
+    // SAFETY: `va` is aligned and mapped.
+    let value = unsafe { va.as_ptr::<u64>().read_volatile() };
+    println!("{pa}: {value:#018x}");
+}
+
+/// Read a line of input into `buf`, echoing it back. Returns the line length.
+fn read_line(buf: &mut [u8; LINE_MAX]) -> usize {
+    let mut len = 0;
+    loop {
+        let b = uart::console_read_byte();
+        match b {
+            b'\r' | b'\n' => {
+                println!();
+                return len;
+            }
+            BACKSPACE | DELETE if len > 0 => {
+                len -= 1;
+                log::write(format_args!("\x08 \x08"));
+            }
+            b' '..=b'~' if len < LINE_MAX => {
+                buf[len] = b;
+                len += 1;
+                log::write(format_args!("{}", b as char));
+            }
+            _ => (),
+        }
+    }
+}
+
+fn parse_u64(s: &str) -> Option<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -3,10 +3,12 @@
 use core::mem;
 
 use aarch64::memory::PA;
+use alloc::vec;
 use alloc::vec::Vec;
 
 use crate::memory::{mmio, pa_to_va};
 use crate::pci::{Function, Sbdf};
+use crate::warn;
 
 pub(super) struct Discovery {
     acpi_rsdp: *const acpi::RSDP,
@@ -34,93 +36,72 @@ impl Discovery {
     }
 
     fn find_config_allocations(&self) -> Vec<ConfigAllocation> {
-        let rsdp = unsafe { &*self.acpi_rsdp };
-
-        assert_eq!(rsdp.signature, *b"RSD PTR ");
-        assert_eq!(rsdp.revision, 2);
-
-        let xsdt_pa = PA::new(rsdp.xsdt_address);
-        let xsdt_ptr: *const acpi::XSDT = pa_to_va(xsdt_pa).as_ptr();
-        let xsdt = unsafe { &*xsdt_ptr };
-        assert_eq!(xsdt.header.signature, *b"XSDT");
-        assert_eq!(xsdt.header.revision, 1);
-
-        let xsdt_size = xsdt.header.length as usize;
-        let mut entry_size = xsdt_size - mem::offset_of!(acpi::XSDT, entry);
-        let mut entry_ptr = xsdt.entry.as_ptr();
-
-        let mut mcfg: Option<&acpi::MCFG> = None;
-        const ADDR_SIZE: usize = mem::size_of::<usize>();
-        while entry_size >= ADDR_SIZE {
-            let addr_bytes_ptr = entry_ptr as *mut [u8; ADDR_SIZE];
-            let addr_bytes = unsafe { *addr_bytes_ptr };
-            let addr = u64::from_le_bytes(addr_bytes);
-
-            let desc_pa = PA::new(addr);
-            let desc_ptr: *const acpi::DESCRIPTION_HEADER = pa_to_va(desc_pa).as_ptr();
-            let desc = unsafe { &*desc_ptr };
-
-            if desc.signature == *b"MCFG" {
-                mcfg = Some(unsafe { &*desc_ptr.cast() });
-                break;
-            }
-
-            entry_ptr = unsafe { entry_ptr.add(ADDR_SIZE) };
-            entry_size -= ADDR_SIZE;
+        let xsdt = unsafe {
+            acpi::Xsdt::with_phys_offset(self.acpi_rsdp, pa_to_va(PA::new(0)).into_u64())
         }
-
-        let mcfg = mcfg.expect("MCFG table present");
+        .expect("valid ACPI tables");
+        let mcfg_ptr = xsdt.find_table(b"MCFG").expect("MCFG table present");
+        let mcfg = unsafe { &*mcfg_ptr.cast::<acpi::MCFG>() };
         assert!(mcfg.header.revision == 1 || mcfg.header.revision == 2);
 
-        let mcfg_size = mcfg.header.length as usize;
-        let allocations_size = mcfg_size - mem::offset_of!(acpi::MCFG, allocations);
-        let mut allocation_ptr: *const acpi::MCFG_Allocation = mcfg.allocations.as_ptr().cast();
-
-        const ALLOCATION_SIZE: usize = mem::size_of::<acpi::MCFG_Allocation>();
-        let num_allocations = allocations_size / ALLOCATION_SIZE;
-
-        let mut allocations = Vec::with_capacity(num_allocations);
-        for _ in 0..num_allocations {
-            let allocation: &acpi::MCFG_Allocation = unsafe { &*allocation_ptr };
+        let allocations = unsafe { mcfg.allocations() };
+        let allocations = allocations.map(|allocation| ConfigAllocation {
+            segment: allocation.segment,
+            start_bus: allocation.start_bus_number,
+            end_bus: allocation.end_bus_number,
+            base_address: PA::new(allocation.base_address),
+        });
 
-            allocations.push(ConfigAllocation {
-                segment: allocation.segment,
-                start_bus: allocation.start_bus_number,
-                end_bus: allocation.end_bus_number,
-                base_address: PA::new(allocation.base_address),
-            });
-
-            allocation_ptr = unsafe { allocation_ptr.add(1) };
-        }
-
-        allocations
+        allocations.collect()
     }
 
+    /// Enumerate the functions on all buses of the given allocation.
+    ///
+    /// A single allocation can contain the root buses of several host bridges (e.g. with QEMU's
+    /// pxb-pcie), so every bus in the range is scanned. Buses behind PCI-to-PCI bridges are
+    /// enumerated right after the bridge, and skipped when the scan reaches them again.
     fn enumerate_functions(&mut self, alloc: &ConfigAllocation) {
-        let mut cursor = alloc.cursor();
-        while cursor.valid() {
-            self.probe_device(&mut cursor);
-            cursor.step_device();
+        let mut visited = [false; 256];
+
+        for root_bus in alloc.start_bus..=alloc.end_bus {
+            let mut buses = vec![root_bus];
+            while let Some(bus_nr) = buses.pop() {
+                // Also guards against misconfigured bridges forming loops.
+                if mem::replace(&mut visited[usize::from(bus_nr)], true) {
+                    continue;
+                }
+
+                let mut cursor = alloc.cursor(bus_nr);
+                while cursor.valid() {
+                    self.probe_device(&mut cursor, &mut buses);
+                    cursor.step_device();
+                }
+            }
         }
     }
 
-    fn probe_device(&mut self, cursor: &mut Cursor<'_>) {
-        let multi_fn = self.probe_function(cursor);
+    fn probe_device(&mut self, cursor: &mut Cursor<'_>, buses: &mut Vec<u8>) {
+        let multi_fn = self.probe_function(cursor, buses);
         if !multi_fn {
             return;
         }
 
         cursor.step_function();
         while cursor.valid() {
-            self.probe_function(cursor);
+            self.probe_function(cursor, buses);
             cursor.step_function();
         }
     }
 
-    fn probe_function(&mut self, cursor: &mut Cursor<'_>) -> bool {
+    /// Probe the function at the cursor, queueing the secondary bus if it is a bridge.
+    ///
+    /// Returns whether the function belongs to a multi-function device.
+    fn probe_function(&mut self, cursor: &mut Cursor<'_>, buses: &mut Vec<u8>) -> bool {
         let pa = cursor.config_address();
         let fun = unsafe {
-            let config_space = mmio::claim_page(pa);
+            // Discovery only ever reads config space, so map it read-only to catch accidental
+            // writes.
+            let config_space = mmio::claim_page_ro(pa);
             Function::new(cursor.sbdf(), config_space)
         };
 
@@ -128,6 +109,14 @@ impl Discovery {
             return false;
         }
 
+        if let Some(bus_nr) = fun.secondary_bus() {
+            if cursor.alloc.contains_bus(bus_nr) {
+                buses.push(bus_nr);
+            } else {
+                warn!("{fun}: secondary bus {bus_nr:02x} outside config allocation");
+            }
+        }
+
         let multi_fn = fun.multi_function();
         self.functions.push(fun);
 
@@ -144,16 +133,24 @@ struct ConfigAllocation {
 }
 
 impl ConfigAllocation {
-    fn cursor(&self) -> Cursor<'_> {
+    fn contains_bus(&self, bus_nr: u8) -> bool {
+        (self.start_bus..=self.end_bus).contains(&bus_nr)
+    }
+
+    /// Return a cursor over the functions on the given bus.
+    fn cursor(&self, bus_nr: u8) -> Cursor<'_> {
+        assert!(self.contains_bus(bus_nr));
+
         Cursor {
             alloc: self,
-            bus_nr: self.start_bus,
+            bus_nr,
             dev_nr: 0,
             fun_nr: 0,
         }
     }
 }
 
+/// A position on a single bus.
 struct Cursor<'a> {
     alloc: &'a ConfigAllocation,
     bus_nr: u8,
@@ -163,18 +160,12 @@ struct Cursor<'a> {
 
 impl Cursor<'_> {
     fn valid(&self) -> bool {
-        self.bus_nr <= self.alloc.end_bus && self.dev_nr < 32 && self.fun_nr < 8
+        self.dev_nr < 32 && self.fun_nr < 8
     }
 
     fn step_device(&mut self) {
-        if self.dev_nr < 32 {
-            self.dev_nr += 1;
-            self.fun_nr = 0
-        } else {
-            self.bus_nr += 1;
-            self.dev_nr = 0;
-            self.fun_nr = 0;
-        }
+        self.dev_nr += 1;
+        self.fun_nr = 0;
     }
 
     fn step_function(&mut self) {
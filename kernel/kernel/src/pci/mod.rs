@@ -2,16 +2,22 @@ mod discover;
 mod id;
 
 use alloc::vec::Vec;
-use core::{fmt, mem};
+use core::cell::{RefCell, RefMut};
+use core::{fmt, iter, mem};
 
-use crate::log;
-use crate::memory::mmio::MmioPage;
+use aarch64::memory::PA;
+
+use crate::log::Level;
+use crate::memory::mmio::{self, Mmio, MmioPage, MmioRegion};
 use crate::pci::discover::Discovery;
+use crate::{debug, log};
 
 #[derive(Debug)]
 pub struct Function {
     sbdf: Sbdf,
     config_space: MmioPage,
+    /// Writable alias of `config_space`, mapped on first use.
+    config_alias: RefCell<Option<MmioRegion>>,
 }
 
 impl Function {
@@ -19,21 +25,25 @@ impl Function {
     ///
     /// `config_space` must point to a valid PCIe config space.
     unsafe fn new(sbdf: Sbdf, config_space: MmioPage) -> Self {
-        Self { sbdf, config_space }
+        Self {
+            sbdf,
+            config_space,
+            config_alias: RefCell::new(None),
+        }
     }
 
-    fn read_config_word(&self, idx: usize) -> u32 {
+    pub fn read_config_word(&self, idx: usize) -> u32 {
         assert!(idx < 1024);
 
         let offset = idx * mem::size_of::<u32>();
         unsafe { self.config_space.read(offset) }
     }
 
-    fn vendor_id(&self) -> u16 {
+    pub fn vendor_id(&self) -> u16 {
         self.read_config_word(0) as u16
     }
 
-    fn device_id(&self) -> u16 {
+    pub fn device_id(&self) -> u16 {
         let w = self.read_config_word(0);
         (w >> 16) as u16
     }
@@ -57,6 +67,306 @@ impl Function {
         let w = self.read_config_word(3);
         w & (1 << 23) != 0
     }
+
+    fn header_type(&self) -> u8 {
+        let w = self.read_config_word(3);
+        (w >> 16) as u8 & 0x7f
+    }
+
+    /// Return the secondary bus number, if this function is a PCI-to-PCI bridge.
+    fn secondary_bus(&self) -> Option<u8> {
+        if self.header_type() != 1 {
+            return None;
+        }
+
+        let w = self.read_config_word(6);
+        Some((w >> 8) as u8)
+    }
+
+    fn num_bars(&self) -> usize {
+        match self.header_type() {
+            0 => 6,
+            1 => 2,
+            _ => 0,
+        }
+    }
+
+    /// Return a writable alias of this function's config space.
+    ///
+    /// Config space is mapped read-only during discovery, to catch accidental writes. Code that
+    /// needs to write config registers must go through this explicit alias instead. The alias is
+    /// mapped on first use and kept for the lifetime of the `Function`, since window ranges are
+    /// never released.
+    fn config_space_mut(&self) -> RefMut<'_, MmioRegion> {
+        RefMut::map(self.config_alias.borrow_mut(), |alias| {
+            // SAFETY: `config_space` points to a valid PCIe config space, and the alias is only
+            //         used through `Function` methods.
+            alias.get_or_insert_with(|| unsafe { MmioRegion::map(self.config_space.pa(), 1) })
+        })
+    }
+
+    /// Decode the BAR with the given index.
+    ///
+    /// Returns `None` if the BAR isn't implemented, or if it is the upper half of a 64-bit BAR.
+    fn bar(&self, index: usize) -> Option<Bar> {
+        if index >= self.num_bars() {
+            return None;
+        }
+
+        let mut config = self.config_space_mut();
+        let offset = (4 + index) * mem::size_of::<u32>();
+
+        // SAFETY: The command register and BARs are writable config registers. We disable
+        //         decoding while sizing the BAR, so the device never responds to the bogus
+        //         addresses we write, and restore the original values afterwards.
+        unsafe {
+            let command: u16 = config.read(COMMAND_OFFSET);
+            config.write(COMMAND_OFFSET, command & !(COMMAND_IO | COMMAND_MEMORY));
+
+            let bar = size_bar(&mut config, index, offset);
+
+            config.write(COMMAND_OFFSET, command);
+            bar
+        }
+    }
+
+    /// Iterate over the function's implemented BARs.
+    ///
+    /// 64-bit memory BARs occupy two consecutive registers and are returned only once, with the
+    /// index of their lower half.
+    ///
+    /// Sizing a BAR briefly disables the function's decoding, so this should only be called on
+    /// demand. Functions that are in use, like the framebuffer's display device, stop responding
+    /// for the duration.
+    pub fn bars(&self) -> impl Iterator<Item = Bar> + '_ {
+        let mut index = 0;
+        iter::from_fn(move || {
+            while index < self.num_bars() {
+                let bar = self.bar(index);
+                index += 1;
+
+                if let Some(bar) = bar {
+                    if let BarKind::Memory { is_64bit: true, .. } = bar.kind {
+                        index += 1;
+                    }
+                    return Some(bar);
+                }
+            }
+            None
+        })
+    }
+
+    /// Iterate over the function's capability list.
+    pub fn capabilities(&self) -> impl Iterator<Item = Capability> + '_ {
+        let status = (self.read_config_word(1) >> 16) as u16;
+        let mut ptr = if status & STATUS_CAPABILITIES != 0 {
+            self.read_config_word(CAPABILITIES_POINTER_OFFSET / 4) as u8 & !0x3
+        } else {
+            0
+        };
+
+        let mut count = 0;
+        iter::from_fn(move || {
+            if ptr == 0 || count >= CAPABILITIES_MAX {
+                return None;
+            }
+            count += 1;
+
+            let offset = ptr;
+            let header = self.read_config_word(usize::from(offset) / 4);
+            ptr = (header >> 8) as u8 & !0x3;
+
+            Some(Capability {
+                id: header as u8,
+                offset,
+            })
+        })
+    }
+
+    /// Allow the function to respond to memory accesses and to perform DMA.
+    pub fn enable_bus_mastering(&self) {
+        let mut config = self.config_space_mut();
+
+        // SAFETY: The command register is a writable config register.
+        unsafe {
+            let command: u16 = config.read(COMMAND_OFFSET);
+            config.write(
+                COMMAND_OFFSET,
+                command | COMMAND_MEMORY | COMMAND_BUS_MASTER,
+            );
+        }
+    }
+
+    /// Map the memory BAR with the given index into the kernel address space.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the BAR isn't implemented, isn't a memory BAR, or wasn't assigned an address by
+    /// the firmware.
+    pub fn map_bar(&self, index: usize) -> MmioRegion {
+        let Some(bar) = self.bar(index) else {
+            panic!("[{}] BAR{index} not implemented", self.sbdf);
+        };
+        let BarKind::Memory { .. } = bar.kind else {
+            panic!("[{}] BAR{index} is not a memory BAR", self.sbdf);
+        };
+        assert!(bar.base != 0, "[{}] BAR{index} not assigned", self.sbdf);
+
+        let size = usize::try_from(bar.size).expect("BAR size fits usize");
+
+        // SAFETY: The BAR references the function's MMIO registers, which are owned by this
+        //         `Function`.
+        unsafe { mmio::map_region(PA::new(bar.base), size) }
+    }
+}
+
+const COMMAND_OFFSET: usize = 0x4;
+const COMMAND_IO: u16 = 1 << 0;
+const COMMAND_MEMORY: u16 = 1 << 1;
+const COMMAND_BUS_MASTER: u16 = 1 << 2;
+
+const STATUS_CAPABILITIES: u16 = 1 << 4;
+const CAPABILITIES_POINTER_OFFSET: usize = 0x34;
+
+/// Upper bound on the length of a capability list, to guard against malformed loops.
+const CAPABILITIES_MAX: usize = 48;
+
+/// Decode and size the BAR at the given config space offset.
+///
+/// # Safety
+///
+/// `offset` must point to a BAR in `config`, and decoding must be disabled for the function.
+unsafe fn size_bar(config: &mut MmioRegion, index: usize, offset: usize) -> Option<Bar> {
+    // Sizing works by writing all ones to the BAR and reading back the value, which has zeros in
+    // all address bits below the BAR's size.
+    let mut probe = |offset: usize| unsafe {
+        let orig: u32 = config.read(offset);
+        config.write(offset, u32::MAX);
+        let mask: u32 = config.read(offset);
+        config.write(offset, orig);
+        (orig, mask)
+    };
+
+    let (lo, lo_mask) = probe(offset);
+
+    if lo & 0x1 != 0 {
+        let base = u64::from(lo & !0x3);
+        // I/O BARs may only implement the lower 16 address bits.
+        let mut mask = lo_mask & !0x3;
+        if mask >> 16 == 0 {
+            mask |= 0xffff_0000;
+        }
+        let size = u64::from(!mask) + 1;
+        return (mask != 0xffff_0000).then_some(Bar {
+            index,
+            kind: BarKind::Io,
+            base,
+            size,
+        });
+    }
+
+    let prefetchable = lo & 0x8 != 0;
+    let is_64bit = match (lo >> 1) & 0x3 {
+        0b00 => false,
+        0b10 => true,
+        _ => return None,
+    };
+
+    let (base, mask) = if is_64bit {
+        let (hi, hi_mask) = probe(offset + mem::size_of::<u32>());
+        (
+            u64::from(hi) << 32 | u64::from(lo),
+            u64::from(hi_mask) << 32 | u64::from(lo_mask),
+        )
+    } else {
+        (u64::from(lo), 0xffff_ffff_0000_0000 | u64::from(lo_mask))
+    };
+
+    let base = base & !0xf;
+    let mask = mask & !0xf;
+    if mask == 0 || mask == 0xffff_ffff_0000_0000 {
+        return None;
+    }
+
+    Some(Bar {
+        index,
+        kind: BarKind::Memory {
+            is_64bit,
+            prefetchable,
+        },
+        base,
+        size: !mask + 1,
+    })
+}
+
+/// A decoded Base Address Register.
+#[derive(Clone, Copy, Debug)]
+pub struct Bar {
+    /// Index of the (lower) BAR register.
+    pub index: usize,
+    pub kind: BarKind,
+    /// Address assigned by the firmware, or zero if unassigned.
+    pub base: u64,
+    /// Size of the region, in bytes.
+    pub size: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BarKind {
+    Io,
+    Memory { is_64bit: bool, prefetchable: bool },
+}
+
+impl fmt::Display for Bar {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BAR{}: ", self.index)?;
+        match self.kind {
+            BarKind::Io => write!(f, "io")?,
+            BarKind::Memory {
+                is_64bit,
+                prefetchable,
+            } => {
+                write!(f, "{}", if is_64bit { "mem64" } else { "mem32" })?;
+                if prefetchable {
+                    write!(f, " prefetchable")?;
+                }
+            }
+        }
+        write!(f, " base={:#x} size={:#x}", self.base, self.size)
+    }
+}
+
+/// An entry in a function's capability list.
+#[derive(Clone, Copy, Debug)]
+pub struct Capability {
+    pub id: u8,
+    /// Config space offset of the capability header, in bytes.
+    pub offset: u8,
+}
+
+impl Capability {
+    pub const MSI: u8 = 0x05;
+    pub const VENDOR: u8 = 0x09;
+    pub const PCIE: u8 = 0x10;
+    pub const MSI_X: u8 = 0x11;
+
+    /// Index of the config word containing the capability header.
+    pub fn word_idx(&self) -> usize {
+        usize::from(self.offset) / 4
+    }
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self.id {
+            Self::MSI => "msi",
+            Self::VENDOR => "vendor",
+            Self::PCIE => "pcie",
+            Self::MSI_X => "msi-x",
+            id => return write!(f, "{id:#04x}@{:#x}", self.offset),
+        };
+        write!(f, "{name}@{:#x}", self.offset)
+    }
 }
 
 impl fmt::Display for Function {
@@ -125,7 +435,30 @@ pub unsafe fn discover(acpi_rsdp: *const acpi::RSDP) -> Vec<Function> {
     let functions = unsafe { Discovery::new(acpi_rsdp).run() };
     for func in &functions {
         log!("  {func}");
+        // Sizing BARs writes config space, so only do it when the result gets logged.
+        if log::enabled(Level::Debug) {
+            for bar in func.bars() {
+                debug!("    {bar}");
+            }
+        }
+        for cap in func.capabilities() {
+            debug!("    capability {cap}");
+        }
     }
 
     functions
 }
+
+/// Log a deterministic summary of the discovered functions, for `cargo xtask boot-test` to assert
+/// on.
+pub fn log_summary(functions: &[Function]) {
+    let count = functions.len();
+    match functions.first() {
+        Some(first) => log!(
+            "pci summary: count={count} first={} vendor={:04x}",
+            first.sbdf,
+            first.vendor_id(),
+        ),
+        None => log!("pci summary: count=0"),
+    }
+}
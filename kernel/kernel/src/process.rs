@@ -3,44 +3,197 @@ use alloc::vec::Vec;
 use core::arch::asm;
 use kstd::io;
 
+use aarch64::instruction::{dsb_ishst, isb};
 use aarch64::memory::paging::{AccessPermissions, Flags, load_ttbr0};
 use aarch64::memory::{PAGE_SIZE, VA};
 use elf::ElfFile;
+use kstd::sync::Mutex;
 
 use crate::memory::phys;
-use crate::memory::virt::{PageMap, PageNr};
+use crate::memory::virt::{PageMap, PageNr, USERIMG_SIZE};
 use crate::userimg;
 
-const STACK_TOP: VA = VA::new(0x0001_0000_0000_0000);
+/// End of the userspace address range.
+pub const USER_END: VA = VA::new(0x0001_0000_0000_0000);
+
+const STACK_TOP: VA = USER_END;
 const STACK_SIZE: usize = 16 << 10;
 
 const HEAP_START: VA = VA::new(0x0000_1000_0000_0000);
 const HEAP_SIZE: usize = 10 << 20;
+/// Upper bound for the heap size, including growth through `sbrk`.
+const HEAP_MAX: usize = 256 << 20;
+
+/// Address range in which `mmap` places anonymous mappings.
+const MMAP_START: VA = VA::new(0x0000_2000_0000_0000);
+const MMAP_END: VA = VA::new(0x0000_8000_0000_0000);
 
-struct Process {
+/// `mmap` protection flag: the mapping is readable.
+pub const PROT_READ: u32 = 1 << 0;
+/// `mmap` protection flag: the mapping is writable.
+pub const PROT_WRITE: u32 = 1 << 1;
+
+/// Reasons for an `mmap` request to fail.
+#[derive(Debug)]
+pub enum MmapError {
+    /// `len` is zero, or `prot` is invalid.
+    InvalidArgument,
+    /// No large enough free range is left in the mmap area.
+    NoSpace,
+}
+
+/// The process currently running in userspace.
+static CURRENT: Mutex<Option<Process>> = Mutex::new(None);
+
+pub struct Process {
     page_map: PageMap,
+    /// Current end of the heap, as moved by `sbrk`.
+    heap_break: VA,
+    /// End of the mapped part of the heap.
+    heap_mapped: VA,
+    /// Start and end addresses of the regions mapped through `mmap`.
+    mmaps: Vec<(VA, VA)>,
+    /// Address at which the next `mmap` without a usable hint is placed.
+    mmap_next: VA,
 }
 
 impl Process {
     fn new() -> Self {
+        let heap_end = HEAP_START + HEAP_SIZE;
         Self {
             page_map: PageMap::new(),
+            heap_break: heap_end,
+            heap_mapped: heap_end,
+            mmaps: Vec::new(),
+            mmap_next: MMAP_START,
+        }
+    }
+
+    /// Move the heap break by `increment` bytes.
+    ///
+    /// The initial heap passed to the process on startup can't be shrunk. Returns the previous
+    /// break, or `None` if the new break would be outside the heap bounds.
+    pub fn sbrk(&mut self, increment: isize) -> Option<VA> {
+        let old_break = self.heap_break;
+        let new_break = old_break.into_u64().checked_add_signed(increment as i64)?;
+        let new_break = VA::new(new_break);
+
+        if new_break < HEAP_START + HEAP_SIZE || new_break > HEAP_START + HEAP_MAX {
+            return None;
+        }
+
+        // Shrinking only moves the break. The pages stay mapped, to be reused by later growth.
+        if new_break > self.heap_mapped {
+            let mut vpn = PageNr::from_va(self.heap_mapped);
+            while vpn.va() < new_break {
+                let frame = phys::alloc_zero();
+                self.page_map.map_ram_page(vpn, frame, heap_flags());
+                vpn += 1;
+            }
+            self.heap_mapped = vpn.va();
+
+            // Wait for the new mappings to become visible.
+            dsb_ishst();
+            isb();
         }
+
+        self.heap_break = new_break;
+        Some(old_break)
+    }
+
+    /// Map `len` bytes of zeroed anonymous memory with the given protection.
+    ///
+    /// The mapping is placed at `hint` if that is page-aligned and the range is free, and at an
+    /// address chosen by the kernel otherwise. Returns the start of the mapping.
+    pub fn mmap(&mut self, hint: VA, len: usize, prot: u32) -> Result<VA, MmapError> {
+        let ap = match prot {
+            PROT_READ => AccessPermissions::UnprivRO,
+            p if p == PROT_READ | PROT_WRITE => AccessPermissions::UnprivRW,
+            _ => return Err(MmapError::InvalidArgument),
+        };
+        if len == 0 {
+            return Err(MmapError::InvalidArgument);
+        }
+
+        let pages = len.div_ceil(PAGE_SIZE);
+        let size = pages.checked_mul(PAGE_SIZE).ok_or(MmapError::NoSpace)?;
+
+        let start = if self.mmap_range_free(hint, size) {
+            hint
+        } else if self.mmap_range_free(self.mmap_next, size) {
+            self.mmap_next
+        } else {
+            return Err(MmapError::NoSpace);
+        };
+        let end = start + size;
+
+        let flags = Flags::default()
+            .access_permissions(ap)
+            .privileged_execute_never(true)
+            .unprivileged_execute_never(true);
+
+        let mut vpn = PageNr::from_va(start);
+        for _ in 0..pages {
+            let frame = phys::alloc_zero();
+            self.page_map.map_ram_page(vpn, frame, flags);
+            vpn += 1;
+        }
+
+        // Wait for the new mappings to become visible.
+        dsb_ishst();
+        isb();
+
+        self.mmaps.push((start, end));
+        self.mmap_next = self.mmap_next.max(end);
+
+        Ok(start)
+    }
+
+    /// Check whether `size` bytes at `start` lie within the mmap area and don't overlap existing
+    /// mappings.
+    fn mmap_range_free(&self, start: VA, size: usize) -> bool {
+        if !start.is_page_aligned() || start < MMAP_START {
+            return false;
+        }
+        let Some(end) = start.into_u64().checked_add(size as u64) else {
+            return false;
+        };
+        let end = VA::new(end);
+        if end > MMAP_END {
+            return false;
+        }
+
+        self.mmaps.iter().all(|&(s, e)| end <= s || start >= e)
     }
 }
 
+/// Run the given closure on the current process.
+///
+/// # Panics
+///
+/// Panics if no process is running.
+pub fn with_current<R>(f: impl FnOnce(&mut Process) -> R) -> R {
+    let mut current = CURRENT.lock();
+    let proc = current.as_mut().expect("current process");
+    f(proc)
+}
+
 pub fn run() -> ! {
     let mut proc = Process::new();
 
     let userimg = userimg::Reader::new();
-    let mut elf = ElfFile::open(userimg);
+    // The actual image size is unknown, so bound reads by the size of the userimg region.
+    let mut elf = ElfFile::open(userimg, USERIMG_SIZE as u64);
 
     load_address_space(&mut proc.page_map, &mut elf);
     alloc_stack(&mut proc.page_map);
     alloc_heap(&mut proc.page_map);
 
+    let ttbr0 = proc.page_map.base();
+    *CURRENT.lock() = Some(proc);
+
     unsafe {
-        load_ttbr0(proc.page_map.base(), 1);
+        load_ttbr0(ttbr0, 1);
 
         asm!(
             r#"
@@ -64,7 +217,10 @@ fn load_address_space<R>(page_map: &mut PageMap, elf: &mut ElfFile<R>)
 where
     R: io::Read + io::Seek,
 {
-    let phdrs: Vec<_> = elf.program_headers().collect();
+    let phdrs: Vec<_> = elf
+        .program_headers()
+        .collect::<Result<_, _>>()
+        .unwrap_or_else(|error| panic!("invalid userimg binary: {error:?}"));
     for phdr in phdrs {
         if !phdr.is_load() {
             continue;
@@ -122,15 +278,17 @@ fn alloc_stack(page_map: &mut PageMap) {
 fn alloc_heap(page_map: &mut PageMap) {
     let pages = HEAP_SIZE / PAGE_SIZE;
 
-    let flags = Flags::default()
-        .access_permissions(AccessPermissions::UnprivRW)
-        .privileged_execute_never(true)
-        .unprivileged_execute_never(true);
-
     let mut vpn = PageNr::from_va(HEAP_START);
     for _ in 0..pages {
         let frame = phys::alloc_zero();
-        page_map.map_ram_page(vpn, frame, flags);
+        page_map.map_ram_page(vpn, frame, heap_flags());
         vpn += 1;
     }
 }
+
+fn heap_flags() -> Flags {
+    Flags::default()
+        .access_permissions(AccessPermissions::UnprivRW)
+        .privileged_execute_never(true)
+        .unprivileged_execute_never(true)
+}
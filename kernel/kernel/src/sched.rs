@@ -0,0 +1,369 @@
+//! A minimal cooperative scheduler.
+//!
+//! Kernel tasks run on their own stacks and switch only when the running task calls
+//! [`yield_now`], either directly or through the `yield` syscall. There is no preemption yet.
+//!
+//! The boot context becomes the first task, so everything running on the kernel stack, including
+//! userspace syscalls, takes part in scheduling.
+//!
+//! Spawned tasks get their stacks from the task stack region, below an unmapped guard page, so
+//! stack overflows are detected like those of the kernel stack.
+
+use alloc::boxed::Box;
+use alloc::collections::VecDeque;
+use alloc::vec::Vec;
+use core::arch::naked_asm;
+use core::mem;
+use core::ops::Range;
+use core::sync::atomic::{AtomicU64, Ordering};
+
+use aarch64::memory::PAGE_SIZE;
+use kstd::sync::{IrqMutex, Mutex};
+
+use crate::memory::virt::{
+    self, KSTACK_GUARD_SIZE, KSTACK_SIZE, KSTACK_START, KTASK_STACK_SIZE, KTASK_STACK_SLOT_SIZE,
+    KTASK_STACKS_SIZE, KTASK_STACKS_START, PageNr,
+};
+use crate::{debug, log, memory};
+
+static SCHED: IrqMutex<Option<Scheduler>> = IrqMutex::new(None);
+
+/// Task stack slots that are free for reuse.
+static STACK_SLOTS: Mutex<StackSlots> = Mutex::new(StackSlots {
+    free: Vec::new(),
+    next: 0,
+});
+
+/// The bounds of the stack of the running task.
+///
+/// Updated by [`switch_context`], and read by the exception vectors to detect stack overflows.
+#[repr(C)]
+pub(crate) struct CurrentStack {
+    start: AtomicU64,
+    end: AtomicU64,
+}
+
+pub(crate) static CURRENT_STACK: CurrentStack = CurrentStack {
+    start: AtomicU64::new(KSTACK_START.into_u64()),
+    end: AtomicU64::new(KSTACK_START.into_u64() + KSTACK_SIZE as u64),
+};
+
+struct Scheduler {
+    current: Box<Task>,
+    ready: VecDeque<Box<Task>>,
+    /// A task that exited, to be dropped once we have switched off its stack.
+    exited: Option<Box<Task>>,
+    next_id: usize,
+}
+
+struct Task {
+    id: usize,
+    context: Context,
+    /// The task's stack, or `None` for the boot task, which runs on the kernel stack.
+    _stack: Option<TaskStack>,
+}
+
+struct StackSlots {
+    free: Vec<usize>,
+    next: usize,
+}
+
+/// A task stack, mapped into a slot of the task stack region.
+///
+/// The first page of each slot is a guard page and is never mapped.
+struct TaskStack {
+    slot: usize,
+}
+
+impl TaskStack {
+    fn new() -> Self {
+        let slot = {
+            let mut slots = STACK_SLOTS.lock();
+            slots.free.pop().unwrap_or_else(|| {
+                let slot = slots.next;
+                assert!(
+                    slot < KTASK_STACKS_SIZE / KTASK_STACK_SLOT_SIZE,
+                    "out of task stack slots"
+                );
+                slots.next += 1;
+                slot
+            })
+        };
+
+        let stack = Self { slot };
+        for vpn in stack.pages() {
+            virt::map_data_page(vpn);
+        }
+        stack
+    }
+
+    fn range(&self) -> Range<u64> {
+        let slot_start = KTASK_STACKS_START.into_u64() + (self.slot * KTASK_STACK_SLOT_SIZE) as u64;
+        let start = slot_start + KSTACK_GUARD_SIZE as u64;
+        start..start + KTASK_STACK_SIZE as u64
+    }
+
+    fn pages(&self) -> impl Iterator<Item = PageNr> {
+        let start = PageNr::from_va(self.range().start.into());
+        (0..(KTASK_STACK_SIZE / PAGE_SIZE) as u64).map(move |i| start + i)
+    }
+}
+
+impl Drop for TaskStack {
+    fn drop(&mut self) {
+        for vpn in self.pages() {
+            virt::unmap_page(vpn);
+        }
+        STACK_SLOTS.lock().free.push(self.slot);
+    }
+}
+
+/// The register state of a task that isn't running.
+///
+/// Tasks only switch inside [`switch_context`], so this holds just the callee-saved registers and
+/// the stack pointer, plus the stack bounds to publish in [`CURRENT_STACK`]. The offsets are used
+/// by `switch_context`.
+#[derive(Default)]
+#[repr(C)]
+struct Context {
+    x19: u64,
+    x20: u64,
+    x21: u64,
+    x22: u64,
+    x23: u64,
+    x24: u64,
+    x25: u64,
+    x26: u64,
+    x27: u64,
+    x28: u64,
+    x29: u64,
+    x30: u64,
+    sp: u64,
+    stack_start: u64,
+    stack_end: u64,
+}
+
+/// Initialize the scheduler, turning the boot context into the first task.
+///
+/// Requires the kernel heap to be initialized.
+pub fn init() {
+    log!("initializing scheduler");
+
+    let boot_task = Task {
+        id: 0,
+        context: Context {
+            stack_start: KSTACK_START.into_u64(),
+            stack_end: KSTACK_START.into_u64() + KSTACK_SIZE as u64,
+            ..Default::default()
+        },
+        _stack: None,
+    };
+
+    *SCHED.lock() = Some(Scheduler {
+        current: Box::new(boot_task),
+        ready: VecDeque::new(),
+        exited: None,
+        next_id: 1,
+    });
+
+    if crate::boot_tests_enabled() {
+        boot_test_sched();
+    }
+}
+
+/// Spawn a new task running `entry`, and return its ID.
+///
+/// The task starts when another task yields to it, and exits when `entry` returns.
+pub fn spawn(entry: fn()) -> usize {
+    let stack = TaskStack::new();
+    let stack_range = stack.range();
+
+    // The first switch to the task "returns" to `task_start`, with `entry` in x19.
+    let context = Context {
+        x19: entry as *const () as u64,
+        x30: task_start as *const () as u64,
+        sp: stack_range.end,
+        stack_start: stack_range.start,
+        stack_end: stack_range.end,
+        ..Default::default()
+    };
+
+    let mut sched = SCHED.lock();
+    let sched = sched.as_mut().expect("scheduler initialized");
+
+    let id = sched.next_id;
+    sched.next_id += 1;
+    sched.ready.push_back(Box::new(Task {
+        id,
+        context,
+        _stack: Some(stack),
+    }));
+
+    id
+}
+
+/// Switch to the next ready task, if any.
+///
+/// Returns once the scheduler switches back to the calling task.
+pub fn yield_now() {
+    // Yielding happens outside of any locks, so this is a good time to shrink the heap.
+    memory::trim_heap();
+
+    let (prev, next) = {
+        let mut sched = SCHED.lock();
+        let sched = sched.as_mut().expect("scheduler initialized");
+
+        let Some(next) = sched.ready.pop_front() else {
+            return;
+        };
+        let prev = mem::replace(&mut sched.current, next);
+        sched.ready.push_back(prev);
+
+        let prev = sched.ready.back_mut().unwrap();
+        (&raw mut prev.context, &raw const sched.current.context)
+    };
+
+    // SAFETY: Tasks are boxed, so their contexts stay put while the lock is released. Only the
+    //         running task switches contexts, and the scheduler no longer considers `prev`
+    //         running.
+    unsafe { switch_context(prev, next) };
+
+    finish_switch();
+}
+
+/// Exit the running task and switch to the next ready task.
+///
+/// # Panics
+///
+/// Panics if there is no other task to switch to.
+fn exit() -> ! {
+    let (prev, next) = {
+        let mut sched = SCHED.lock();
+        let sched = sched.as_mut().expect("scheduler initialized");
+
+        let next = sched.ready.pop_front().expect("no task to switch to");
+        let prev = mem::replace(&mut sched.current, next);
+        let prev = sched.exited.insert(prev);
+        debug!("task {} exited", prev.id);
+
+        (&raw mut prev.context, &raw const sched.current.context)
+    };
+
+    // SAFETY: As in `yield_now`. The exited task is dropped only after the switch, by the next
+    //         task.
+    unsafe { switch_context(prev, next) };
+
+    unreachable!("switched back to exited task");
+}
+
+/// Return the bounds of the stack of the running task.
+pub(crate) fn current_stack() -> Range<u64> {
+    let start = CURRENT_STACK.start.load(Ordering::Relaxed);
+    let end = CURRENT_STACK.end.load(Ordering::Relaxed);
+    start..end
+}
+
+/// Clean up after a context switch, on the stack of the task switched to.
+fn finish_switch() {
+    let exited = SCHED.lock().as_mut().and_then(|sched| sched.exited.take());
+    drop(exited);
+}
+
+/// The Rust entry point of spawned tasks.
+extern "C" fn task_main(entry: *const ()) -> ! {
+    finish_switch();
+
+    // SAFETY: `spawn` put a `fn()` into x19, which `task_start` passed on.
+    let entry: fn() = unsafe { mem::transmute(entry) };
+    entry();
+
+    exit();
+}
+
+/// The first code run by a spawned task, moving the entry function into place for `task_main`.
+#[unsafe(naked)]
+unsafe extern "C" fn task_start() -> ! {
+    naked_asm!(
+        r#"
+        mov x0, x19
+        b {task_main}
+        "#,
+        task_main = sym task_main,
+    )
+}
+
+/// Save the callee-saved registers to `prev`, and resume the task saved in `next`.
+///
+/// Interrupts are masked while the stack pointer and [`CURRENT_STACK`] disagree, so exception
+/// handlers never mistake the switch for a stack overflow.
+///
+/// # Safety
+///
+/// `next` must hold the context of a task that isn't running.
+#[unsafe(naked)]
+unsafe extern "C" fn switch_context(prev: *mut Context, next: *const Context) {
+    naked_asm!(
+        r#"
+        mrs x12, daif
+        msr daifset, #3
+
+        stp x19, x20, [x0, #0]
+        stp x21, x22, [x0, #16]
+        stp x23, x24, [x0, #32]
+        stp x25, x26, [x0, #48]
+        stp x27, x28, [x0, #64]
+        stp x29, x30, [x0, #80]
+        mov x9, sp
+        str x9, [x0, #96]
+
+        ldp x19, x20, [x1, #0]
+        ldp x21, x22, [x1, #16]
+        ldp x23, x24, [x1, #32]
+        ldp x25, x26, [x1, #48]
+        ldp x27, x28, [x1, #64]
+        ldp x29, x30, [x1, #80]
+        ldp x10, x11, [x1, #104]
+        adrp x9, {current_stack}
+        add x9, x9, :lo12:{current_stack}
+        stp x10, x11, [x9]
+        ldr x9, [x1, #96]
+        mov sp, x9
+
+        msr daif, x12
+        ret
+        "#,
+        current_stack = sym CURRENT_STACK,
+    )
+}
+
+/// Spawn two tasks that yield back and forth, and check that they interleave and exit.
+fn boot_test_sched() {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    static STEPS: AtomicUsize = AtomicUsize::new(0);
+
+    fn task() {
+        for _ in 0..3 {
+            STEPS.fetch_add(1, Ordering::Relaxed);
+            yield_now();
+        }
+    }
+
+    spawn(task);
+    spawn(task);
+
+    // Each round of yields runs both tasks one step, and the last round lets them exit.
+    for _ in 0..4 {
+        yield_now();
+    }
+
+    let steps = STEPS.load(Ordering::Relaxed);
+    assert_eq!(steps, 6, "tasks ran {steps} steps");
+
+    let sched = SCHED.lock();
+    let sched = sched.as_ref().unwrap();
+    assert!(sched.ready.is_empty(), "tasks didn't exit");
+    assert_eq!(sched.current.id, 0, "not back on the boot task");
+
+    log!("boot-test: sched ok");
+}
@@ -0,0 +1,154 @@
+//! Periodic timer ticks, driven by the virtual generic timer.
+
+use core::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use core::time::Duration;
+
+use aarch64::register::{CNTFRQ_EL0, CNTV_CTL_EL0, CNTV_CVAL_EL0};
+use kstd::sync::IrqMutex;
+
+use crate::{interrupt, log};
+
+/// Number of ticks per second.
+const TICK_HZ: u64 = 100;
+
+/// INTID of the virtual timer PPI.
+const TIMER_INTID: u32 = 27;
+
+/// Maximum number of callbacks registered through [`every`].
+const MAX_PERIODIC: usize = 8;
+
+static TICKS: AtomicU64 = AtomicU64::new(0);
+/// Counter ticks between two timer ticks.
+static INTERVAL: AtomicU64 = AtomicU64::new(0);
+static PERIODIC: IrqMutex<[Option<Periodic>; MAX_PERIODIC]> = IrqMutex::new([None; MAX_PERIODIC]);
+
+/// A callback invoked every `period` ticks.
+#[derive(Clone, Copy)]
+struct Periodic {
+    period: u64,
+    /// Tick at which the callback was registered.
+    start: u64,
+    f: fn(),
+}
+
+/// Start the periodic timer.
+///
+/// Requires interrupts to be initialized.
+pub fn init() {
+    log!("initializing timer ticks");
+
+    let freq = CNTFRQ_EL0::read().ClockFreq();
+    let interval = freq / TICK_HZ;
+    INTERVAL.store(interval, Ordering::Relaxed);
+    log!("  counter frequency {freq} Hz, tick every {interval} counts");
+
+    interrupt::register_handler(TIMER_INTID, handle_tick);
+
+    let now = aarch64::uptime_ticks();
+    set_deadline(now + interval);
+
+    let mut ctl = CNTV_CTL_EL0::default();
+    ctl.set_ENABLE(1);
+    ctl.set_IMASK(0);
+    // SAFETY: The timer interrupt has a handler registered.
+    unsafe { CNTV_CTL_EL0::write(ctl) };
+
+    interrupt::enable_irq(TIMER_INTID);
+
+    if crate::boot_tests_enabled() {
+        boot_test_ticking();
+        boot_test_delay();
+    }
+}
+
+/// Return the number of ticks since the timer was started.
+pub fn ticks() -> u64 {
+    TICKS.load(Ordering::Relaxed)
+}
+
+/// Invoke `f` periodically, every `period`, rounded up to whole ticks.
+///
+/// Callbacks run in IRQ context, with interrupts masked.
+///
+/// # Panics
+///
+/// Panics if too many callbacks are registered.
+pub fn every(period: Duration, f: fn()) {
+    let tick_nanos = 1_000_000_000 / u128::from(TICK_HZ);
+    let period = period.as_nanos().div_ceil(tick_nanos).max(1) as u64;
+
+    let mut periodic = PERIODIC.lock();
+    let slot = periodic
+        .iter_mut()
+        .find(|p| p.is_none())
+        .expect("too many periodic callbacks");
+    *slot = Some(Periodic {
+        period,
+        start: ticks(),
+        f,
+    });
+}
+
+fn handle_tick() {
+    let tick = TICKS.fetch_add(1, Ordering::Relaxed) + 1;
+
+    // Advance the deadline from the previous one, rather than from now, so the tick rate doesn't
+    // drift with the IRQ latency.
+    let interval = INTERVAL.load(Ordering::Relaxed);
+    let deadline = CNTV_CVAL_EL0::read().CompareValue();
+    set_deadline(deadline + interval);
+
+    // Copy the callbacks out, so they can register new ones without deadlocking.
+    let periodic = *PERIODIC.lock();
+    for p in periodic.iter().flatten() {
+        if (tick - p.start).is_multiple_of(p.period) {
+            (p.f)();
+        }
+    }
+}
+
+fn set_deadline(count: u64) {
+    let mut cval = CNTV_CVAL_EL0::default();
+    cval.set_CompareValue(count);
+    // SAFETY: Only moves the next timer interrupt.
+    unsafe { CNTV_CVAL_EL0::write(cval) };
+}
+
+/// Check that timer ticks arrive and invoke periodic callbacks.
+fn boot_test_ticking() {
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    static CALLS: AtomicUsize = AtomicUsize::new(0);
+
+    // Callbacks can't be unregistered, so this one keeps counting. That's cheap enough.
+    every(Duration::ZERO, || {
+        CALLS.fetch_add(1, Ordering::Relaxed);
+    });
+
+    let watch = aarch64::Stopwatch::start();
+    while CALLS.load(Ordering::Relaxed) < 2 {
+        assert!(watch.elapsed() < TIMEOUT, "timer not ticking");
+        core::hint::spin_loop();
+    }
+    assert!(ticks() >= 2);
+
+    log!("boot-test: ticking ok");
+}
+
+/// Check that `delay_ms` waits at least as long as requested, and returns promptly for zero.
+fn boot_test_delay() {
+    let watch = aarch64::Stopwatch::start();
+    aarch64::delay_ms(0);
+    let zero = watch.elapsed();
+    assert!(zero < Duration::from_millis(1), "delay_ms(0) took {zero:?}");
+
+    let watch = aarch64::Stopwatch::start();
+    aarch64::delay_ms(10);
+    let ten = watch.elapsed();
+    assert!(
+        ten >= Duration::from_millis(10),
+        "delay_ms(10) took {ten:?}"
+    );
+
+    log!("boot-test: delay ok");
+}
@@ -1,8 +1,99 @@
 //! Simple drivers for supported UART devices.
 
+use core::sync::atomic::{AtomicBool, Ordering};
 use core::{fmt, hint};
 
-use crate::memory::mmio::MmioPage;
+use kstd::ring::RingBuffer;
+use kstd::sync::IrqMutex;
+
+use crate::memory::mmio::{Mmio, MmioPage};
+use crate::{interrupt, log};
+
+/// Size of the console receive buffer.
+const RX_BUFFER_SIZE: usize = 256;
+
+/// Whether console input is buffered by the RX interrupt handler.
+static CONSOLE_IRQ: AtomicBool = AtomicBool::new(false);
+static CONSOLE_RX: IrqMutex<ConsoleRx> = IrqMutex::new(ConsoleRx {
+    ring: RingBuffer::new(),
+    dropped: 0,
+});
+
+/// Bytes received on the console UART, buffered by the RX interrupt handler.
+struct ConsoleRx {
+    ring: RingBuffer<RX_BUFFER_SIZE>,
+    /// Number of bytes dropped because the ring was full.
+    dropped: u64,
+}
+
+/// Make console input interrupt-driven, using the given UART interrupt.
+///
+/// Requires interrupts to be initialized.
+pub fn init_console(irq: u32) {
+    interrupt::register_handler(irq, handle_rx);
+    log::enable_rx_interrupt();
+    interrupt::enable_irq(irq);
+    CONSOLE_IRQ.store(true, Ordering::Release);
+}
+
+/// Read console input into `buf`, returning the number of bytes read.
+///
+/// Returns 0 if no input is available. Without an RX interrupt, this polls the UART instead.
+#[cfg_attr(
+    not(feature = "monitor"),
+    expect(dead_code, reason = "no users outside the monitor yet")
+)]
+pub fn console_read(buf: &mut [u8]) -> usize {
+    if !CONSOLE_IRQ.load(Ordering::Acquire) {
+        return poll_read(buf);
+    }
+
+    let mut rx = CONSOLE_RX.lock();
+    if rx.dropped > 0 {
+        log!("console RX buffer overflowed, dropped {} bytes", rx.dropped);
+        rx.dropped = 0;
+    }
+    rx.ring.read(buf)
+}
+
+/// Read a single byte of console input, waiting until one is available.
+#[cfg_attr(
+    not(feature = "monitor"),
+    expect(dead_code, reason = "no users outside the monitor yet")
+)]
+pub fn console_read_byte() -> u8 {
+    let mut byte = [0];
+    while console_read(&mut byte) == 0 {
+        if CONSOLE_IRQ.load(Ordering::Acquire) {
+            // The RX interrupt wakes us up.
+            aarch64::instruction::wfe();
+        } else {
+            hint::spin_loop();
+        }
+    }
+    byte[0]
+}
+
+fn poll_read(buf: &mut [u8]) -> usize {
+    let mut count = 0;
+    for slot in buf {
+        let Some(b) = log::read_byte() else {
+            break;
+        };
+        *slot = b;
+        count += 1;
+    }
+    count
+}
+
+fn handle_rx() {
+    let mut rx = CONSOLE_RX.lock();
+    while let Some(b) = log::read_byte() {
+        if rx.ring.push(b) {
+            rx.dropped += 1;
+        }
+    }
+}
 
 #[derive(Debug)]
 pub enum Uart {
@@ -15,8 +106,32 @@ impl Uart {
         Self::Pl011(Pl011 { mmio })
     }
 
-    pub unsafe fn uart16550(mmio: MmioPage) -> Self {
-        Self::Uart16550(Uart16550 { mmio })
+    /// `reg_width` is the register access width in bytes, either 1, 2 or 4.
+    pub unsafe fn uart16550(mmio: MmioPage, reg_width: u8) -> Self {
+        assert!(
+            matches!(reg_width, 1 | 2 | 4),
+            "invalid register width: {reg_width}"
+        );
+
+        let mut uart = Uart16550 { mmio, reg_width };
+        uart.init();
+        Self::Uart16550(uart)
+    }
+
+    /// Read a received byte, if one is available.
+    pub fn read_byte(&mut self) -> Option<u8> {
+        match self {
+            Uart::Pl011(inner) => inner.read_byte(),
+            Uart::Uart16550(inner) => inner.read_byte(),
+        }
+    }
+
+    /// Enable the interrupt signaling received data.
+    pub fn enable_rx_interrupt(&mut self) {
+        match self {
+            Uart::Pl011(inner) => inner.enable_rx_interrupt(),
+            Uart::Uart16550(inner) => inner.enable_rx_interrupt(),
+        }
     }
 }
 
@@ -36,17 +151,42 @@ pub struct Pl011 {
 
 impl Pl011 {
     fn write_dr(&mut self, val: u8) {
-        unsafe { self.mmio.write(0x000, val) }
+        unsafe { self.mmio.write8(0x000, val) }
+    }
+
+    fn read_dr(&mut self) -> u8 {
+        unsafe { self.mmio.read8(0x000) }
     }
 
     fn read_fr(&self) -> u16 {
-        unsafe { self.mmio.read(0x018) }
+        unsafe { self.mmio.read16(0x018) }
+    }
+
+    fn write_imsc(&mut self, val: u16) {
+        unsafe { self.mmio.write16(0x038, val) }
+    }
+
+    /// Enable the receive (RXIM) and receive timeout (RTIM) interrupts.
+    ///
+    /// Both are cleared by draining the receive FIFO.
+    fn enable_rx_interrupt(&mut self) {
+        self.write_imsc((1 << 4) | (1 << 6));
     }
 
     fn busy(&self) -> bool {
         let flags = self.read_fr();
         flags & (1 << 3) != 0
     }
+
+    /// Whether the receive FIFO is empty (FR.RXFE).
+    fn rx_empty(&self) -> bool {
+        let flags = self.read_fr();
+        flags & (1 << 4) != 0
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        (!self.rx_empty()).then(|| self.read_dr())
+    }
 }
 
 impl fmt::Write for Pl011 {
@@ -64,30 +204,92 @@ impl fmt::Write for Pl011 {
 #[derive(Debug)]
 pub struct Uart16550 {
     mmio: MmioPage,
+    /// Register access width in bytes, which is also the stride between registers.
+    reg_width: u8,
 }
 
 impl Uart16550 {
+    // FIFO control register bits.
+    const FCR_ENABLE: u8 = 1 << 0;
+    const FCR_CLEAR_RX: u8 = 1 << 1;
+    const FCR_CLEAR_TX: u8 = 1 << 2;
+    const FCR_TRIGGER_8: u8 = 0b10 << 6;
+
+    /// Enable and clear the FIFOs, with the RX trigger level at 8 bytes.
+    fn init(&mut self) {
+        let fcr = Self::FCR_ENABLE | Self::FCR_CLEAR_RX | Self::FCR_CLEAR_TX | Self::FCR_TRIGGER_8;
+        self.write_fcr(fcr);
+    }
+
+    fn read_reg(&self, reg: usize) -> u8 {
+        let offset = reg * usize::from(self.reg_width);
+        match self.reg_width {
+            4 => unsafe { self.mmio.read32(offset) as u8 },
+            2 => unsafe { self.mmio.read16(offset) as u8 },
+            _ => unsafe { self.mmio.read8(offset) },
+        }
+    }
+
+    fn write_reg(&mut self, reg: usize, val: u8) {
+        let offset = reg * usize::from(self.reg_width);
+        match self.reg_width {
+            4 => unsafe { self.mmio.write32(offset, u32::from(val)) },
+            2 => unsafe { self.mmio.write16(offset, u16::from(val)) },
+            _ => unsafe { self.mmio.write8(offset, val) },
+        }
+    }
+
     fn write_thr(&mut self, val: u8) {
-        unsafe { self.mmio.write(0b000, val) }
+        self.write_reg(0b000, val)
+    }
+
+    fn write_ier(&mut self, val: u8) {
+        self.write_reg(0b001, val)
+    }
+
+    fn write_fcr(&mut self, val: u8) {
+        self.write_reg(0b010, val)
+    }
+
+    /// Enable the received data available interrupt (IER.ERBFI).
+    ///
+    /// It is cleared by draining the receive FIFO.
+    fn enable_rx_interrupt(&mut self) {
+        self.write_ier(1 << 0);
+    }
+
+    fn read_rbr(&mut self) -> u8 {
+        self.read_reg(0b000)
     }
 
     fn read_lsr(&self) -> u8 {
-        unsafe { self.mmio.read(0b101) }
+        self.read_reg(0b101)
     }
 
+    /// Whether the transmit holding register is empty (LSR.THRE).
     fn thr_empty(&self) -> bool {
         let flags = self.read_lsr();
         flags & (1 << 5) != 0
     }
+
+    /// Whether received data is available (LSR.DR).
+    fn data_ready(&self) -> bool {
+        let flags = self.read_lsr();
+        flags & (1 << 0) != 0
+    }
+
+    fn read_byte(&mut self) -> Option<u8> {
+        self.data_ready().then(|| self.read_rbr())
+    }
 }
 
 impl fmt::Write for Uart16550 {
     fn write_str(&mut self, s: &str) -> fmt::Result {
         for b in s.bytes() {
-            self.write_thr(b);
             while !self.thr_empty() {
                 hint::spin_loop();
             }
+            self.write_thr(b);
         }
         Ok(())
     }
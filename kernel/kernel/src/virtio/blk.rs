@@ -0,0 +1,186 @@
+//! Virtio block device driver.
+
+use core::{hint, mem};
+
+use aarch64::memory::PAGE_SIZE;
+use kstd::block::BlockDevice;
+use kstd::sync::Mutex;
+
+use crate::log;
+use crate::memory::phys::{self, FrameRef};
+use crate::pci::Function;
+use crate::virtio::{Buffer, DeviceType, Transport, Virtqueue, device_type};
+
+/// Size of a block device sector, in bytes.
+pub const SECTOR_SIZE: usize = 512;
+
+const REQ_TYPE_IN: u32 = 0;
+
+const STATUS_OK: u8 = 0;
+
+// Offsets into the device-specific configuration structure.
+const CONFIG_CAPACITY: usize = 0x00;
+
+/// Header of a block request, as defined by the virtio spec.
+#[repr(C)]
+struct RequestHeader {
+    type_: u32,
+    reserved: u32,
+    sector: u64,
+}
+
+/// Offset of the status byte in the request frame, following the header.
+const STATUS_OFFSET: usize = mem::size_of::<RequestHeader>();
+
+/// A virtio block device.
+pub struct VirtioBlk {
+    /// Device capacity, in sectors.
+    capacity: u64,
+    queue: Mutex<RequestQueue>,
+}
+
+/// The request virtqueue, together with the DMA buffers used to submit requests.
+struct RequestQueue {
+    transport: Transport,
+    queue: Virtqueue,
+    /// DMA buffer holding the request header and status byte.
+    request: FrameRef,
+    /// DMA buffer receiving the sector data.
+    data: FrameRef,
+}
+
+impl VirtioBlk {
+    /// Initialize the virtio block device behind the given PCI function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the function is not a virtio block device.
+    pub fn new(func: &Function) -> Self {
+        assert_eq!(device_type(func), Some(DeviceType::Block));
+
+        let mut transport = Transport::new(func);
+        transport.init(0);
+        let queue = transport.setup_queue(0);
+        transport.driver_ok();
+
+        let capacity = transport.read_device_config(CONFIG_CAPACITY);
+
+        Self {
+            capacity,
+            queue: Mutex::new(RequestQueue {
+                transport,
+                queue,
+                request: phys::alloc_zero(),
+                data: phys::alloc_zero(),
+            }),
+        }
+    }
+}
+
+impl BlockDevice for VirtioBlk {
+    /// Read sectors, starting at `sector`, into `buf`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is not a multiple of the sector size, if the read exceeds the device
+    /// capacity, or if the device reports an error.
+    fn read_block(&self, sector: u64, buf: &mut [u8]) {
+        assert!(buf.len().is_multiple_of(SECTOR_SIZE), "partial sector read");
+
+        let sectors = (buf.len() / SECTOR_SIZE) as u64;
+        let end = sector.checked_add(sectors);
+        assert!(
+            end.is_some_and(|end| end <= self.capacity),
+            "read beyond device capacity: sector={sector}, count={sectors}",
+        );
+
+        // The data buffer is a single page frame, so read at most a page at a time.
+        let mut queue = self.queue.lock();
+        let mut sector = sector;
+        for chunk in buf.chunks_mut(PAGE_SIZE) {
+            queue.read_chunk(sector, chunk);
+            sector += (chunk.len() / SECTOR_SIZE) as u64;
+        }
+    }
+
+    fn block_size(&self) -> usize {
+        SECTOR_SIZE
+    }
+
+    fn block_count(&self) -> u64 {
+        self.capacity
+    }
+}
+
+impl RequestQueue {
+    fn read_chunk(&mut self, sector: u64, buf: &mut [u8]) {
+        self.request.with_contents(|req| {
+            let header = RequestHeader {
+                type_: REQ_TYPE_IN,
+                reserved: 0,
+                sector,
+            };
+            // SAFETY: The request frame is large enough to hold the header, and
+            //         `RequestHeader` is plain old data.
+            unsafe {
+                req.as_mut_ptr()
+                    .cast::<RequestHeader>()
+                    .write_unaligned(header)
+            };
+            req[STATUS_OFFSET] = 0xff;
+        });
+
+        let request_pa = self.request.pa();
+        let buffers = [
+            Buffer {
+                pa: request_pa,
+                len: mem::size_of::<RequestHeader>() as u32,
+                device_writable: false,
+            },
+            Buffer {
+                pa: self.data.pa(),
+                len: buf.len() as u32,
+                device_writable: true,
+            },
+            Buffer {
+                pa: request_pa + STATUS_OFFSET,
+                len: 1,
+                device_writable: true,
+            },
+        ];
+
+        // SAFETY: The request and data frames are owned by `self` and we wait for the device to
+        //         return the chain before touching them again.
+        let head = unsafe { self.queue.push(&buffers) }.expect("virtqueue has free descriptors");
+        self.transport.notify(&self.queue);
+
+        // Poll for completion.
+        let used_head = loop {
+            if let Some((id, _len)) = self.queue.pop_used() {
+                break id;
+            }
+            hint::spin_loop();
+        };
+        assert_eq!(used_head, head, "unexpected request completed");
+
+        let mut status = 0;
+        self.request
+            .with_contents(|req| status = req[STATUS_OFFSET]);
+        assert_eq!(status, STATUS_OK, "block read failed: sector={sector}");
+
+        self.data
+            .with_contents(|data| buf.copy_from_slice(&data[..buf.len()]));
+    }
+}
+
+/// Initialize the first virtio block device among the given PCI functions, if any.
+pub fn probe(functions: &[Function]) -> Option<VirtioBlk> {
+    let func = functions
+        .iter()
+        .find(|f| device_type(f) == Some(DeviceType::Block))?;
+
+    let dev = VirtioBlk::new(func);
+    log!("virtio-blk: {} sectors", dev.block_count());
+
+    Some(dev)
+}
@@ -0,0 +1,343 @@
+//! Virtio 1.0 PCI transport.
+//!
+//! This module implements the device-independent parts of the virtio PCI transport: locating and
+//! mapping the configuration structures, feature negotiation, and virtqueue setup. Device class
+//! drivers build on top of it.
+
+pub mod blk;
+
+mod queue;
+
+use core::mem;
+
+use aarch64::instruction::dmb_oshst;
+
+use crate::log;
+use crate::memory::mmio::{Mmio, MmioRegion};
+use crate::pci::{Capability, Function};
+
+pub use self::queue::{Buffer, Virtqueue};
+
+const VENDOR_ID: u16 = 0x1af4;
+
+/// Feature bit indicating compliance with the virtio 1.0 spec.
+const F_VERSION_1: u64 = 1 << 32;
+
+const STATUS_ACKNOWLEDGE: u8 = 1;
+const STATUS_DRIVER: u8 = 2;
+const STATUS_DRIVER_OK: u8 = 4;
+const STATUS_FEATURES_OK: u8 = 8;
+const STATUS_FAILED: u8 = 128;
+
+/// Maximum number of entries in a virtqueue.
+///
+/// With this limit, each of the three virtqueue parts fits into a single page.
+const QUEUE_SIZE_MAX: u16 = 256;
+
+// Offsets into the common configuration structure.
+const COMMON_DEVICE_FEATURE_SELECT: usize = 0x00;
+const COMMON_DEVICE_FEATURE: usize = 0x04;
+const COMMON_DRIVER_FEATURE_SELECT: usize = 0x08;
+const COMMON_DRIVER_FEATURE: usize = 0x0c;
+const COMMON_NUM_QUEUES: usize = 0x12;
+const COMMON_DEVICE_STATUS: usize = 0x14;
+const COMMON_QUEUE_SELECT: usize = 0x16;
+const COMMON_QUEUE_SIZE: usize = 0x18;
+const COMMON_QUEUE_ENABLE: usize = 0x1c;
+const COMMON_QUEUE_NOTIFY_OFF: usize = 0x1e;
+const COMMON_QUEUE_DESC: usize = 0x20;
+const COMMON_QUEUE_DRIVER: usize = 0x28;
+const COMMON_QUEUE_DEVICE: usize = 0x30;
+
+// Virtio PCI capability types.
+const PCI_CAP_COMMON_CFG: u8 = 1;
+const PCI_CAP_NOTIFY_CFG: u8 = 2;
+const PCI_CAP_DEVICE_CFG: u8 = 4;
+
+/// Virtio device types.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DeviceType {
+    Network,
+    Block,
+    Other(u16),
+}
+
+impl DeviceType {
+    fn from_id(id: u16) -> Self {
+        match id {
+            1 => Self::Network,
+            2 => Self::Block,
+            id => Self::Other(id),
+        }
+    }
+}
+
+/// Return the virtio device type of the given PCI function.
+///
+/// Returns `None` if the function is not a virtio device.
+pub fn device_type(func: &Function) -> Option<DeviceType> {
+    if func.vendor_id() != VENDOR_ID {
+        return None;
+    }
+
+    match func.device_id() {
+        // Transitional devices report the device type in the subsystem ID.
+        0x1000..=0x103f => {
+            let subsystem_id = (func.read_config_word(11) >> 16) as u16;
+            Some(DeviceType::from_id(subsystem_id))
+        }
+        id @ 0x1040..=0x107f => Some(DeviceType::from_id(id - 0x1040)),
+        _ => None,
+    }
+}
+
+/// Location of a configuration structure inside a BAR.
+#[derive(Clone, Copy, Debug)]
+struct CfgLocation {
+    bar: usize,
+    offset: usize,
+    length: usize,
+}
+
+/// A virtio PCI transport.
+#[derive(Debug)]
+pub struct Transport {
+    bars: [Option<MmioRegion>; 6],
+    common: CfgLocation,
+    notify: CfgLocation,
+    notify_off_multiplier: u32,
+    device: CfgLocation,
+}
+
+impl Transport {
+    /// Create a transport for the given PCI function.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the function is not a virtio 1.0 device.
+    pub fn new(func: &Function) -> Self {
+        assert!(device_type(func).is_some(), "not a virtio device");
+
+        let mut common = None;
+        let mut notify = None;
+        let mut notify_off_multiplier = 0;
+        let mut device = None;
+
+        for cap in vendor_capabilities(func) {
+            // The spec allows multiple capabilities of the same type, in order of preference.
+            match cap.cfg_type {
+                PCI_CAP_COMMON_CFG if common.is_none() => common = Some(cap.location),
+                PCI_CAP_NOTIFY_CFG if notify.is_none() => {
+                    notify = Some(cap.location);
+                    notify_off_multiplier = func.read_config_word(cap.word_idx + 4);
+                }
+                PCI_CAP_DEVICE_CFG if device.is_none() => device = Some(cap.location),
+                _ => (),
+            }
+        }
+
+        let common = common.expect("common config present");
+        let notify = notify.expect("notify config present");
+        let device = device.expect("device config present");
+
+        let mut bars = [const { None }; 6];
+        for loc in [common, notify, device] {
+            bars[loc.bar].get_or_insert_with(|| func.map_bar(loc.bar));
+        }
+
+        func.enable_bus_mastering();
+
+        Self {
+            bars,
+            common,
+            notify,
+            notify_off_multiplier,
+            device,
+        }
+    }
+
+    /// Initialize the device and negotiate the feature set.
+    ///
+    /// `features` are the device-specific features supported by the driver. Returns the features
+    /// both the driver and the device support.
+    ///
+    /// After this returns, the driver should set up its virtqueues and then signal readiness
+    /// through [`Transport::driver_ok`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the device doesn't accept the negotiated features.
+    pub fn init(&mut self, features: u64) -> u64 {
+        self.reset();
+
+        self.set_status(STATUS_ACKNOWLEDGE);
+        self.set_status(STATUS_ACKNOWLEDGE | STATUS_DRIVER);
+
+        let device_features = self.device_features();
+        assert!(
+            device_features & F_VERSION_1 != 0,
+            "device doesn't support virtio 1.0"
+        );
+
+        let features = device_features & (features | F_VERSION_1);
+        self.set_driver_features(features);
+
+        let status = STATUS_ACKNOWLEDGE | STATUS_DRIVER | STATUS_FEATURES_OK;
+        self.set_status(status);
+        if self.status() & STATUS_FEATURES_OK == 0 {
+            self.set_status(status | STATUS_FAILED);
+            panic!("device rejected features: {features:#x}");
+        }
+
+        log!("negotiated features: {features:#x}");
+
+        features
+    }
+
+    /// Signal to the device that the driver is ready.
+    pub fn driver_ok(&mut self) {
+        let status = self.status() | STATUS_DRIVER_OK;
+        self.set_status(status);
+    }
+
+    /// Reset the device.
+    pub fn reset(&mut self) {
+        self.set_status(0);
+
+        // The reset is complete once the status reads back as zero.
+        while self.status() != 0 {
+            core::hint::spin_loop();
+        }
+    }
+
+    /// Return the number of virtqueues supported by the device.
+    pub fn num_queues(&self) -> u16 {
+        self.read_common(COMMON_NUM_QUEUES)
+    }
+
+    /// Set up and enable the virtqueue with the given index.
+    ///
+    /// Must be called between [`Transport::init`] and [`Transport::driver_ok`].
+    pub fn setup_queue(&mut self, index: u16) -> Virtqueue {
+        assert!(index < self.num_queues(), "invalid queue index: {index}");
+
+        self.write_common(COMMON_QUEUE_SELECT, index);
+
+        let max_size: u16 = self.read_common(COMMON_QUEUE_SIZE);
+        assert!(max_size != 0, "queue {index} not available");
+
+        let size = max_size.min(QUEUE_SIZE_MAX);
+        let notify_off = self.read_common(COMMON_QUEUE_NOTIFY_OFF);
+        let queue = Virtqueue::new(index, size, notify_off);
+
+        let (desc, driver, device) = queue.addresses();
+        self.write_common(COMMON_QUEUE_SIZE, size);
+        self.write_common(COMMON_QUEUE_DESC, desc.into_u64());
+        self.write_common(COMMON_QUEUE_DRIVER, driver.into_u64());
+        self.write_common(COMMON_QUEUE_DEVICE, device.into_u64());
+        self.write_common(COMMON_QUEUE_ENABLE, 1_u16);
+
+        queue
+    }
+
+    /// Notify the device that new buffers are available in the given queue.
+    pub fn notify(&mut self, queue: &Virtqueue) {
+        let offset = usize::from(queue.notify_off()) * self.notify_off_multiplier as usize;
+
+        // Make sure the device observes the updated available ring.
+        dmb_oshst();
+
+        self.write(self.notify, offset, queue.index());
+    }
+
+    /// Read a value from the device-specific configuration structure.
+    pub fn read_device_config<T: Copy>(&self, offset: usize) -> T {
+        self.read(self.device, offset)
+    }
+
+    fn status(&self) -> u8 {
+        self.read_common(COMMON_DEVICE_STATUS)
+    }
+
+    fn set_status(&mut self, status: u8) {
+        self.write_common(COMMON_DEVICE_STATUS, status);
+    }
+
+    fn device_features(&mut self) -> u64 {
+        self.write_common(COMMON_DEVICE_FEATURE_SELECT, 0_u32);
+        let lo: u32 = self.read_common(COMMON_DEVICE_FEATURE);
+        self.write_common(COMMON_DEVICE_FEATURE_SELECT, 1_u32);
+        let hi: u32 = self.read_common(COMMON_DEVICE_FEATURE);
+
+        u64::from(hi) << 32 | u64::from(lo)
+    }
+
+    fn set_driver_features(&mut self, features: u64) {
+        self.write_common(COMMON_DRIVER_FEATURE_SELECT, 0_u32);
+        self.write_common(COMMON_DRIVER_FEATURE, features as u32);
+        self.write_common(COMMON_DRIVER_FEATURE_SELECT, 1_u32);
+        self.write_common(COMMON_DRIVER_FEATURE, (features >> 32) as u32);
+    }
+
+    fn read_common<T: Copy>(&self, offset: usize) -> T {
+        self.read(self.common, offset)
+    }
+
+    fn write_common<T: Copy>(&mut self, offset: usize, val: T) {
+        self.write(self.common, offset, val);
+    }
+
+    fn read<T: Copy>(&self, loc: CfgLocation, offset: usize) -> T {
+        assert!(offset + mem::size_of::<T>() <= loc.length);
+
+        let bar = self.bars[loc.bar].as_ref().expect("BAR mapped");
+        // SAFETY: The location describes a virtio config structure, which only contains readable
+        //         registers.
+        unsafe { bar.read(loc.offset + offset) }
+    }
+
+    fn write<T: Copy>(&mut self, loc: CfgLocation, offset: usize, val: T) {
+        assert!(offset + mem::size_of::<T>() <= loc.length);
+
+        let bar = self.bars[loc.bar].as_mut().expect("BAR mapped");
+        // SAFETY: Callers only write to registers the virtio spec declares writable.
+        unsafe { bar.write(loc.offset + offset, val) }
+    }
+}
+
+/// A virtio vendor-specific PCI capability.
+struct VendorCapability {
+    /// Config space word index of the capability.
+    word_idx: usize,
+    cfg_type: u8,
+    location: CfgLocation,
+}
+
+/// Return an iterator over the virtio vendor-specific capabilities of the given function.
+fn vendor_capabilities(func: &Function) -> impl Iterator<Item = VendorCapability> + '_ {
+    func.capabilities()
+        .filter(|cap| cap.id == Capability::VENDOR)
+        .filter_map(|cap| {
+            let word_idx = cap.word_idx();
+            let header = func.read_config_word(word_idx);
+            let cfg_type = (header >> 24) as u8;
+            let bar = func.read_config_word(word_idx + 1) as u8;
+            let offset = func.read_config_word(word_idx + 2);
+            let length = func.read_config_word(word_idx + 3);
+
+            // BAR values outside the BAR range are reserved and must be ignored.
+            if bar > 5 {
+                return None;
+            }
+
+            Some(VendorCapability {
+                word_idx,
+                cfg_type,
+                location: CfgLocation {
+                    bar: usize::from(bar),
+                    offset: offset as usize,
+                    length: length as usize,
+                },
+            })
+        })
+}
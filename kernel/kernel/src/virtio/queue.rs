@@ -0,0 +1,240 @@
+//! Split virtqueues.
+
+use core::mem;
+
+use aarch64::instruction::{dmb_oshld, dmb_oshst};
+use aarch64::memory::{PA, PAGE_SIZE};
+
+use crate::memory::pa_to_va;
+use crate::memory::phys::{self, FrameRefRange};
+
+const DESC_F_NEXT: u16 = 1;
+const DESC_F_WRITE: u16 = 2;
+
+// Offsets into the available and used rings.
+const RING_IDX: usize = 2;
+const RING_ENTRIES: usize = 4;
+
+// Frames of the descriptor table, the available ring, and the used ring.
+const DESC_FRAME: usize = 0;
+const AVAIL_FRAME: usize = 1;
+const USED_FRAME: usize = 2;
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct Descriptor {
+    addr: u64,
+    len: u32,
+    flags: u16,
+    next: u16,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy, Debug)]
+struct UsedElem {
+    id: u32,
+    len: u32,
+}
+
+/// A buffer to be passed to the device.
+#[derive(Clone, Copy, Debug)]
+pub struct Buffer {
+    pub pa: PA,
+    pub len: u32,
+    /// Whether the device writes to (rather than reads from) the buffer.
+    pub device_writable: bool,
+}
+
+/// A split virtqueue.
+///
+/// The descriptor table, the available ring, and the used ring each live in their own page frame.
+/// The virtio 1.0 PCI transport lets us specify their addresses independently, but we allocate
+/// them as one contiguous run anyway, so the queue stays usable with the legacy layout.
+pub struct Virtqueue {
+    index: u16,
+    size: u16,
+    notify_off: u16,
+    rings: FrameRefRange,
+    /// Head of the list of free descriptors, linked through their `next` fields.
+    free_head: u16,
+    num_free: u16,
+    /// Our shadow of the available ring index.
+    avail_idx: u16,
+    /// Index of the next used ring entry to consume.
+    last_used_idx: u16,
+}
+
+impl Virtqueue {
+    pub(super) fn new(index: u16, size: u16, notify_off: u16) -> Self {
+        assert!(size > 0);
+        assert!(usize::from(size) * mem::size_of::<Descriptor>() <= PAGE_SIZE);
+        assert!(RING_ENTRIES + usize::from(size) * mem::size_of::<UsedElem>() + 2 <= PAGE_SIZE);
+
+        let queue = Self {
+            index,
+            size,
+            notify_off,
+            rings: phys::alloc_contiguous_zero(3).expect("contiguous frames for virtqueue"),
+            free_head: 0,
+            num_free: size,
+            avail_idx: 0,
+            last_used_idx: 0,
+        };
+
+        for i in 0..size {
+            let desc = queue.desc_ptr(i);
+            // SAFETY: `desc` points into the descriptor table, which isn't shared with the device
+            //         yet.
+            unsafe { (*desc).next = i + 1 };
+        }
+
+        queue
+    }
+
+    pub fn index(&self) -> u16 {
+        self.index
+    }
+
+    pub(super) fn notify_off(&self) -> u16 {
+        self.notify_off
+    }
+
+    /// Return the physical addresses of the descriptor table, available ring, and used ring.
+    pub(super) fn addresses(&self) -> (PA, PA, PA) {
+        (
+            self.ring_pa(DESC_FRAME),
+            self.ring_pa(AVAIL_FRAME),
+            self.ring_pa(USED_FRAME),
+        )
+    }
+
+    /// Add a chain of buffers to the queue.
+    ///
+    /// Returns the ID of the chain's head descriptor, or `None` if there aren't enough free
+    /// descriptors. The device isn't notified, which is the responsibility of the caller.
+    ///
+    /// # Safety
+    ///
+    /// The buffers must remain valid until the device has returned the chain through the used
+    /// ring.
+    pub unsafe fn push(&mut self, buffers: &[Buffer]) -> Option<u16> {
+        assert!(!buffers.is_empty(), "empty buffer chain");
+
+        if buffers.len() > usize::from(self.num_free) {
+            return None;
+        }
+
+        let head = self.free_head;
+        let mut id = head;
+        for (i, buf) in buffers.iter().enumerate() {
+            let desc = self.desc_ptr(id);
+
+            let mut flags = 0;
+            if buf.device_writable {
+                flags |= DESC_F_WRITE;
+            }
+            if i + 1 < buffers.len() {
+                flags |= DESC_F_NEXT;
+            }
+
+            // SAFETY: `desc` points to a free descriptor, which the device doesn't access.
+            //         Free descriptors are linked through their `next` fields, which we keep
+            //         intact, so the last descriptor of the chain points to the new free head.
+            unsafe {
+                let next = (*desc).next;
+                desc.write_volatile(Descriptor {
+                    addr: buf.pa.into_u64(),
+                    len: buf.len,
+                    flags,
+                    next,
+                });
+                id = next;
+            }
+        }
+
+        self.free_head = id;
+        self.num_free -= buffers.len() as u16;
+
+        let slot = usize::from(self.avail_idx % self.size);
+        let entry: *mut u16 = self.ring_ptr(AVAIL_FRAME, RING_ENTRIES + slot * 2);
+        // SAFETY: The ring slot is owned by the driver until the index is published.
+        unsafe { entry.write_volatile(head) };
+
+        // Make sure the device observes the ring entry before the new index.
+        dmb_oshst();
+
+        self.avail_idx = self.avail_idx.wrapping_add(1);
+        let idx: *mut u16 = self.ring_ptr(AVAIL_FRAME, RING_IDX);
+        // SAFETY: The available ring index is only written by the driver.
+        unsafe { idx.write_volatile(self.avail_idx) };
+
+        Some(head)
+    }
+
+    /// Take a completed buffer chain from the used ring.
+    ///
+    /// Returns the ID of the chain's head descriptor and the number of bytes the device wrote
+    /// into the chain.
+    pub fn pop_used(&mut self) -> Option<(u16, u32)> {
+        let idx: *const u16 = self.ring_ptr(USED_FRAME, RING_IDX);
+        // SAFETY: The used ring index is valid to read at any time.
+        let used_idx = unsafe { idx.read_volatile() };
+        if used_idx == self.last_used_idx {
+            return None;
+        }
+
+        // Make sure we don't read the used ring entry before the index.
+        dmb_oshld();
+
+        let slot = usize::from(self.last_used_idx % self.size);
+        let offset = RING_ENTRIES + slot * mem::size_of::<UsedElem>();
+        let entry: *const UsedElem = self.ring_ptr(USED_FRAME, offset);
+        // SAFETY: The device has published the entry by incrementing the index.
+        let elem = unsafe { entry.read_volatile() };
+
+        self.last_used_idx = self.last_used_idx.wrapping_add(1);
+
+        let head = u16::try_from(elem.id).expect("valid descriptor ID");
+        self.free_chain(head);
+
+        Some((head, elem.len))
+    }
+
+    fn free_chain(&mut self, head: u16) {
+        let mut id = head;
+        loop {
+            let desc = self.desc_ptr(id);
+            self.num_free += 1;
+
+            // SAFETY: The device has returned the chain, so we own all its descriptors.
+            unsafe {
+                if (*desc).flags & DESC_F_NEXT == 0 {
+                    (*desc).next = self.free_head;
+                    break;
+                }
+                id = (*desc).next;
+            }
+        }
+
+        self.free_head = head;
+    }
+
+    fn desc_ptr(&self, id: u16) -> *mut Descriptor {
+        assert!(id < self.size, "invalid descriptor ID: {id}");
+
+        let offset = usize::from(id) * mem::size_of::<Descriptor>();
+        self.ring_ptr(DESC_FRAME, offset)
+    }
+
+    fn ring_pa(&self, frame: usize) -> PA {
+        assert!(frame < self.rings.len());
+        self.rings.pa() + frame * PAGE_SIZE
+    }
+
+    fn ring_ptr<T>(&self, frame: usize, offset: usize) -> *mut T {
+        debug_assert!(offset + mem::size_of::<T>() <= PAGE_SIZE);
+
+        let va = pa_to_va(self.ring_pa(frame)) + offset;
+        va.as_mut_ptr()
+    }
+}
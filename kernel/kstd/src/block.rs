@@ -0,0 +1,236 @@
+//! Traits for block storage devices.
+
+use crate::io::{Error, Read, Seek};
+
+/// A device storing data in fixed-size blocks.
+pub trait BlockDevice {
+    /// Read blocks, starting at `lba`, into `buf`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `buf` is not a multiple of the block size, or if the read extends beyond the end
+    /// of the device.
+    fn read_block(&self, lba: u64, buf: &mut [u8]);
+
+    /// Return the size of a block, in bytes.
+    fn block_size(&self) -> usize;
+
+    /// Return the number of blocks on the device.
+    fn block_count(&self) -> u64;
+}
+
+/// A block device backed by a byte slice in memory.
+pub struct RamDisk<'a> {
+    data: &'a [u8],
+    block_size: usize,
+}
+
+impl<'a> RamDisk<'a> {
+    /// # Panics
+    ///
+    /// Panics if `data` is not a multiple of `block_size`.
+    pub fn new(data: &'a [u8], block_size: usize) -> Self {
+        assert!(block_size > 0);
+        assert!(data.len().is_multiple_of(block_size), "partial block");
+
+        Self { data, block_size }
+    }
+}
+
+impl BlockDevice for RamDisk<'_> {
+    fn read_block(&self, lba: u64, buf: &mut [u8]) {
+        assert!(
+            buf.len().is_multiple_of(self.block_size),
+            "partial block read"
+        );
+
+        let start = usize::try_from(lba)
+            .ok()
+            .and_then(|lba| lba.checked_mul(self.block_size));
+        let end = start.and_then(|start| start.checked_add(buf.len()));
+        let (Some(start), Some(end)) = (start, end) else {
+            panic!("read beyond device end: lba={lba}");
+        };
+        assert!(end <= self.data.len(), "read beyond device end: lba={lba}");
+
+        buf.copy_from_slice(&self.data[start..end]);
+    }
+
+    fn block_size(&self) -> usize {
+        self.block_size
+    }
+
+    fn block_count(&self) -> u64 {
+        (self.data.len() / self.block_size) as u64
+    }
+}
+
+/// A [`Read`] and [`Seek`] adapter over a byte range of a block device.
+///
+/// The block containing the current position is cached, so sequential reads only hit the device
+/// once per block.
+pub struct BlockReader<'a, D> {
+    dev: &'a D,
+    /// Device byte offset of the range.
+    start: u64,
+    /// Length of the range, in bytes.
+    len: u64,
+    /// Current position, relative to `start`.
+    pos: u64,
+    cache: &'a mut [u8],
+    cached_lba: Option<u64>,
+}
+
+impl<'a, D: BlockDevice> BlockReader<'a, D> {
+    /// Create a reader over the `len` bytes starting at byte offset `start` of `dev`.
+    ///
+    /// `cache` is used to hold the current block and must be exactly one block in size.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the range extends beyond the end of the device, or if `cache` has the wrong size.
+    pub fn new(dev: &'a D, start: u64, len: u64, cache: &'a mut [u8]) -> Self {
+        let block_size = dev.block_size();
+        assert_eq!(cache.len(), block_size, "cache must hold one block");
+
+        let dev_size = dev.block_count().checked_mul(block_size as u64);
+        let end = start.checked_add(len);
+        assert!(
+            dev_size.zip(end).is_some_and(|(size, end)| end <= size),
+            "range beyond device end",
+        );
+
+        Self {
+            dev,
+            start,
+            len,
+            pos: 0,
+            cache,
+            cached_lba: None,
+        }
+    }
+}
+
+impl<D: BlockDevice> Read for BlockReader<'_, D> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let block_size = self.cache.len() as u64;
+        let remaining = self.len - self.pos;
+        let len = (buf.len() as u64).min(remaining) as usize;
+
+        let mut done = 0;
+        while done < len {
+            let offset = self.start + self.pos;
+            let lba = offset / block_size;
+            let block_offset = (offset % block_size) as usize;
+
+            if self.cached_lba != Some(lba) {
+                self.dev.read_block(lba, self.cache);
+                self.cached_lba = Some(lba);
+            }
+
+            let n = (len - done).min(self.cache.len() - block_offset);
+            buf[done..done + n].copy_from_slice(&self.cache[block_offset..block_offset + n]);
+
+            done += n;
+            self.pos += n as u64;
+        }
+
+        Ok(done)
+    }
+}
+
+impl<D: BlockDevice> Seek for BlockReader<'_, D> {
+    fn seek(&mut self, pos: u64) -> Result<(), Error> {
+        if pos > self.len {
+            return Err(Error::SeekOutOfBounds);
+        }
+
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::Cell;
+
+    use super::*;
+
+    /// A block device that counts the reads issued to it.
+    struct CountingDisk<'a> {
+        inner: RamDisk<'a>,
+        reads: Cell<usize>,
+    }
+
+    impl BlockDevice for CountingDisk<'_> {
+        fn read_block(&self, lba: u64, buf: &mut [u8]) {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.read_block(lba, buf);
+        }
+
+        fn block_size(&self) -> usize {
+            self.inner.block_size()
+        }
+
+        fn block_count(&self) -> u64 {
+            self.inner.block_count()
+        }
+    }
+
+    #[test]
+    fn test_ram_disk() {
+        let data: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let disk = RamDisk::new(&data, 16);
+
+        assert_eq!(disk.block_size(), 16);
+        assert_eq!(disk.block_count(), 4);
+
+        let mut buf = [0; 32];
+        disk.read_block(1, &mut buf);
+        assert_eq!(buf, data[16..48]);
+    }
+
+    #[test]
+    #[should_panic(expected = "read beyond device end")]
+    fn test_ram_disk_out_of_bounds() {
+        let data = [0; 64];
+        let disk = RamDisk::new(&data, 16);
+
+        let mut buf = [0; 32];
+        disk.read_block(3, &mut buf);
+    }
+
+    #[test]
+    fn test_block_reader() {
+        let data: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let disk = CountingDisk {
+            inner: RamDisk::new(&data, 16),
+            reads: Cell::new(0),
+        };
+        let mut cache = [0; 16];
+        let mut reader = BlockReader::new(&disk, 10, 40, &mut cache);
+
+        // Sequential reads within a block hit the device only once.
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[10..13]);
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[13..16]);
+        assert_eq!(disk.reads.get(), 1);
+
+        // Reads spanning blocks.
+        let mut buf = [0; 20];
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[16..36]);
+        assert_eq!(disk.reads.get(), 3);
+
+        // Seeking and reading up to the end of the range.
+        reader.seek(30).unwrap();
+        let mut buf = [0; 20];
+        assert_eq!(reader.read(&mut buf).unwrap(), 10);
+        assert_eq!(buf[..10], data[40..50]);
+        assert_eq!(reader.read(&mut buf).unwrap(), 0);
+
+        assert!(matches!(reader.seek(41), Err(Error::SeekOutOfBounds)));
+    }
+}
@@ -1,4 +1,8 @@
-//! Traits for common I/O operations.
+//! Traits for common I/O operations, and adapters implementing them.
+
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
 
 pub trait Read {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error>;
@@ -10,11 +14,47 @@ pub trait Read {
             Err(Error::UnexpectedEof)
         }
     }
+
+    /// Read exactly `len` bytes into a newly allocated `Vec`.
+    fn read_exact_vec(&mut self, len: usize) -> Result<Vec<u8>, Error> {
+        let mut buf = vec![0; len];
+        self.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Read all bytes until the end of the stream, appending them to `buf`.
+    ///
+    /// Returns the number of bytes read.
+    fn read_to_end(&mut self, buf: &mut Vec<u8>) -> Result<usize, Error> {
+        const CHUNK_SIZE: usize = 512;
+
+        let start = buf.len();
+        loop {
+            let len = buf.len();
+            buf.resize(len + CHUNK_SIZE, 0);
+            let n = self.read(&mut buf[len..])?;
+            buf.truncate(len + n);
+
+            if n == 0 {
+                return Ok(buf.len() - start);
+            }
+        }
+    }
 }
 
 pub trait Write {
     fn write(&mut self, buf: &[u8]) -> Result<usize, Error>;
     fn flush(&mut self) -> Result<(), Error>;
+
+    fn write_all(&mut self, mut buf: &[u8]) -> Result<(), Error> {
+        while !buf.is_empty() {
+            match self.write(buf)? {
+                0 => return Err(Error::WriteZero),
+                n => buf = &buf[n..],
+            }
+        }
+        Ok(())
+    }
 }
 
 pub trait Seek {
@@ -25,4 +65,390 @@ pub trait Seek {
 pub enum Error {
     UnexpectedEof,
     SeekOutOfBounds,
+    /// A write call accepted no data.
+    WriteZero,
+    /// The underlying device reported an error.
+    Device,
+}
+
+/// A [`Read`] and [`Seek`] adapter that buffers reads from the inner reader.
+///
+/// Small reads are served from an internal buffer, so reading a stream in small chunks only
+/// issues one read to the inner reader per buffer fill. Reads at least as large as the buffer
+/// bypass it.
+pub struct BufReader<R> {
+    inner: R,
+    buf: Box<[u8]>,
+    /// Stream position of `buf[0]`.
+    ///
+    /// The inner reader is always positioned at `buf_start + filled`.
+    buf_start: u64,
+    /// Number of valid bytes in `buf`.
+    filled: usize,
+    /// Offset of the next byte to return from `buf`.
+    offset: usize,
+}
+
+impl<R: Read + Seek> BufReader<R> {
+    pub const DEFAULT_CAPACITY: usize = 4096;
+
+    /// Create a buffered reader with the default capacity.
+    ///
+    /// `inner` must be positioned at the start of the stream.
+    pub fn new(inner: R) -> Self {
+        Self::with_capacity(Self::DEFAULT_CAPACITY, inner)
+    }
+
+    /// Create a buffered reader with a buffer of `capacity` bytes.
+    ///
+    /// `inner` must be positioned at the start of the stream.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `capacity` is zero.
+    pub fn with_capacity(capacity: usize, inner: R) -> Self {
+        assert!(capacity > 0);
+
+        Self {
+            inner,
+            buf: vec![0; capacity].into_boxed_slice(),
+            buf_start: 0,
+            filled: 0,
+            offset: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read + Seek> Read for BufReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> Result<usize, Error> {
+        let mut done = 0;
+        while done < out.len() {
+            if self.offset == self.filled {
+                // The buffer is exhausted. Discard it, then either read directly into `out` or
+                // refill it.
+                self.buf_start += self.filled as u64;
+                self.filled = 0;
+                self.offset = 0;
+
+                if out.len() - done >= self.buf.len() {
+                    let n = self.inner.read(&mut out[done..])?;
+                    if n == 0 {
+                        break;
+                    }
+                    self.buf_start += n as u64;
+                    done += n;
+                    continue;
+                }
+
+                self.filled = self.inner.read(&mut self.buf)?;
+                if self.filled == 0 {
+                    break;
+                }
+            }
+
+            let n = (out.len() - done).min(self.filled - self.offset);
+            out[done..done + n].copy_from_slice(&self.buf[self.offset..self.offset + n]);
+            self.offset += n;
+            done += n;
+        }
+
+        Ok(done)
+    }
+}
+
+impl<R: Read + Seek> Seek for BufReader<R> {
+    fn seek(&mut self, pos: u64) -> Result<(), Error> {
+        // Seeks within the buffered range don't need to touch the inner reader.
+        let buf_end = self.buf_start + self.filled as u64;
+        if (self.buf_start..=buf_end).contains(&pos) {
+            self.offset = (pos - self.buf_start) as usize;
+            return Ok(());
+        }
+
+        self.inner.seek(pos)?;
+        self.buf_start = pos;
+        self.filled = 0;
+        self.offset = 0;
+        Ok(())
+    }
+}
+
+/// An in-memory reader (and writer) over a byte buffer.
+///
+/// Reads and writes operate at the current position, which is advanced by each operation.
+#[derive(Clone, Debug, Default)]
+pub struct Cursor<T> {
+    inner: T,
+    pos: u64,
+}
+
+impl<T> Cursor<T> {
+    /// Create a cursor over `inner`, positioned at the start.
+    pub fn new(inner: T) -> Self {
+        Self { inner, pos: 0 }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    pub fn get_ref(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn position(&self) -> u64 {
+        self.pos
+    }
+}
+
+impl<T: AsRef<[u8]>> Cursor<T> {
+    /// Return the data between the current position and the end of the buffer.
+    fn remaining_slice(&self) -> &[u8] {
+        let data = self.inner.as_ref();
+        let start = usize::try_from(self.pos).map_or(data.len(), |pos| pos.min(data.len()));
+        &data[start..]
+    }
+}
+
+impl<T: AsRef<[u8]>> Read for Cursor<T> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let remaining = self.remaining_slice();
+        let n = buf.len().min(remaining.len());
+        buf[..n].copy_from_slice(&remaining[..n]);
+
+        self.pos += n as u64;
+        Ok(n)
+    }
+}
+
+impl<T: AsRef<[u8]>> Seek for Cursor<T> {
+    fn seek(&mut self, pos: u64) -> Result<(), Error> {
+        if pos > self.inner.as_ref().len() as u64 {
+            return Err(Error::SeekOutOfBounds);
+        }
+
+        self.pos = pos;
+        Ok(())
+    }
+}
+
+impl Write for Cursor<Vec<u8>> {
+    /// Write `buf` at the current position, overwriting existing data and growing the vector as
+    /// needed.
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        // Seeking ensures the position is never beyond the end of the data.
+        let start = self.pos as usize;
+        let end = start + buf.len();
+        if end > self.inner.len() {
+            self.inner.resize(end, 0);
+        }
+        self.inner[start..end].copy_from_slice(buf);
+
+        self.pos = end as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+/// A [`Write`] implementation that discards all data, counting the number of bytes written.
+///
+/// Useful for measuring the size of some output before allocating space for it.
+#[derive(Debug, Default)]
+pub struct CountingWriter {
+    count: u64,
+}
+
+impl CountingWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.count
+    }
+}
+
+impl Write for CountingWriter {
+    fn write(&mut self, buf: &[u8]) -> Result<usize, Error> {
+        self.count += buf.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// An in-memory reader that counts the reads and seeks issued to it.
+    struct CountingReader<'a> {
+        data: &'a [u8],
+        pos: usize,
+        reads: usize,
+        seeks: usize,
+    }
+
+    impl<'a> CountingReader<'a> {
+        fn new(data: &'a [u8]) -> Self {
+            Self {
+                data,
+                pos: 0,
+                reads: 0,
+                seeks: 0,
+            }
+        }
+    }
+
+    impl Read for CountingReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+            self.reads += 1;
+            let n = buf.len().min(self.data.len() - self.pos);
+            buf[..n].copy_from_slice(&self.data[self.pos..self.pos + n]);
+            self.pos += n;
+            Ok(n)
+        }
+    }
+
+    impl Seek for CountingReader<'_> {
+        fn seek(&mut self, pos: u64) -> Result<(), Error> {
+            self.seeks += 1;
+            if pos > self.data.len() as u64 {
+                return Err(Error::SeekOutOfBounds);
+            }
+            self.pos = pos as usize;
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_cursor_read() {
+        let data: [u8; 8] = core::array::from_fn(|i| i as u8);
+        let mut cursor = Cursor::new(&data[..]);
+
+        let mut buf = [0; 3];
+        cursor.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [0, 1, 2]);
+        assert_eq!(cursor.position(), 3);
+
+        cursor.seek(6).unwrap();
+        assert!(matches!(
+            cursor.read_exact(&mut buf),
+            Err(Error::UnexpectedEof)
+        ));
+        assert_eq!(cursor.position(), 8);
+        assert_eq!(cursor.read(&mut buf).unwrap(), 0);
+
+        assert!(matches!(cursor.seek(9), Err(Error::SeekOutOfBounds)));
+    }
+
+    #[test]
+    fn test_read_to_end() {
+        let data: Vec<u8> = (0..2000).map(|i| i as u8).collect();
+        let mut cursor = Cursor::new(&data[..]);
+        cursor.seek(100).unwrap();
+
+        let mut buf = vec![42];
+        assert_eq!(cursor.read_to_end(&mut buf).unwrap(), 1900);
+        assert_eq!(buf[0], 42);
+        assert_eq!(buf[1..], data[100..]);
+
+        // At the end of the stream, nothing is read.
+        assert_eq!(cursor.read_to_end(&mut buf).unwrap(), 0);
+        assert_eq!(buf.len(), 1901);
+    }
+
+    #[test]
+    fn test_read_exact_vec() {
+        let data: [u8; 8] = core::array::from_fn(|i| i as u8);
+        let mut cursor = Cursor::new(&data[..]);
+
+        assert_eq!(cursor.read_exact_vec(5).unwrap(), data[..5]);
+        assert!(matches!(
+            cursor.read_exact_vec(5),
+            Err(Error::UnexpectedEof)
+        ));
+    }
+
+    #[test]
+    fn test_cursor_write() {
+        let mut cursor = Cursor::new(Vec::new());
+        cursor.write_all(&[1, 2, 3, 4]).unwrap();
+        assert_eq!(cursor.get_ref(), &[1, 2, 3, 4]);
+
+        // Writes overwrite existing data and grow the vector.
+        cursor.seek(2).unwrap();
+        cursor.write_all(&[5, 6, 7]).unwrap();
+        assert_eq!(cursor.position(), 5);
+        assert_eq!(cursor.into_inner(), [1, 2, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_counting_writer() {
+        let mut writer = CountingWriter::new();
+        assert_eq!(writer.bytes_written(), 0);
+
+        writer.write_all(&[1, 2, 3]).unwrap();
+        writer.write_all(&[]).unwrap();
+        assert_eq!(writer.write(&[0; 100]).unwrap(), 100);
+        writer.flush().unwrap();
+        assert_eq!(writer.bytes_written(), 103);
+    }
+
+    #[test]
+    fn test_buf_reader() {
+        let data: [u8; 64] = core::array::from_fn(|i| i as u8);
+        let mut reader = BufReader::with_capacity(16, CountingReader::new(&data));
+
+        // Small reads are served from the buffer.
+        let mut buf = [0; 4];
+        for i in 0..4 {
+            reader.read_exact(&mut buf).unwrap();
+            assert_eq!(buf, data[i * 4..i * 4 + 4]);
+        }
+        assert_eq!(reader.inner.reads, 1);
+
+        // Reads spanning the buffer end refill it.
+        reader.seek(14).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[14..18]);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.inner.seeks, 0);
+
+        // Seeking backwards within the buffer doesn't touch the inner reader.
+        reader.seek(16).unwrap();
+        reader.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, data[16..20]);
+        assert_eq!(reader.inner.reads, 2);
+        assert_eq!(reader.inner.seeks, 0);
+
+        // Large reads bypass the buffer.
+        reader.seek(0).unwrap();
+        let mut large = [0; 32];
+        reader.read_exact(&mut large).unwrap();
+        assert_eq!(large, data[..32]);
+        assert_eq!(reader.inner.seeks, 1);
+        assert_eq!(reader.inner.reads, 3);
+
+        // Reads are truncated at the end of the stream.
+        reader.seek(60).unwrap();
+        assert_eq!(reader.read(&mut large).unwrap(), 4);
+        assert_eq!(large[..4], data[60..]);
+        assert_eq!(reader.read(&mut large).unwrap(), 0);
+
+        assert!(matches!(reader.seek(65), Err(Error::SeekOutOfBounds)));
+    }
 }
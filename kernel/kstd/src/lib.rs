@@ -2,5 +2,10 @@
 
 #![no_std]
 
+extern crate alloc;
+
+pub mod block;
 pub mod io;
+pub mod ring;
 pub mod sync;
+pub mod time;
@@ -0,0 +1,124 @@
+//! A fixed-capacity ring buffer.
+
+/// A ring buffer holding up to `N` bytes.
+///
+/// When full, pushing a new byte drops the oldest one.
+pub struct RingBuffer<const N: usize> {
+    buf: [u8; N],
+    /// Index of the oldest byte.
+    head: usize,
+    len: usize,
+}
+
+impl<const N: usize> RingBuffer<N> {
+    pub const fn new() -> Self {
+        assert!(N > 0, "empty ring buffer");
+
+        Self {
+            buf: [0; N],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Return the number of bytes in the buffer.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Append a byte to the buffer.
+    ///
+    /// Returns `true` if the buffer was full and the oldest byte was dropped to make room.
+    pub fn push(&mut self, byte: u8) -> bool {
+        let tail = (self.head + self.len) % N;
+        self.buf[tail] = byte;
+
+        if self.len == N {
+            self.head = (self.head + 1) % N;
+            true
+        } else {
+            self.len += 1;
+            false
+        }
+    }
+
+    /// Remove and return the oldest byte.
+    pub fn pop(&mut self) -> Option<u8> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let byte = self.buf[self.head];
+        self.head = (self.head + 1) % N;
+        self.len -= 1;
+        Some(byte)
+    }
+
+    /// Move the oldest bytes into `buf`, returning the number of bytes moved.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let mut count = 0;
+        for slot in buf {
+            let Some(byte) = self.pop() else {
+                break;
+            };
+            *slot = byte;
+            count += 1;
+        }
+        count
+    }
+}
+
+impl<const N: usize> Default for RingBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let mut ring = RingBuffer::<4>::new();
+        assert!(ring.is_empty());
+        assert_eq!(ring.pop(), None);
+
+        assert!(!ring.push(1));
+        assert!(!ring.push(2));
+        assert_eq!(ring.len(), 2);
+        assert_eq!(ring.pop(), Some(1));
+
+        // Wrap around the end of the storage.
+        assert!(!ring.push(3));
+        assert!(!ring.push(4));
+        assert!(!ring.push(5));
+        assert_eq!(ring.len(), 4);
+
+        let mut buf = [0; 8];
+        assert_eq!(ring.read(&mut buf), 4);
+        assert_eq!(buf[..4], [2, 3, 4, 5]);
+        assert!(ring.is_empty());
+    }
+
+    #[test]
+    fn test_overflow_drops_oldest() {
+        let mut ring = RingBuffer::<3>::new();
+        for b in 0..3 {
+            assert!(!ring.push(b));
+        }
+        assert!(ring.push(3));
+        assert!(ring.push(4));
+        assert_eq!(ring.len(), 3);
+
+        let mut buf = [0; 2];
+        assert_eq!(ring.read(&mut buf), 2);
+        assert_eq!(buf, [2, 3]);
+        assert_eq!(ring.pop(), Some(4));
+        assert_eq!(ring.pop(), None);
+    }
+}
@@ -7,6 +7,12 @@ use core::cell::UnsafeCell;
 use core::ops::{Deref, DerefMut};
 use core::sync::atomic::{AtomicBool, Ordering};
 
+#[cfg(target_arch = "aarch64")]
+mod irq;
+
+#[cfg(target_arch = "aarch64")]
+pub use self::irq::{IrqMutex, IrqMutexGuard};
+
 /// A simple lock.
 pub struct Lock {
     locked: AtomicBool,
@@ -24,6 +30,13 @@ impl Lock {
         assert!(!was_locked);
     }
 
+    /// Try to acquire the lock, returning whether it was acquired.
+    pub fn try_lock(&self) -> bool {
+        self.locked
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .is_ok()
+    }
+
     pub fn unlock(&self) {
         self.locked.swap(false, Ordering::SeqCst);
     }
@@ -55,6 +68,23 @@ impl<T> Mutex<T> {
         self.lock.lock();
         MutexGuard { lock: self }
     }
+
+    /// Return a mutable reference to the wrapped data.
+    ///
+    /// No locking is needed, since the mutable borrow guarantees exclusive access.
+    pub const fn get_mut(&mut self) -> &mut T {
+        self.data.get_mut()
+    }
+
+    /// Consume the mutex, returning the wrapped data.
+    pub fn into_inner(self) -> T {
+        self.data.into_inner()
+    }
+
+    /// Try to lock the mutex, returning `None` if it is already locked.
+    pub fn try_lock(&self) -> Option<MutexGuard<'_, T>> {
+        self.lock.try_lock().then(|| MutexGuard { lock: self })
+    }
 }
 
 pub struct MutexGuard<'a, T> {
@@ -80,3 +110,46 @@ impl<T> Drop for MutexGuard<'_, T> {
         self.lock.lock.unlock();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_try_lock() {
+        let mutex = Mutex::new(1);
+
+        let guard = mutex.try_lock().unwrap();
+        assert!(mutex.try_lock().is_none());
+        drop(guard);
+
+        let mut guard = mutex.try_lock().unwrap();
+        *guard += 1;
+        drop(guard);
+
+        assert_eq!(*mutex.lock(), 2);
+    }
+
+    #[test]
+    fn test_try_lock_contended() {
+        let mutex = Mutex::new(1);
+
+        let guard = mutex.lock();
+        assert!(mutex.try_lock().is_none());
+        assert!(mutex.try_lock().is_none());
+        assert!(mutex.lock.locked.load(Ordering::SeqCst));
+        drop(guard);
+
+        assert!(!mutex.lock.locked.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_get_mut_into_inner() {
+        let mut mutex = Mutex::new(1);
+
+        *mutex.get_mut() += 1;
+        assert_eq!(*mutex.lock(), 2);
+
+        assert_eq!(mutex.into_inner(), 2);
+    }
+}
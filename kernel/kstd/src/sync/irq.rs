@@ -0,0 +1,72 @@
+//! A mutex that masks interrupts while locked.
+
+use core::cell::UnsafeCell;
+use core::ops::{Deref, DerefMut};
+
+use aarch64::interrupt;
+use aarch64::register::DAIF;
+
+use super::Lock;
+
+/// A wrapper that ensures exclusive access to the wrapped data, with interrupts masked.
+///
+/// This is the correct choice for data shared with interrupt handlers. If an interrupt handler
+/// tried to lock a plain [`Mutex`](super::Mutex) already held by the code it interrupted, the
+/// lock would deadlock. `IrqMutex` prevents this by masking IRQs and FIQs for as long as the lock
+/// is held.
+///
+/// When holding multiple `IrqMutex` locks, drop their guards in the reverse order of locking.
+/// Otherwise interrupts get unmasked while a lock is still held.
+pub struct IrqMutex<T> {
+    data: UnsafeCell<T>,
+    lock: Lock,
+}
+
+unsafe impl<T> Sync for IrqMutex<T> {}
+
+impl<T> IrqMutex<T> {
+    pub const fn new(data: T) -> Self {
+        Self {
+            data: UnsafeCell::new(data),
+            lock: Lock::new(),
+        }
+    }
+
+    pub fn lock(&self) -> IrqMutexGuard<'_, T> {
+        let daif = interrupt::mask();
+        self.lock.lock();
+        IrqMutexGuard { lock: self, daif }
+    }
+}
+
+pub struct IrqMutexGuard<'a, T> {
+    lock: &'a IrqMutex<T>,
+    /// The interrupt mask state from before the lock was taken.
+    ///
+    /// Restoring this, rather than unmasking unconditionally, makes nested locks work.
+    daif: DAIF,
+}
+
+impl<T> Deref for IrqMutexGuard<'_, T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        unsafe { &*self.lock.data.get() }
+    }
+}
+
+impl<T> DerefMut for IrqMutexGuard<'_, T> {
+    fn deref_mut(&mut self) -> &mut T {
+        unsafe { &mut *self.lock.data.get() }
+    }
+}
+
+impl<T> Drop for IrqMutexGuard<'_, T> {
+    fn drop(&mut self) {
+        // Unlock before unmasking, so a pending interrupt can take the lock.
+        self.lock.lock.unlock();
+        // SAFETY: `daif` is the state from before the lock was taken, so the code that took the
+        //         lock was prepared to run with it.
+        unsafe { interrupt::restore(self.daif) };
+    }
+}
@@ -0,0 +1,35 @@
+//! Time formatting helpers.
+
+use core::fmt;
+use core::time::Duration;
+
+/// An uptime, formatted as seconds with millisecond precision.
+///
+/// The seconds are padded to at least three digits, e.g. `  1.234`.
+pub struct Timestamp(pub Duration);
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let secs = self.0.as_secs();
+        let millis = self.0.subsec_millis();
+        write!(f, "{secs:3}.{millis:03}")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloc::string::ToString;
+
+    use super::*;
+
+    #[test]
+    fn test_timestamp() {
+        let format = |millis| Timestamp(Duration::from_millis(millis)).to_string();
+
+        assert_eq!(format(0), "  0.000");
+        assert_eq!(format(7), "  0.007");
+        assert_eq!(format(1_234), "  1.234");
+        assert_eq!(format(60_050), " 60.050");
+        assert_eq!(format(1_234_567), "1234.567");
+    }
+}
@@ -1,14 +1,108 @@
 use core::arch::asm;
 
+/// Error returned by the kernel for exhausted memory or address space, negated.
+pub const ENOMEM: isize = 12;
+/// Error returned by the kernel for invalid pointers, negated.
+pub const EFAULT: isize = 14;
+/// Error returned by the kernel for invalid arguments, negated.
+pub const EINVAL: isize = 22;
+/// Error returned by the kernel for unknown syscalls, negated.
+pub const ENOSYS: isize = 38;
+
 pub fn print(s: &str) {
-    let ptr = s.as_ptr();
-    let len = s.len();
+    print_raw(s.as_ptr(), s.len());
+}
+
+/// Print `len` bytes of UTF-8 starting at `ptr`.
+///
+/// Returns 0 on success, or a negated error number. Pointers to memory that isn't readable by
+/// the process are rejected by the kernel with `-EFAULT`.
+pub fn print_raw(ptr: *const u8, len: usize) -> isize {
+    let ret: isize;
 
     unsafe {
         asm!(
             "svc #0",
-            in("x0") ptr,
+            inlateout("x0") ptr => ret,
             in("x1") len,
         )
     }
+
+    ret
+}
+
+/// Move the end of the process heap by `increment` bytes.
+///
+/// Returns the previous end of the heap, or `ENOMEM` if the heap can't be resized.
+pub fn sbrk(increment: isize) -> Result<*mut u8, isize> {
+    let ret: isize;
+
+    unsafe {
+        asm!(
+            "svc #1",
+            inlateout("x0") increment => ret,
+        )
+    }
+
+    address_result(ret)
+}
+
+/// `mmap` protection flag: the mapping is readable.
+pub const PROT_READ: u32 = 1 << 0;
+/// `mmap` protection flag: the mapping is writable.
+pub const PROT_WRITE: u32 = 1 << 1;
+
+/// Map `len` bytes of zeroed memory with the given protection.
+///
+/// `prot` must be `PROT_READ` or `PROT_READ | PROT_WRITE`. The mapping is placed at `hint` if
+/// possible, and at an address chosen by the kernel otherwise.
+///
+/// Returns the start of the mapping. Fails with `EINVAL` if `len` is zero or `prot` is invalid,
+/// and with `ENOMEM` if there is no room for the mapping.
+pub fn mmap(hint: usize, len: usize, prot: u32) -> Result<*mut u8, isize> {
+    let ret: isize;
+
+    unsafe {
+        asm!(
+            "svc #2",
+            inlateout("x0") hint => ret,
+            in("x1") len,
+            in("x2") prot,
+        )
+    }
+
+    address_result(ret)
+}
+
+/// Let the kernel run other tasks before returning.
+pub fn yield_now() {
+    unsafe {
+        asm!(
+            "svc #4",
+            lateout("x0") _,
+        )
+    }
+}
+
+/// Terminate the process with the given exit code.
+pub fn exit(code: i32) -> ! {
+    unsafe {
+        asm!(
+            "svc #3",
+            in("x0") code,
+            options(noreturn),
+        )
+    }
+}
+
+/// Decode the return value of a syscall returning an address.
+///
+/// Userspace addresses are below the kernel's half of the address space, so they are never
+/// negative, and negative values are negated error numbers.
+fn address_result(ret: isize) -> Result<*mut u8, isize> {
+    if ret < 0 {
+        Err(-ret)
+    } else {
+        Ok(ret as *mut u8)
+    }
 }
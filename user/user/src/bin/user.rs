@@ -16,7 +16,21 @@ pub fn _start(heap_start: *mut u8, heap_size: usize) -> ! {
 
     let s = format!("heap_start={heap_start:?}, heap_size={heap_size:#x}");
     syscall::print(&s);
-    loop {}
+
+    check_bad_pointer_rejected();
+
+    syscall::exit(0);
+}
+
+/// Check that the kernel refuses to print from a kernel address.
+fn check_bad_pointer_rejected() {
+    let kernel_ptr = 0xffff_0000_0000_0000 as *const u8;
+    let ret = syscall::print_raw(kernel_ptr, 16);
+    if ret != -syscall::EFAULT {
+        syscall::print("print from a kernel address wasn't rejected");
+        syscall::exit(1);
+    }
+    syscall::print("print from a kernel address rejected");
 }
 
 #[panic_handler]
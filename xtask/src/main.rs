@@ -1,11 +1,12 @@
-use std::fs::File;
-use std::io::Read;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Read, Write};
 use std::path::{Path, PathBuf};
-use std::process::Command;
-use std::time::Duration;
-use std::{env, io};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use std::{env, io, panic, thread};
 
-use anyhow::{Context, bail};
+use anyhow::{Context, anyhow, bail};
 use aws_sdk_ebs::primitives::ByteStream;
 use aws_sdk_ebs::types::ChecksumAlgorithm;
 use aws_sdk_ec2::client::Waiters;
@@ -15,10 +16,12 @@ use aws_sdk_ec2::types::builders::{
 };
 use aws_sdk_ec2::types::{ArchitectureValues, BootModeValues, InstanceType, ResourceType};
 use base64::prelude::*;
+use elf::ElfFile;
 use fatfs::{FileSystem, FormatVolumeOptions, FsOptions};
 use fscommon::{BufStream, StreamSlice};
 use gpt::mbr::ProtectiveMBR;
 use gpt::{GptConfig, partition_types};
+use kstd::io::Cursor;
 use sha2::{Digest, Sha256};
 
 /// xtask runner for the TeaOS repo.
@@ -33,6 +36,9 @@ struct Args {
 enum TaskArgs {
     Qemu(QemuArgs),
     Aws(AwsArgs),
+    Test(TestArgs),
+    BootTest(BootTestArgs),
+    Symbols(SymbolsArgs),
 }
 
 /// Run TeaOS in qemu.
@@ -45,6 +51,28 @@ struct QemuArgs {
     /// wait for a gdb connection on tcp::1234
     #[argh(switch)]
     gdb: bool,
+    /// like --gdb, but also launch gdb-multiarch with the kernel and userimg symbols loaded
+    #[argh(switch)]
+    gdb_attach: bool,
+    /// attach the given disk image as a virtio-blk data disk, creating it if it doesn't exist
+    #[argh(option)]
+    data_disk: Option<PathBuf>,
+    /// size in MiB of data disk images created by --data-disk
+    #[argh(option, default = "64")]
+    data_disk_size: u64,
+    /// guest memory size, e.g. 512M or 4G
+    #[argh(
+        option,
+        default = "String::from(\"512M\")",
+        from_str_fn(parse_memory_size)
+    )]
+    memory: String,
+    /// CPU model to emulate
+    #[argh(option, default = "String::from(\"neoverse-n1\")")]
+    cpu: String,
+    /// number of CPU cores
+    #[argh(option, default = "1")]
+    smp: u32,
 }
 
 /// Run TeaOS in AWS.
@@ -56,6 +84,66 @@ struct AwsArgs {
     release: bool,
 }
 
+/// Run the unit tests of the host-testable crates.
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "test")]
+struct TestArgs {}
+
+/// Boot TeaOS in qemu with the boot tests enabled, and check their results.
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "boot-test")]
+struct BootTestArgs {
+    /// build in release mode
+    #[argh(switch)]
+    release: bool,
+}
+
+/// Print the symbol table of the kernel.
+#[derive(argh::FromArgs)]
+#[argh(subcommand, name = "symbols")]
+struct SymbolsArgs {
+    /// build in release mode
+    #[argh(switch)]
+    release: bool,
+    /// include symbols of all types, not only functions and objects
+    #[argh(switch)]
+    all: bool,
+}
+
+/// Crates whose unit tests build and run on the host.
+///
+/// The remaining crates are bare-metal binaries that can't link on the host.
+const HOST_TEST_CRATES: &[&str] = &[
+    "common/freelist",
+    "kernel/acpi",
+    "kernel/boot-info",
+    "kernel/crc",
+    "kernel/elf",
+    "kernel/kstd",
+];
+
+/// Kernel command line used by `boot-test`.
+const BOOT_TEST_CMDLINE: &str = "boottest pcisummary";
+
+/// Boot tests that must log a `boot-test: <name> ok` line.
+const BOOT_TESTS: &[&str] = &[
+    "unmap_page",
+    "protect_page",
+    "lookup",
+    "map_shared",
+    "copy-on-write",
+    "alloc_contiguous",
+    "ticking",
+    "delay",
+    "sched",
+];
+
+/// PCI summary expected on qemu's `virt` machine without a NIC: only the host bridge.
+const BOOT_TEST_PCI_SUMMARY: &str = "pci summary: count=1 first=0000:00:00.0 vendor=1b36";
+
+/// How long `boot-test` waits for the kernel to finish the boot tests.
+const BOOT_TEST_TIMEOUT: Duration = Duration::from_secs(120);
+
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     let args: Args = argh::from_env();
@@ -64,42 +152,119 @@ async fn main() -> anyhow::Result<()> {
     env::set_current_dir(repo_root)?;
 
     match args.task {
-        TaskArgs::Qemu(args) => task_qemu(args.release, args.gdb),
+        TaskArgs::Qemu(args) => task_qemu(args),
         TaskArgs::Aws(args) => task_aws(args.release).await,
+        TaskArgs::Test(_) => task_test(),
+        TaskArgs::BootTest(args) => task_boot_test(args.release),
+        TaskArgs::Symbols(args) => task_symbols(args),
     }
 }
 
-fn task_qemu(release: bool, gdb: bool) -> anyhow::Result<()> {
-    let disk_img = build_disk_image(release)?;
+fn task_qemu(args: QemuArgs) -> anyhow::Result<()> {
+    let disk_img = build_disk_image(args.release, None)?;
+
+    let mut cmd = qemu_command(&disk_img, &args.cpu, &args.memory, args.smp);
+    if let Some(data_disk) = &args.data_disk {
+        if !data_disk.exists() {
+            println!("creating data disk image ({} MiB)", args.data_disk_size);
+            create_blank_image(data_disk, args.data_disk_size * 1024 * 1024)?;
+        }
+        cmd.args([
+            "-drive",
+            &format!("format=raw,file={},if=none,id=d0", data_disk.display()),
+        ])
+        .args(["-device", "virtio-blk-pci,drive=d0"]);
+    }
+    if args.gdb_attach {
+        return run_qemu_with_gdb(cmd, &disk_img);
+    }
+    if args.gdb {
+        cmd.args(["-s", "-S"]);
+        println!("qemu waits for gdb; connect with `target remote localhost:1234`");
+    }
+    cmd.status().context("qemu-system-aarch64")?;
+
+    Ok(())
+}
 
+/// Build the qemu command booting the given disk image on the `virt` machine.
+fn qemu_command(disk_img: &DiskImage, cpu: &str, memory: &str, smp: u32) -> Command {
     let mut cmd = Command::new("qemu-system-aarch64");
     cmd.args(["-machine", "virt"])
-        .args(["-cpu", "neoverse-n1"])
-        .args(["-m", "512M"])
+        .args(["-cpu", cpu])
+        .args(["-m", memory])
+        .args(["-smp", &smp.to_string()])
         .args([
             "-drive",
             "if=pflash,format=raw,readonly=on,file=/opt/homebrew/share/qemu/edk2-aarch64-code.fd",
         ])
-        .args(["-drive", &format!("format=raw,file={}", disk_img.display())])
+        .args([
+            "-drive",
+            &format!("format=raw,file={}", disk_img.path.display()),
+        ])
         .arg("-nographic");
-    if gdb {
-        cmd.args(["-s", "-S"]);
-        println!("qemu waits for gdb; connect with `target remote localhost:1234`");
-    }
-    cmd.status().context("qemu-system-aarch64")?;
+    cmd
+}
+
+/// Start qemu waiting for gdb, and attach gdb-multiarch to it.
+///
+/// The kernel and userimg run at their link addresses, so gdb can take the symbol addresses
+/// straight from the ELF files. The boot loader is relocated by the firmware, so its symbols
+/// aren't loaded.
+fn run_qemu_with_gdb(mut qemu: Command, disk_img: &DiskImage) -> anyhow::Result<()> {
+    let script = target_dir().join("gdbinit");
+    let script_content = format!(
+        "target remote localhost:1234\n\
+         symbol-file {}\n\
+         add-symbol-file {}\n",
+        disk_img.kernel_bin.display(),
+        disk_img.userimg_bin.display(),
+    );
+    fs::write(&script, script_content).context("writing gdb script")?;
+
+    // gdb owns the terminal, so qemu only gets to print the serial output.
+    qemu.args(["-s", "-S"]).stdin(Stdio::null());
+    let mut qemu = qemu.spawn().context("qemu-system-aarch64")?;
+
+    let gdb_status = Command::new("gdb-multiarch")
+        .arg("-x")
+        .arg(&script)
+        .status()
+        .context("gdb-multiarch");
+
+    qemu.kill()?;
+    qemu.wait()?;
+    gdb_status?;
 
     Ok(())
 }
 
+/// Parse a QEMU memory size, like `512M` or `4G`.
+///
+/// Sizes without a suffix are in MiB, like QEMU interprets them.
+fn parse_memory_size(value: &str) -> Result<String, String> {
+    let digits = value.trim_end_matches(|c: char| "KMGT".contains(c.to_ascii_uppercase()));
+    let suffix = &value[digits.len()..];
+
+    let valid = suffix.len() <= 1 && digits.parse::<u64>().is_ok_and(|n| n > 0);
+    if !valid {
+        return Err(format!(
+            "invalid memory size `{value}`, expected a number with optional K/M/G/T suffix"
+        ));
+    }
+
+    Ok(value.to_string())
+}
+
 async fn task_aws(release: bool) -> anyhow::Result<()> {
-    let disk_img = build_disk_image(release)?;
+    let disk_img = build_disk_image(release, None)?;
 
     let aws_config = aws_config::load_from_env().await;
     let ec2 = aws_sdk_ec2::Client::new(&aws_config);
     let ebs = aws_sdk_ebs::Client::new(&aws_config);
 
     println!("creating EBS snapshot");
-    let snapshot_id = create_ebs_snapshot(&ebs, &disk_img).await?;
+    let snapshot_id = create_ebs_snapshot(&ebs, &disk_img.path).await?;
 
     println!("waiting for snapshot to complete (snapshot_id={snapshot_id})");
     ec2.wait_until_snapshot_completed()
@@ -170,6 +335,152 @@ async fn task_aws(release: bool) -> anyhow::Result<()> {
     Ok(())
 }
 
+fn task_test() -> anyhow::Result<()> {
+    let mut failed = Vec::new();
+    for krate in HOST_TEST_CRATES {
+        println!("testing {krate}");
+
+        let manifest = Path::new(krate).join("Cargo.toml");
+        let status = Command::new("cargo")
+            .arg("test")
+            .arg("--manifest-path")
+            .arg(manifest)
+            .status()
+            .context("cargo test")?;
+        if !status.success() {
+            failed.push(*krate);
+        }
+    }
+
+    if !failed.is_empty() {
+        bail!("tests failed in: {}", failed.join(", "));
+    }
+
+    println!("all tests passed ({} crates)", HOST_TEST_CRATES.len());
+    Ok(())
+}
+
+/// Boot the kernel with the boot tests enabled, and check the serial output for their results.
+///
+/// The boot tests and the PCI summary are logged before userspace starts, so qemu is stopped once
+/// the summary line shows up.
+fn task_boot_test(release: bool) -> anyhow::Result<()> {
+    let disk_img = build_disk_image(release, Some(BOOT_TEST_CMDLINE))?;
+
+    let mut cmd = qemu_command(&disk_img, "neoverse-n1", "512M", 1);
+    cmd.args(["-nic", "none"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped());
+    let mut qemu = cmd.spawn().context("qemu-system-aarch64")?;
+
+    // Read the serial output on a separate thread, so we can time out while waiting for lines.
+    let stdout = qemu.stdout.take().unwrap();
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let mut reader = BufReader::new(stdout);
+        let mut buf = Vec::new();
+        while matches!(reader.read_until(b'\n', &mut buf), Ok(n) if n > 0) {
+            let line = String::from_utf8_lossy(&buf).trim_end().to_string();
+            if tx.send(line).is_err() {
+                break;
+            }
+            buf.clear();
+        }
+    });
+
+    let deadline = Instant::now() + BOOT_TEST_TIMEOUT;
+    let mut lines = Vec::new();
+    let result = loop {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        let line = match rx.recv_timeout(timeout) {
+            Ok(line) => line,
+            Err(mpsc::RecvTimeoutError::Timeout) => break Err(anyhow!("timed out")),
+            Err(mpsc::RecvTimeoutError::Disconnected) => break Err(anyhow!("qemu exited")),
+        };
+
+        println!("{line}");
+        let done = line.contains("pci summary:");
+        let panicked = line.contains("PANIC");
+        lines.push(line);
+
+        if panicked {
+            break Err(anyhow!("kernel panicked"));
+        } else if done {
+            break Ok(());
+        }
+    };
+
+    qemu.kill()?;
+    qemu.wait()?;
+    io::stdout().flush()?;
+    result.context("boot did not complete")?;
+
+    check_boot_test_output(&lines)?;
+
+    println!("all boot tests passed ({} tests)", BOOT_TESTS.len());
+    Ok(())
+}
+
+/// Check the serial output of a boot for the boot test results and the PCI summary.
+fn check_boot_test_output(lines: &[String]) -> anyhow::Result<()> {
+    let mut missing = Vec::new();
+    for test in BOOT_TESTS {
+        let marker = format!("boot-test: {test} ok");
+        if !lines.iter().any(|line| line.contains(&marker)) {
+            missing.push(*test);
+        }
+    }
+    if !missing.is_empty() {
+        bail!("boot tests did not pass: {}", missing.join(", "));
+    }
+
+    if !lines
+        .iter()
+        .any(|line| line.contains(BOOT_TEST_PCI_SUMMARY))
+    {
+        bail!("unexpected PCI summary, expected `{BOOT_TEST_PCI_SUMMARY}`");
+    }
+
+    Ok(())
+}
+
+/// Print the kernel symbols, sorted by address.
+///
+/// The symbol table is read with the repo's own `elf` crate, the same way the boot loader reads
+/// it. Without `--all`, only function and object symbols are shown, which leaves out symbols
+/// like the layout constants defined in assembly.
+fn task_symbols(args: SymbolsArgs) -> anyhow::Result<()> {
+    println!("building kernel (release={})", args.release);
+    let kernel_bin = build_kernel(args.release)?;
+
+    let data =
+        fs::read(&kernel_bin).with_context(|| format!("reading {}", kernel_bin.display()))?;
+    let len = data.len() as u64;
+    let mut elf = ElfFile::open(Cursor::new(data), len);
+
+    let strtab = elf.symbol_strtab().context("kernel has no symbol table")?;
+    let mut symbols: Vec<_> = elf
+        .symbols()
+        .unwrap()
+        .filter(|sym| args.all || sym.is_function() || sym.is_object())
+        .collect();
+    symbols.sort_by_key(|sym| sym.value());
+
+    for sym in symbols {
+        let kind = if sym.is_function() {
+            'F'
+        } else if sym.is_object() {
+            'O'
+        } else {
+            '-'
+        };
+        let name = sym.name(&strtab).to_string_lossy();
+        println!("{:016x} {:8} {kind} {name}", sym.value(), sym.size());
+    }
+
+    Ok(())
+}
+
 fn get_repo_root() -> anyhow::Result<PathBuf> {
     let output = Command::new("git")
         .args(["rev-parse", "--show-toplevel"])
@@ -186,7 +497,18 @@ fn target_dir() -> PathBuf {
     PathBuf::from("target")
 }
 
-fn build_disk_image(release: bool) -> anyhow::Result<PathBuf> {
+/// A bootable disk image, and the binaries it contains.
+struct DiskImage {
+    path: PathBuf,
+    kernel_bin: PathBuf,
+    userimg_bin: PathBuf,
+}
+
+/// Build the binaries and pack them into a bootable disk image.
+///
+/// With a `cmdline`, the image boots through the UEFI shell, which passes the command line to the
+/// loader as load options.
+fn build_disk_image(release: bool, cmdline: Option<&str>) -> anyhow::Result<DiskImage> {
     println!("building boot.efi (release={release})");
     let boot_bin = build_boot(release)?;
     println!("building kernel (release={release})");
@@ -196,9 +518,16 @@ fn build_disk_image(release: bool) -> anyhow::Result<PathBuf> {
 
     println!("creating disk image");
     let esp_img = target_dir().join("esp.img");
-    create_esp_image(&esp_img, &boot_bin, &kernel_bin, &userimg_bin)?;
+    create_esp_image(&esp_img, &boot_bin, &kernel_bin, &userimg_bin, cmdline)?;
 
-    Ok(esp_img)
+    println!("verifying disk image");
+    verify_esp_image(&esp_img)?;
+
+    Ok(DiskImage {
+        path: esp_img,
+        kernel_bin,
+        userimg_bin,
+    })
 }
 
 fn build_boot(release: bool) -> anyhow::Result<PathBuf> {
@@ -272,6 +601,7 @@ fn create_esp_image(
     boot_bin: &Path,
     kernel_bin: &Path,
     userimg_bin: &Path,
+    cmdline: Option<&str>,
 ) -> anyhow::Result<()> {
     const MB: u64 = 1024 * 1024;
     const DISK_SIZE: u64 = 100 * MB;
@@ -313,11 +643,23 @@ fn create_esp_image(
     let fs = FileSystem::new(&mut partition, FsOptions::new())?;
     let root = fs.root_dir();
     root.create_dir("efi")?;
-    root.create_dir("efi/boot")?;
 
+    // The firmware starts the removable media loader without load options. To pass a command
+    // line, install the loader elsewhere, so the firmware falls back to the UEFI shell, and let
+    // the shell start it from `startup.nsh`.
     let mut src = File::open(boot_bin)?;
-    let mut dst = root.create_file("efi/boot/bootaa64.efi")?;
-    io::copy(&mut src, &mut dst)?;
+    if let Some(cmdline) = cmdline {
+        root.create_dir("efi/teaos")?;
+        let mut dst = root.create_file("efi/teaos/boot.efi")?;
+        io::copy(&mut src, &mut dst)?;
+
+        let mut script = root.create_file("startup.nsh")?;
+        writeln!(script, "fs0:\\efi\\teaos\\boot.efi {cmdline}")?;
+    } else {
+        root.create_dir("efi/boot")?;
+        let mut dst = root.create_file("efi/boot/bootaa64.efi")?;
+        io::copy(&mut src, &mut dst)?;
+    }
 
     let mut src = File::open(kernel_bin)?;
     let mut dst = root.create_file("kernel")?;
@@ -330,6 +672,82 @@ fn create_esp_image(
     Ok(())
 }
 
+/// Check that the kernel in the given disk image is a loadable ELF executable.
+///
+/// The image is read back from scratch, through the partition table and the FAT filesystem, so
+/// this also checks that those are readable.
+fn verify_esp_image(img_path: &Path) -> anyhow::Result<()> {
+    let disk = GptConfig::new().open(img_path)?;
+    let block_size = disk.logical_block_size().as_u64();
+    let part_info = disk
+        .partitions()
+        .values()
+        .next()
+        .context("disk image has no partitions")?;
+
+    let start_offset = part_info.first_lba * block_size;
+    let end_offset = (part_info.last_lba + 1) * block_size;
+    let img_file = File::open(img_path)?;
+    let partition = StreamSlice::new(img_file, start_offset, end_offset)?;
+    let mut partition = BufStream::new(partition);
+
+    let fs = FileSystem::new(&mut partition, FsOptions::new())?;
+    let mut kernel = Vec::new();
+    fs.root_dir()
+        .open_file("kernel")
+        .context("opening kernel in disk image")?
+        .read_to_end(&mut kernel)?;
+
+    verify_kernel_elf(kernel).context("invalid kernel in disk image")
+}
+
+/// Check that `data` is an aarch64 ELF executable the boot loader can load.
+fn verify_kernel_elf(data: Vec<u8>) -> anyhow::Result<()> {
+    if !data.starts_with(b"\x7fELF") {
+        bail!("not an ELF file");
+    }
+
+    // The ELF parser panics on unsupported headers, e.g. for other architectures.
+    let len = data.len() as u64;
+    let mut elf = panic::catch_unwind(|| ElfFile::open(Cursor::new(data), len))
+        .map_err(|_| anyhow!("unsupported ELF header, expected an aarch64 executable"))?;
+
+    if elf.is_dynamic() {
+        bail!("position-independent executable, expected ET_EXEC");
+    }
+
+    let mut load_segments = 0;
+    for phdr in elf.program_headers() {
+        let phdr = phdr.map_err(|error| anyhow!("invalid program header: {error:?}"))?;
+        if phdr.is_load() {
+            load_segments += 1;
+        }
+    }
+    if load_segments == 0 {
+        bail!("no PT_LOAD segments");
+    }
+
+    let strtab = elf.symbol_strtab().context("no symbol table")?;
+    let has_userimg_start = elf
+        .symbols()
+        .unwrap()
+        .any(|sym| sym.name(&strtab) == c"userimg_start");
+    if !has_userimg_start {
+        bail!("missing `userimg_start` symbol");
+    }
+
+    Ok(())
+}
+
+fn create_blank_image(img_path: &Path, size: u64) -> anyhow::Result<()> {
+    let img_file =
+        File::create_new(img_path).with_context(|| format!("creating {}", img_path.display()))?;
+    img_file.set_len(size)?;
+    img_file.sync_data()?;
+
+    Ok(())
+}
+
 async fn create_ebs_snapshot(ebs: &aws_sdk_ebs::Client, img_path: &Path) -> anyhow::Result<String> {
     let img_file = File::open(img_path)?;
     let img_size = img_file.metadata()?.len();